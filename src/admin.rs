@@ -0,0 +1,345 @@
+//! Admin messages: reading and changing a node's config, our own radio's or one out on the mesh.
+//!
+//! Other nodes get them as ordinary `AdminApp` packets and answer our `get_*` requests the same
+//! way, so replies trickle in over a while and some may never come.  What does come back is kept
+//! in `REMOTE_CONFIGS`, which the config tabs show while that node is the admin target.  A node
+//! a few hops off can't take a burst of requests, so they go out one every few seconds.
+//!
+//! Firmware 2.5 and later put a session passkey (field 101) in their replies and drop any
+//! `set_*` message that doesn't carry it back within five minutes.  The protobufs this build
+//! uses (2.3) predate the field, so we pick it out of the raw reply bytes ourselves and append it
+//! to the encoded messages we send.  Older firmware ignores it, and only takes admin messages
+//! that arrive on a channel named `admin`, so we send on one of those whenever we have it.
+use crate::app::DeviceConfiguration;
+use crate::ipc::IPCMessage;
+use crate::{consts, util, DEVICE_CONFIGS, REMOTE_CONFIGS};
+use anyhow::{bail, Result};
+use meshtastic::protobufs::admin_message::{self, ConfigType, ModuleConfigType};
+use meshtastic::protobufs::{
+    config, mesh_packet, module_config, to_radio, AdminMessage, Config, Data, MeshPacket,
+    ModuleConfig, PortNum, ToRadio,
+};
+use meshtastic::Message;
+use std::time::Duration;
+
+const CONFIG_TYPES: [ConfigType; 7] = [
+    ConfigType::DeviceConfig,
+    ConfigType::PositionConfig,
+    ConfigType::PowerConfig,
+    ConfigType::NetworkConfig,
+    ConfigType::DisplayConfig,
+    ConfigType::LoraConfig,
+    ConfigType::BluetoothConfig,
+];
+
+const MODULE_CONFIG_TYPES: [ModuleConfigType; 13] = [
+    ModuleConfigType::MqttConfig,
+    ModuleConfigType::SerialConfig,
+    ModuleConfigType::ExtnotifConfig,
+    ModuleConfigType::StoreforwardConfig,
+    ModuleConfigType::RangetestConfig,
+    ModuleConfigType::TelemetryConfig,
+    ModuleConfigType::CannedmsgConfig,
+    ModuleConfigType::AudioConfig,
+    ModuleConfigType::RemotehardwareConfig,
+    ModuleConfigType::NeighborinfoConfig,
+    ModuleConfigType::AmbientlightingConfig,
+    ModuleConfigType::DetectionsensorConfig,
+    ModuleConfigType::PaxcounterConfig,
+];
+
+/// The active radio's channel named `admin`, or the primary channel if it hasn't got one.
+async fn admin_channel() -> u32 {
+    let config = util::device_config().await.unwrap_or_default();
+    config
+        .channels
+        .values()
+        .find(|c| {
+            c.settings
+                .as_ref()
+                .is_some_and(|s| s.name.eq_ignore_ascii_case(consts::ADMIN_CHANNEL_NAME))
+        })
+        .map(|c| c.index as u32)
+        .unwrap_or_default()
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// The session passkey in an encoded admin message, if it has one.
+pub(crate) fn session_passkey(raw: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    while pos < raw.len() {
+        let key = read_varint(raw, &mut pos)?;
+        match key & 7 {
+            0 => {
+                read_varint(raw, &mut pos)?;
+            }
+            1 => pos += 8,
+            2 => {
+                let len = read_varint(raw, &mut pos)? as usize;
+                let end = pos.checked_add(len).filter(|end| *end <= raw.len())?;
+                if key >> 3 == consts::SESSION_PASSKEY_FIELD {
+                    return Some(raw[pos..end].to_vec());
+                }
+                pos = end;
+            }
+            5 => pos += 4,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// `message` encoded, with the passkey tacked on the end as field 101.
+pub(crate) fn encode_with_passkey(message: &AdminMessage, passkey: &[u8]) -> Vec<u8> {
+    let mut encoded = message.encode_to_vec();
+    if !passkey.is_empty() {
+        write_varint((consts::SESSION_PASSKEY_FIELD << 3) | 2, &mut encoded);
+        write_varint(passkey.len() as u64, &mut encoded);
+        encoded.extend_from_slice(passkey);
+    }
+    encoded
+}
+
+fn admin_packet(
+    to: u32,
+    channel: u32,
+    message: AdminMessage,
+    passkey: &[u8],
+    remote: bool,
+) -> ToRadio {
+    let want_response = matches!(
+        message.payload_variant,
+        Some(
+            admin_message::PayloadVariant::GetChannelRequest(_)
+                | admin_message::PayloadVariant::GetOwnerRequest(_)
+                | admin_message::PayloadVariant::GetConfigRequest(_)
+                | admin_message::PayloadVariant::GetModuleConfigRequest(_)
+                | admin_message::PayloadVariant::GetDeviceMetadataRequest(_)
+        )
+    );
+    let mesh_packet = MeshPacket {
+        to,
+        channel,
+        want_ack: remote,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+            portnum: i32::from(PortNum::AdminApp),
+            payload: encode_with_passkey(&message, passkey),
+            want_response,
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    ToRadio {
+        payload_variant: Some(to_radio::PayloadVariant::Packet(mesh_packet)),
+    }
+}
+
+/// The passkey `node` last gave us.  Our own radio doesn't want one.
+async fn passkey_for(node: u32, variant: &admin_message::PayloadVariant) -> Vec<u8> {
+    let remote = REMOTE_CONFIGS.read().await;
+    let (passkey, received) = match remote.get(&node) {
+        Some(c) => (c.session_passkey.clone(), c.passkey_received),
+        None => (vec![], 0),
+    };
+    let is_set = !matches!(
+        variant,
+        admin_message::PayloadVariant::GetChannelRequest(_)
+            | admin_message::PayloadVariant::GetOwnerRequest(_)
+            | admin_message::PayloadVariant::GetConfigRequest(_)
+            | admin_message::PayloadVariant::GetModuleConfigRequest(_)
+            | admin_message::PayloadVariant::GetDeviceMetadataRequest(_)
+    );
+    if is_set && passkey_expired(&passkey, received) {
+        warn!("!{node:x} hasn't given us a current session key; firmware 2.5+ will refuse this.");
+    }
+    passkey
+}
+
+/// Whether a node on newer firmware would turn away a change sent with this key.
+pub(crate) fn passkey_expired(passkey: &[u8], received: u64) -> bool {
+    passkey.is_empty()
+        || util::get_secs().saturating_sub(received) > consts::SESSION_PASSKEY_LIFETIME_SECS
+}
+
+/// Sends an admin message to `target`, or to the active radio (which is `my_node_id`) if that's
+/// None.
+pub async fn send(
+    target: Option<u32>,
+    my_node_id: u32,
+    variant: admin_message::PayloadVariant,
+) -> Result<()> {
+    let (to, channel, passkey) = match target {
+        Some(node) => (
+            node,
+            admin_channel().await,
+            passkey_for(node, &variant).await,
+        ),
+        None if my_node_id == 0 => bail!("We don't know our own node number yet."),
+        None => (my_node_id, 0, vec![]),
+    };
+    let message = AdminMessage {
+        payload_variant: Some(variant),
+    };
+    let packet = admin_packet(to, channel, message, &passkey, target.is_some());
+    util::send_to_radio(IPCMessage::ToRadio(packet)).await
+}
+
+/// Sends a batch of admin messages in the background, spaced out so neither the radio's
+/// transmit queue nor the mesh gets them all at once.
+pub fn send_paced(
+    target: Option<u32>,
+    my_node_id: u32,
+    variants: Vec<admin_message::PayloadVariant>,
+) {
+    let spacing = match target {
+        Some(_) => Duration::from_secs(consts::ADMIN_REMOTE_SPACING_SECS),
+        None => Duration::from_millis(consts::ADMIN_LOCAL_SPACING_MS),
+    };
+    tokio::spawn(async move {
+        let count = variants.len();
+        for (i, variant) in variants.into_iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(spacing).await;
+            }
+            if let Err(e) = send(target, my_node_id, variant).await {
+                error!("Gave up after {i} of {count} admin messages: {e}");
+                return;
+            }
+        }
+        info!("Sent all {count} admin messages.");
+    });
+}
+
+/// Asks `node` for its owner, metadata, every config section, module config and channel.
+/// The requests go out over the next minute or so, and the answers follow them in.
+pub async fn request_remote_config(node: u32) -> Result<()> {
+    use admin_message::PayloadVariant::*;
+    REMOTE_CONFIGS.write().await.entry(node).or_default();
+    let mut requests = vec![GetOwnerRequest(true), GetDeviceMetadataRequest(true)];
+    requests.extend(CONFIG_TYPES.iter().map(|t| GetConfigRequest(*t as i32)));
+    requests.extend(
+        MODULE_CONFIG_TYPES
+            .iter()
+            .map(|t| GetModuleConfigRequest(*t as i32)),
+    );
+    // channels are asked for by index plus one.
+    requests.extend((1..=consts::MAX_CHANNEL_INDEX + 1).map(GetChannelRequest));
+    info!(
+        "Asking !{node:x} for its config, {} requests in all",
+        requests.len()
+    );
+    send_paced(Some(node), 0, requests);
+    Ok(())
+}
+
+/// Files a node's answer to one of our requests.  Anything we didn't ask it for is ignored.
+pub async fn file_response(from: u32, raw: &[u8]) -> Result<()> {
+    use admin_message::PayloadVariant::*;
+    let message = AdminMessage::decode(raw)?;
+    let mut remote = REMOTE_CONFIGS.write().await;
+    let config = match remote.get_mut(&from) {
+        Some(c) => c,
+        None => {
+            debug!("Ignoring an admin message from !{from:x}, we didn't ask it anything.");
+            return Ok(());
+        }
+    };
+    if let Some(passkey) = session_passkey(raw) {
+        config.session_passkey = passkey;
+        config.passkey_received = util::get_secs();
+    }
+    match message.payload_variant {
+        Some(GetConfigResponse(Config {
+            payload_variant: Some(section),
+        })) => config.file_config(section),
+        Some(GetModuleConfigResponse(ModuleConfig {
+            payload_variant: Some(module),
+        })) => config.file_module_config(module),
+        Some(GetChannelResponse(channel)) => config.file_channel(channel),
+        Some(GetOwnerResponse(owner)) => {
+            info!("!{from:x} is {}", owner.long_name);
+            config.owner = owner;
+            config.last_update = util::get_secs();
+        }
+        Some(GetDeviceMetadataResponse(metadata)) => {
+            info!(
+                "!{from:x} is running firmware {}",
+                metadata.firmware_version
+            );
+            config.metadata = metadata;
+            config.last_update = util::get_secs();
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Keeps our copy of the target's config in step with a change we just sent it.
+async fn update_copy(target: Option<u32>, update: impl FnOnce(&mut DeviceConfiguration)) {
+    match target {
+        Some(node) => update(REMOTE_CONFIGS.write().await.entry(node).or_default()),
+        None => update(
+            DEVICE_CONFIGS
+                .write()
+                .await
+                .entry(util::active_radio())
+                .or_default(),
+        ),
+    }
+}
+
+pub async fn set_config(
+    target: Option<u32>,
+    my_node_id: u32,
+    section: config::PayloadVariant,
+) -> Result<()> {
+    let config = Config {
+        payload_variant: Some(section.clone()),
+    };
+    send(
+        target,
+        my_node_id,
+        admin_message::PayloadVariant::SetConfig(config),
+    )
+    .await?;
+    update_copy(target, |c| c.file_config(section)).await;
+    Ok(())
+}
+
+pub async fn set_module_config(
+    target: Option<u32>,
+    my_node_id: u32,
+    module: module_config::PayloadVariant,
+) -> Result<()> {
+    let config = ModuleConfig {
+        payload_variant: Some(module.clone()),
+    };
+    send(
+        target,
+        my_node_id,
+        admin_message::PayloadVariant::SetModuleConfig(config),
+    )
+    .await?;
+    update_copy(target, |c| c.file_module_config(module)).await;
+    Ok(())
+}
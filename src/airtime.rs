@@ -0,0 +1,323 @@
+//! How long our packets spend on the air, worked out from the LoRa settings.
+//!
+//! This is the time-on-air formula from Semtech's SX127x datasheets, with the settings the
+//! firmware uses: explicit header, CRC on, a 16 symbol preamble, and low data rate
+//! optimisation whenever a symbol takes longer than 16ms.
+//!
+//! On top of that, `Budget` adds up what the node sends on its own schedule (positions,
+//! telemetry, node info) to see how much of each hour it spends transmitting, which is what the
+//! EU duty-cycle limits are about.  Packet sizes are typical ones rather than exact.
+use crate::app::DeviceConfiguration;
+use crate::consts;
+use meshtastic::protobufs::config::lo_ra_config::{ModemPreset, RegionCode};
+use meshtastic::protobufs::config::LoRaConfig;
+
+const PREAMBLE_SYMBOLS: f64 = 16.0;
+/// Every packet carries this much unencrypted header ahead of its payload.
+pub const MESH_HEADER_BYTES: usize = 16;
+/// Roughly what a position report comes to, header and all.
+pub const POSITION_PACKET_BYTES: usize = 60;
+pub const TELEMETRY_PACKET_BYTES: usize = 50;
+pub const NODEINFO_PACKET_BYTES: usize = 70;
+pub const NEIGHBORINFO_PACKET_BYTES: usize = 60;
+/// What a text message adds to its text: the mesh header and the `Data` wrapped around it.
+pub const TEXT_OVERHEAD_BYTES: usize = MESH_HEADER_BYTES + 4;
+pub const MAX_PACKET_BYTES: usize = 255;
+const MS_PER_HOUR: f64 = 3_600_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modulation {
+    pub bandwidth_khz: f64,
+    pub spreading_factor: u32,
+    /// The denominator of the coding rate, 5 to 8 for 4/5 to 4/8.
+    pub coding_rate: u32,
+}
+
+impl Modulation {
+    /// What the radio is set to: the preset's settings, or the custom ones if it isn't using
+    /// a preset.  Custom settings left at 0 fall back to LongFast's, like the firmware.
+    pub fn from_config(lora: &LoRaConfig) -> Self {
+        let preset = Modulation::preset(lora.modem_preset());
+        if lora.use_preset {
+            return preset;
+        }
+        Modulation {
+            bandwidth_khz: match lora.bandwidth {
+                0 => preset.bandwidth_khz,
+                // the firmware takes these as shorthand for the odd sizes.
+                31 => 31.25,
+                62 => 62.5,
+                200 => 203.125,
+                400 => 406.25,
+                800 => 812.5,
+                1600 => 1625.0,
+                bw => bw as f64,
+            },
+            spreading_factor: match lora.spread_factor {
+                0 => preset.spreading_factor,
+                sf => sf.clamp(7, 12),
+            },
+            coding_rate: match lora.coding_rate {
+                0 => preset.coding_rate,
+                cr => cr.clamp(5, 8),
+            },
+        }
+    }
+
+    pub fn preset(preset: ModemPreset) -> Self {
+        let (bandwidth_khz, spreading_factor, coding_rate) = match preset {
+            ModemPreset::ShortFast => (250.0, 7, 5),
+            ModemPreset::ShortSlow => (250.0, 8, 5),
+            ModemPreset::MediumFast => (250.0, 9, 5),
+            ModemPreset::MediumSlow => (250.0, 10, 5),
+            ModemPreset::LongFast => (250.0, 11, 5),
+            ModemPreset::LongModerate => (125.0, 11, 8),
+            ModemPreset::LongSlow => (125.0, 12, 8),
+            ModemPreset::VeryLongSlow => (62.5, 12, 8),
+        };
+        Modulation {
+            bandwidth_khz,
+            spreading_factor,
+            coding_rate,
+        }
+    }
+
+    fn symbol_ms(&self) -> f64 {
+        2f64.powi(self.spreading_factor as i32) / self.bandwidth_khz
+    }
+
+    /// How long a packet of `bytes` (header included) takes to send, in milliseconds.
+    pub fn time_on_air_ms(&self, bytes: usize) -> f64 {
+        let symbol_ms = self.symbol_ms();
+        let sf = self.spreading_factor as f64;
+        let low_data_rate = if symbol_ms > 16.0 { 1.0 } else { 0.0 };
+        let bits = 8.0 * bytes as f64 - 4.0 * sf + 28.0 + 16.0;
+        let blocks = (bits / (4.0 * (sf - 2.0 * low_data_rate))).ceil().max(0.0);
+        let payload_symbols = 8.0 + blocks * self.coding_rate as f64;
+        (PREAMBLE_SYMBOLS + 4.25 + payload_symbols) * symbol_ms
+    }
+}
+
+impl std::fmt::Display for Modulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SF{} at {}kHz, CR 4/{}",
+            self.spreading_factor, self.bandwidth_khz, self.coding_rate
+        )
+    }
+}
+
+/// Something the node sends every so often without being asked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheduled {
+    pub name: &'static str,
+    pub interval_secs: u32,
+    /// Time on air for one of them, in milliseconds.
+    pub each_ms: f64,
+}
+
+impl Scheduled {
+    pub fn ms_per_hour(&self) -> f64 {
+        self.each_ms * 3600.0 / self.interval_secs as f64
+    }
+}
+
+fn or_default(secs: u32, default: u32) -> u32 {
+    match secs {
+        0 => default,
+        secs => secs,
+    }
+}
+
+/// How the node's own scheduled traffic adds up over an hour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    pub modulation: Modulation,
+    pub region: RegionCode,
+    pub scheduled: Vec<Scheduled>,
+}
+
+impl Budget {
+    pub fn new(config: &DeviceConfiguration) -> Self {
+        let modulation = Modulation::from_config(&config.lora);
+        let mut sources = vec![
+            (
+                "Position",
+                or_default(
+                    config.position.position_broadcast_secs,
+                    consts::DEFAULT_POSITION_BROADCAST_SECS,
+                ),
+                POSITION_PACKET_BYTES,
+            ),
+            (
+                "Device telemetry",
+                or_default(
+                    config.telemetry.device_update_interval,
+                    consts::DEFAULT_TELEMETRY_INTERVAL_SECS,
+                ),
+                TELEMETRY_PACKET_BYTES,
+            ),
+            (
+                "Node info",
+                consts::NODEINFO_BROADCAST_SECS,
+                NODEINFO_PACKET_BYTES,
+            ),
+        ];
+        if config.telemetry.environment_measurement_enabled {
+            sources.push((
+                "Environment telemetry",
+                or_default(
+                    config.telemetry.environment_update_interval,
+                    consts::DEFAULT_TELEMETRY_INTERVAL_SECS,
+                ),
+                TELEMETRY_PACKET_BYTES,
+            ));
+        }
+        if config.neighbor_info.enabled {
+            sources.push((
+                "Neighbor info",
+                or_default(
+                    config.neighbor_info.update_interval,
+                    consts::DEFAULT_NEIGHBORINFO_INTERVAL_SECS,
+                ),
+                NEIGHBORINFO_PACKET_BYTES,
+            ));
+        }
+        let scheduled = sources
+            .into_iter()
+            .map(|(name, interval_secs, bytes)| Scheduled {
+                name,
+                interval_secs,
+                each_ms: modulation.time_on_air_ms(bytes),
+            })
+            .collect();
+        Budget {
+            modulation,
+            region: config.lora.region(),
+            scheduled,
+        }
+    }
+
+    pub fn ms_per_hour(&self) -> f64 {
+        self.scheduled.iter().map(Scheduled::ms_per_hour).sum()
+    }
+
+    /// The share of the hour spent transmitting, from 0 to 1.
+    pub fn share_of_hour(&self) -> f64 {
+        self.ms_per_hour() / MS_PER_HOUR
+    }
+
+    /// What a text message of `text_bytes` costs, in milliseconds on air.
+    pub fn text_ms(&self, text_bytes: usize) -> f64 {
+        self.modulation
+            .time_on_air_ms((text_bytes + TEXT_OVERHEAD_BYTES).min(MAX_PACKET_BYTES))
+    }
+
+    /// A line per thing worth saying, flagged true when it goes over one of the EU limits.
+    pub fn summary(&self) -> Vec<(String, bool)> {
+        let mut lines = vec![
+            (
+                format!(
+                    "{}: {:.0}ms a position, {:.0}ms a full {MAX_PACKET_BYTES} byte packet",
+                    self.modulation,
+                    self.modulation.time_on_air_ms(POSITION_PACKET_BYTES),
+                    self.modulation.time_on_air_ms(MAX_PACKET_BYTES)
+                ),
+                false,
+            ),
+            (String::new(), false),
+        ];
+        for s in self.scheduled.iter() {
+            lines.push((
+                format!(
+                    "{} every {}s: {:.0}ms each, {:.1}s an hour",
+                    s.name,
+                    s.interval_secs,
+                    s.each_ms,
+                    s.ms_per_hour() / 1000.0
+                ),
+                false,
+            ));
+        }
+        let share = self.share_of_hour();
+        lines.push((
+            format!(
+                "In all: {:.1}s an hour on the air, {:.2}% of the time",
+                self.ms_per_hour() / 1000.0,
+                share * 100.0
+            ),
+            false,
+        ));
+        lines.push((String::new(), false));
+        if !matches!(self.region, RegionCode::Eu868 | RegionCode::Eu433) {
+            lines.push((
+                format!("{:?} isn't an EU region, but for comparison:", self.region),
+                false,
+            ));
+        }
+        for (limit, band) in consts::EU_DUTY_CYCLE_LIMITS {
+            let used = share / limit * 100.0;
+            lines.push((
+                format!(
+                    "EU {:.0}% limit ({band}): {used:.1}% of it used before any messages",
+                    limit * 100.0
+                ),
+                used > 100.0,
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(got: f64, want: f64) {
+        assert!((got - want).abs() < 0.01, "got {got}ms, wanted {want}ms");
+    }
+
+    #[test]
+    fn long_fast_position() {
+        // 83.25 symbols of 8.192ms each.
+        let m = Modulation::preset(ModemPreset::LongFast);
+        assert_close(m.time_on_air_ms(POSITION_PACKET_BYTES), 681.984);
+    }
+
+    #[test]
+    fn very_long_slow_uses_low_data_rate_optimisation() {
+        // 65.536ms symbols; without the optimisation this would be 108.25 symbols, not 124.25.
+        let m = Modulation::preset(ModemPreset::VeryLongSlow);
+        assert!(m.symbol_ms() > 16.0);
+        assert_close(m.time_on_air_ms(POSITION_PACKET_BYTES), 8142.848);
+    }
+
+    #[test]
+    fn time_on_air_grows_with_the_packet() {
+        let m = Modulation::preset(ModemPreset::MediumFast);
+        assert!(m.time_on_air_ms(10) < m.time_on_air_ms(MAX_PACKET_BYTES));
+    }
+
+    #[test]
+    fn custom_settings_fall_back_to_long_fast() {
+        let lora = LoRaConfig {
+            use_preset: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            Modulation::from_config(&lora),
+            Modulation::preset(ModemPreset::LongFast)
+        );
+        let lora = LoRaConfig {
+            use_preset: false,
+            bandwidth: 31,
+            spread_factor: 20,
+            ..Default::default()
+        };
+        let m = Modulation::from_config(&lora);
+        assert_eq!(m.bandwidth_khz, 31.25);
+        assert_eq!(m.spreading_factor, 12);
+        assert_eq!(m.coding_rate, 5);
+    }
+}
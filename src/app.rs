@@ -1,26 +1,35 @@
+use crate::admin;
+use crate::airtime::Budget;
+use crate::config_check;
+use crate::config_file::{self, Profile};
 use crate::consts;
+use crate::crypto::{self, ChannelKey};
 use crate::ipc::IPCMessage;
 use crate::meshtastic_interaction::meshtastic_loop;
+use crate::mqtt::MqttSettings;
 use crate::packet_handler::{process_packet, MessageEnvelope, PacketResponse};
-use crate::tabs::nodes::ComprehensiveNode;
+use crate::picker::{ConnectPicker, PickerAction};
+use crate::store_forward::StoreForwardClient;
 use crate::tabs::*;
 use crate::theme::THEME;
 use crate::tui::Event;
-use crate::{tui, util};
+use crate::{theme, tui, util};
 use anyhow::Result;
 use color_eyre::eyre::WrapErr;
-use crossterm::event::KeyCode;
+use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::style::Print;
 use crossterm::terminal::{disable_raw_mode, LeaveAlternateScreen};
 use itertools::Itertools;
 use meshtastic::packet::PacketDestination;
-use meshtastic::protobufs::Channel;
+use meshtastic::protobufs::{config, module_config, Channel, DeviceMetadata, QueueStatus, User};
 use meshtastic::types::MeshChannel;
-use ratatui::widgets::{Clear, Paragraph};
+use ratatui::widgets::{Clear, Paragraph, Wrap};
 use ratatui::{
     prelude::*,
     widgets::{Block, Borders, Tabs},
 };
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 
 use meshtastic::protobufs::config::*;
@@ -39,6 +48,8 @@ pub struct App {
     pub tab: MenuTabs,
     pub nodes_tab: NodesTab,
     pub channels_tab: ChannelsTab,
+    pub waypoints_tab: WaypointsTab,
+    pub range_test_tab: RangeTestTab,
     pub device_config_tab: ConfigTab,
     pub modules_config_tab: ModulesConfigTab,
     pub messages_tab: MessagesTab,
@@ -46,8 +57,20 @@ pub struct App {
     pub input_mode: InputMode,
     pub cursor_position: usize,
     pub input: String,
-    pub connection: Connection,
+    /// Every radio we're talking to; a radio's `RadioId` is its place in this list.
+    pub connections: Vec<Connection>,
+    /// The active radio's, kept here so rendering doesn't have to wait on a lock.
+    pub connection_state: ConnectionState,
+    pub my_node_ids: HashMap<RadioId, u32>,
+    pub queue_status: Option<QueueStatus>,
     pub user_prefs: Preferences,
+    pub profiles: BTreeMap<String, Profile>,
+    pub picker: Option<ConnectPicker>,
+    pub store_forward: StoreForwardClient,
+    /// Radios that told us they rebooted, to be reconnected on the next tick.
+    pub rebooted: Vec<RadioId>,
+    /// Set when something wants the terminal bell, which rings between frames.
+    pub ring_bell: bool,
 }
 
 impl App {
@@ -67,28 +90,180 @@ impl App {
 
         Widget::render(Clear, area, buf);
         Widget::render(popup_block, popup_area, buf);
+        let input_area = centered_rect(popup_area, 75, 25);
         Widget::render(
             Paragraph::new(self.input.clone()).style(THEME.message_selected),
-            centered_rect(popup_area, 75, 25),
+            input_area,
             buf,
         );
+        // what sending this costs, against the hour the node's own traffic already takes.
+        if let Some(config) = util::try_device_config() {
+            let budget = Budget::new(&config);
+            let message_ms = budget.text_ms(self.input.len());
+            let (limit, _) = consts::EU_DUTY_CYCLE_LIMITS[1];
+            let cost = Line::from(format!(
+                "{} bytes, {:.0}ms on air: {:.2}% of the stricter EU {:.0}% hourly allowance \
+                 ({:.1}% already goes on the node's own traffic)",
+                self.input.len(),
+                message_ms,
+                message_ms / (limit * 3_600_000.0) * 100.0,
+                limit * 100.0,
+                budget.share_of_hour() / limit * 100.0
+            ));
+            let cost_area = Rect {
+                y: input_area.bottom(),
+                height: popup_area.bottom().saturating_sub(input_area.bottom() + 1),
+                ..input_area
+            };
+            Widget::render(
+                Paragraph::new(cost)
+                    .style(THEME.date_display)
+                    .wrap(Wrap { trim: true }),
+                cost_area,
+                buf,
+            );
+        }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct Preferences {
     pub(crate) initialized: String,
     pub(crate) show_mqtt: bool,
+    pub(crate) record_path: Option<String>,
+    pub(crate) default_tab: MenuTabs,
+    pub(crate) message_retention: usize,
+    pub(crate) timeseries_retention: usize,
+    pub(crate) tick_rate: f64,
+    pub(crate) frame_rate: f64,
+    pub(crate) units: Units,
+    pub(crate) theme: String,
+    /// Keys for decrypting channels our radios don't have.
+    pub(crate) channel_keys: Vec<ChannelKey>,
+    /// Ring the terminal bell when a detection sensor goes off.
+    pub(crate) detection_alert: bool,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            initialized: String::new(),
+            show_mqtt: false,
+            record_path: None,
+            default_tab: MenuTabs::default(),
+            message_retention: consts::MAX_MSG_RETENTION,
+            timeseries_retention: consts::MAX_TIMESERIES_RETENTION,
+            tick_rate: consts::TICK_RATE,
+            frame_rate: consts::FRAME_RATE,
+            units: Units::default(),
+            theme: theme::DEFAULT_THEME_NAME.to_string(),
+            channel_keys: vec![],
+            detection_alert: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl Units {
+    pub fn distance(&self, meters: f64) -> String {
+        match self {
+            Units::Metric => format!("{:.3}km", meters / 1000.0_f64),
+            Units::Imperial => format!("{:.3}mi", meters / 1609.344_f64),
+        }
+    }
+    pub fn altitude(&self, meters: i32) -> String {
+        match self {
+            Units::Metric => format!("{}m", meters),
+            Units::Imperial => format!("{:.0}ft", meters as f64 * 3.28084_f64),
+        }
+    }
+}
+
+/// Which of our radios something came from or is going to.
+pub type RadioId = usize;
+
+/// One radio task and the channel its packets come back on.  These live in whatever loop is
+/// driving the app, `run` or the daemon, since none of it can be cloned.
+pub(crate) struct RadioLink {
+    pub id: RadioId,
+    pub rx: mpsc::Receiver<IPCMessage>,
+    pub join_handle: JoinHandle<Result<()>>,
+    pub reconnect_attempts: u32,
+    last_state: ConnectionState,
+    /// After losing the radio: when to stop showing why, and the backoff to start then.
+    pending_retry: Option<(u64, u64)>,
+    /// Set once `rx` has nothing more to give, so `recv_any` stops asking it.
+    closed: bool,
+}
+
+/// The next packet from any of the radios, along with which one sent it.  Never resolves if
+/// none of them have anything left to say.
+pub(crate) async fn recv_any(links: &mut [RadioLink]) -> (RadioId, IPCMessage) {
+    loop {
+        let open: Vec<_> = links
+            .iter_mut()
+            .filter(|link| !link.closed)
+            .map(|link| {
+                Box::pin(async move {
+                    let packet = link.rx.recv().await;
+                    (link, packet)
+                })
+            })
+            .collect();
+        if open.is_empty() {
+            return futures::future::pending().await;
+        }
+        let ((link, packet), _, _) = futures::future::select_all(open).await;
+        match packet {
+            Some(packet) => return (link.id, packet),
+            None => link.closed = true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub enum Connection {
     TCP(String, u16),
     Serial(String),
+    Replay(String, f64),
+    Simulated,
+    Attach(String),
+    Mqtt(MqttSettings),
     #[default]
     None,
 }
 
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Configuring,
+    Connected,
+    Disconnected(String),
+    Retrying(u64),
+}
+
+impl ConnectionState {
+    pub fn status_text(&self) -> String {
+        match self {
+            ConnectionState::Connecting => "Connecting".to_string(),
+            ConnectionState::Configuring => "Configuring".to_string(),
+            ConnectionState::Connected => "Connected".to_string(),
+            ConnectionState::Disconnected(reason) => format!("Disconnected: {reason}"),
+            ConnectionState::Retrying(at) => {
+                format!("Retrying in {}s", at.saturating_sub(util::get_secs()))
+            }
+        }
+    }
+}
+
 impl App {
     fn chain_hook(&mut self) {
         let original_hook = std::panic::take_hook();
@@ -105,6 +280,8 @@ impl App {
             MenuTabs::Nodes => self.nodes_tab.escape(),
             MenuTabs::Messages => self.messages_tab.escape(),
             MenuTabs::Channels => self.channels_tab.escape(),
+            MenuTabs::Waypoints => self.waypoints_tab.escape(),
+            MenuTabs::RangeTest => self.range_test_tab.escape(),
             MenuTabs::DeviceConfig => self.device_config_tab.escape(),
             MenuTabs::ModulesConfig => self.modules_config_tab.escape(),
             MenuTabs::About => self.about_tab.escape(),
@@ -114,165 +291,452 @@ impl App {
         if num == 12 {
             self.mode = Mode::RestartComms;
         }
+        if num == 10 {
+            self.picker = Some(ConnectPicker::new(self.profiles.clone(), true));
+            return;
+        }
+        if num == 9 {
+            self.next_radio();
+            return;
+        }
         match self.tab {
-            MenuTabs::Nodes => self.nodes_tab.function_key(num).await,
-            MenuTabs::Messages => self.messages_tab.function_key(num),
+            MenuTabs::Nodes => match num {
+                3 => self.administer_selected_node().await,
+                _ => self.nodes_tab.function_key(num).await,
+            },
+            MenuTabs::Messages => match num {
+                2 => {
+                    if let Err(e) = self.store_forward.request_history(None).await {
+                        error!("Couldn't ask for Store & Forward history: {e}");
+                    }
+                }
+                3 => {
+                    if let Err(e) = self.store_forward.request_stats().await {
+                        error!("Couldn't ask for Store & Forward stats: {e}");
+                    }
+                }
+                _ => self.messages_tab.function_key(num),
+            },
             MenuTabs::Channels => self.channels_tab.function_key(num).await,
-            MenuTabs::DeviceConfig => self.device_config_tab.function_key(num),
-            MenuTabs::ModulesConfig => self.modules_config_tab.function_key(num),
+            MenuTabs::Waypoints => self.waypoints_tab.function_key(num).await,
+            MenuTabs::RangeTest => self.range_test_tab.function_key(num),
+            MenuTabs::DeviceConfig => self.device_config_tab.function_key(num).await,
+            MenuTabs::ModulesConfig => self.modules_config_tab.function_key(num).await,
             _ => {}
         }
     }
 
+    /// Points the config tabs at the node picked in the nodes tab and asks it for its config.
+    async fn administer_selected_node(&mut self) {
+        let node = match self.nodes_tab.selected_node() {
+            Some(n) => n,
+            None => return,
+        };
+        if node == self.nodes_tab.my_node_id {
+            util::set_admin_target(None);
+        } else {
+            util::set_admin_target(Some(node));
+            if let Err(e) = admin::request_remote_config(node).await {
+                error!("Couldn't ask !{node:x} for its config: {e}");
+                return;
+            }
+        }
+        self.tab = MenuTabs::DeviceConfig;
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         self.chain_hook();
         let mut tui = tui::Tui::new()
             .unwrap()
-            .tick_rate(consts::TICK_RATE)
-            .frame_rate(consts::FRAME_RATE);
+            .tick_rate(self.user_prefs.tick_rate)
+            .frame_rate(self.user_prefs.frame_rate);
 
         let _ = tui.enter(); // Starts event handler, enters raw mode, enters alternate screen
 
-        let (mut fromradio_thread_tx, mut fromradio_thread_rx) =
+        if self.connections.is_empty() {
+            self.picker = Some(ConnectPicker::new(self.profiles.clone(), false));
+        }
+
+        let mut links: Vec<RadioLink> = vec![];
+        self.tend_radios(&mut links).await;
+
+        while self.is_running() {
+            tokio::select! {
+                maybe_event = tui.next() => match maybe_event {
+                    Some(Event::Key(press)) => {
+                        self.handle_key(press).await;
+                        // keypresses get drawn right away so the ui doesn't feel laggy.
+                        self.run_tabs().await;
+                        let _ = self.draw(&mut tui.terminal);
+                    }
+                    Some(Event::Tick) => {
+                        self.tend_radios(&mut links).await;
+                    }
+                    Some(Event::Render) | Some(Event::Resize(_, _)) => {
+                        // everything that arrived since the last frame goes in before we draw it.
+                        for link in links.iter_mut() {
+                            while let Ok(packet) = link.rx.try_recv() {
+                                self.handle_packet(link.id, packet).await;
+                            }
+                        }
+                        self.run_tabs().await;
+                        let _ = self.draw(&mut tui.terminal);
+                    }
+                    Some(_) => {}
+                    None => self.mode = Mode::Exiting,
+                },
+                (radio, packet) = recv_any(&mut links) => {
+                    self.handle_packet(radio, packet).await;
+                }
+            }
+            if std::mem::take(&mut self.ring_bell) {
+                // through the writer ratatui draws with, so it never lands mid-frame.
+                let backend = tui.terminal.backend_mut();
+                let _ = crossterm::queue!(backend, Print('\x07'))
+                    .and_then(|_| io::Write::flush(backend));
+            }
+        }
+
+        let _ = tui.exit(); // stops event handler, exits raw mode, exits alternate screen
+        for link in links.iter() {
+            link.join_handle.abort();
+        }
+        Ok(())
+    }
+
+    /// Spins up a fresh task for `radio` along with the channels to talk to it.
+    pub(crate) async fn spawn_comms(
+        &self,
+        radio: RadioId,
+    ) -> (mpsc::Receiver<IPCMessage>, JoinHandle<Result<()>>) {
+        let (fromradio_thread_tx, fromradio_thread_rx) =
             mpsc::channel::<IPCMessage>(consts::MPSC_BUFFER_SIZE);
-        let (mut toradio_thread_tx, mut toradio_thread_rx) =
+        let (toradio_thread_tx, toradio_thread_rx) =
             mpsc::channel::<IPCMessage>(consts::MPSC_BUFFER_SIZE);
-
         {
             let mut trm = crate::TO_RADIO_MPSC.write().await;
-            *trm = Some(toradio_thread_tx.clone());
+            trm.insert(radio, toradio_thread_tx);
         }
-        let fromradio_tx = fromradio_thread_tx.clone();
-        let conn = self.connection.clone();
-
-        let mut join_handle: JoinHandle<Result<()>> = tokio::task::spawn(async move {
-            meshtastic_loop(conn, fromradio_tx, toradio_thread_rx).await
+        let conn = self.connections[radio].clone();
+        let join_handle: JoinHandle<Result<()>> = tokio::task::spawn(async move {
+            meshtastic_loop(radio, conn, fromradio_thread_tx, toradio_thread_rx).await
         });
+        (fromradio_thread_rx, join_handle)
+    }
 
-        while self.is_running() {
-            // check if we requested a comm restart
-            if self.mode == Mode::RestartComms {
-                join_handle.abort();
-                self.mode = Mode::Running;
+    /// Starts tasks for radios that don't have one yet, restarts the ones that are due, and
+    /// handles a requested restart of the active one.
+    pub(crate) async fn tend_radios(&mut self, links: &mut Vec<RadioLink>) {
+        while links.len() < self.connections.len() {
+            let radio = links.len();
+            let (rx, join_handle) = self.spawn_comms(radio).await;
+            links.push(RadioLink {
+                id: radio,
+                rx,
+                join_handle,
+                reconnect_attempts: 0,
+                last_state: ConnectionState::default(),
+                pending_retry: None,
+                closed: false,
+            });
+        }
+        // check if we requested a comm restart
+        if self.mode == Mode::RestartComms {
+            if let Some(link) = links.get_mut(util::active_radio()) {
+                info!("Restarting radio communications.");
+                link.join_handle.abort();
+                // a manual restart shouldn't have to sit through a backoff from earlier failures.
+                link.reconnect_attempts = 0;
+                util::set_connection_state(link.id, ConnectionState::Retrying(util::get_secs()))
+                    .await;
             }
-
-            // execute runs, if needed
-            match self.tab {
-                MenuTabs::Nodes => self.nodes_tab.run().await,
-                MenuTabs::Messages => self.messages_tab.run().await,
-                MenuTabs::Channels => self.channels_tab.run().await,
-                MenuTabs::DeviceConfig => self.device_config_tab.run().await,
-                MenuTabs::ModulesConfig => self.modules_config_tab.run().await,
-                _ => {}
+            self.mode = Mode::Running;
+        }
+        // reconnecting fetches the config afresh, which is how we catch what the reboot changed.
+        for radio in std::mem::take(&mut self.rebooted) {
+            if let Some(link) = links.get_mut(radio) {
+                info!("Radio {} rebooted, reconnecting to it.", radio + 1);
+                link.join_handle.abort();
+                link.reconnect_attempts = 0;
+                let at = util::get_secs() + consts::REBOOT_RECONNECT_DELAY_SECS;
+                util::set_connection_state(radio, ConnectionState::Retrying(at)).await;
+            }
+        }
+        for link in links.iter_mut() {
+            if self.tend_comms(link).await {
+                (link.rx, link.join_handle) = self.spawn_comms(link.id).await;
+                link.closed = false;
             }
+        }
+        self.connection_state = util::get_connection_state(util::active_radio()).await;
+        self.store_forward
+            .tend(self.connection_state == ConnectionState::Connected)
+            .await;
+        self.nodes_tab.radio_count = self.connections.len();
+    }
 
-            // draw screen
-            let _ = self.draw(&mut tui.terminal);
-
-            // process input
-            if let Some(Event::Key(press)) = tui.next().await {
-                use KeyCode::*;
-                match self.input_mode {
-                    InputMode::Normal => match press.code {
-                        Char('q') | Esc => self.escape(),
-                        Char('h') | Left => self.left(),
-                        Char('l') | Right => self.right(),
-                        Char('k') | Up => self.prev(),
-                        Char('j') | Down => self.next(),
-                        PageUp => self.prev_page(),
-                        PageDown => self.next_page(),
-                        KeyCode::Enter => self.enter_key().await,
-                        KeyCode::BackTab => self.prev_tab(),
-                        KeyCode::Tab => self.next_tab(),
-                        KeyCode::F(n) => self.function_key(n).await,
-                        _ => {}
-                    },
-                    InputMode::Editing => match press.code {
-                        KeyCode::Enter => self.enter_key().await,
-                        KeyCode::Char(to_insert) => self.enter_char(to_insert),
-                        KeyCode::Backspace => {
-                            self.delete_char();
-                        }
-                        KeyCode::Left => {
-                            self.move_cursor_left();
-                        }
-                        KeyCode::Right => {
-                            self.move_cursor_right();
-                        }
-                        KeyCode::Esc => {
-                            self.input_mode = InputMode::Normal;
-                        }
-                        _ => {}
-                    },
+    /// Keeps an eye on one radio task.  Returns true when it's time to start a new one.
+    async fn tend_comms(&self, link: &mut RadioLink) -> bool {
+        let state = util::get_connection_state(link.id).await;
+        let previous_state = std::mem::replace(&mut link.last_state, state.clone());
+        if state == ConnectionState::Connected {
+            link.reconnect_attempts = 0;
+            if previous_state != ConnectionState::Connected {
+                if let Connection::TCP(host, port) = &self.connections[link.id] {
+                    if let Err(e) = config_file::remember_host(host, *port) {
+                        warn!("Couldn't save {host} to the recent hosts list: {e}");
+                    }
                 }
-            };
+            }
+        }
+        if !link.join_handle.is_finished() {
+            return false;
+        }
 
-            // execute action logic
-            if let Ok(packet) = fromradio_thread_rx.try_recv() {
-                let update = process_packet(packet, self.nodes_tab.node_list.clone()).await;
-                if update.is_some() {
-                    // we received an update on a node
-                    match update.unwrap() {
-                        PacketResponse::NodeUpdate(id, cn) => {
-                            self.nodes_tab.node_list.insert(id, *cn);
-                        }
-                        PacketResponse::InboundMessage(envelope) => {
-                            if let Some(cn) = self
-                                .nodes_tab
-                                .node_list
-                                .get(&envelope.clone().source.unwrap().num)
-                            {
-                                let mut ncn = cn.clone();
-                                ncn.last_rssi = envelope.rx_rssi;
-                                ncn.last_snr = envelope.rx_snr;
-                                self.nodes_tab
-                                    .node_list
-                                    .insert(envelope.clone().source.unwrap().num, ncn);
-                            }
-                            self.messages_tab.messages.push_back(envelope);
-                        }
-                        PacketResponse::UserUpdate(id, user) => {
-                            if let Some(cn) = self.nodes_tab.node_list.get(&id) {
-                                let mut ncn = cn.clone();
-                                ncn.node_info.user = Some(user);
-                                ncn.last_seen = util::get_secs();
-                                self.nodes_tab.node_list.insert(id, ncn);
-                            } else {
-                                let mut cn = ComprehensiveNode::with_id(id);
-                                cn.node_info.user = Some(user);
-                                cn.last_seen = util::get_secs();
-                                self.nodes_tab.node_list.insert(id, cn);
-                            }
-                        }
-                        PacketResponse::OurAddress(id) => {
-                            self.nodes_tab.my_node_id = id;
-                            self.nodes_tab.my_node_id = id;
-                        }
-                    }
+        match (state, link.pending_retry) {
+            (ConnectionState::Retrying(at), _) => util::get_secs() >= at,
+            // the reason stays up for a moment before the countdown replaces it.
+            (ConnectionState::Disconnected(_), Some((shown_until, backoff))) => {
+                let now = util::get_secs();
+                if now >= shown_until {
+                    link.pending_retry = None;
+                    info!("Reconnecting to radio {} in {backoff}s", link.id + 1);
+                    util::set_connection_state(link.id, ConnectionState::Retrying(now + backoff))
+                        .await;
                 }
+                false
             }
+            _ => {
+                let reason = match (&mut link.join_handle).await {
+                    Ok(Ok(())) => "radio loop exited".to_string(),
+                    Ok(Err(e)) => e.to_string(),
+                    Err(e) if e.is_cancelled() => "restart requested".to_string(),
+                    Err(e) => e.to_string(),
+                };
+                warn!("Radio {} connection lost: {reason}", link.id + 1);
+                util::set_connection_state(link.id, ConnectionState::Disconnected(reason)).await;
+                let backoff = consts::RECONNECT_BACKOFF_MAX_SECS.min(
+                    consts::RECONNECT_BACKOFF_MIN_SECS
+                        << link
+                            .reconnect_attempts
+                            .min(consts::RECONNECT_BACKOFF_MAX_DOUBLINGS),
+                );
+                link.reconnect_attempts = link.reconnect_attempts.saturating_add(1);
+                let shown_until = util::get_secs() + consts::DISCONNECTED_SHOWN_SECS;
+                link.pending_retry = Some((shown_until, backoff));
+                false
+            }
+        }
+    }
+
+    async fn run_tabs(&mut self) {
+        // execute runs, if needed
+        match self.tab {
+            MenuTabs::Nodes => self.nodes_tab.run().await,
+            MenuTabs::Messages => self.messages_tab.run().await,
+            MenuTabs::Channels => self.channels_tab.run().await,
+            MenuTabs::Waypoints => {
+                let my_id = self.nodes_tab.my_node_id;
+                let my_position = self
+                    .nodes_tab
+                    .node_list
+                    .with_node(my_id, |cn| cn.node_info.position.clone())
+                    .flatten();
+                self.waypoints_tab.run(my_id, my_position).await
+            }
+            MenuTabs::RangeTest => self.range_test_tab.run().await,
+            MenuTabs::DeviceConfig => {
+                let my_id = self.nodes_tab.my_node_id;
+                let me = self
+                    .nodes_tab
+                    .node_list
+                    .with_node(my_id, |cn| cn.node_info.clone());
+                self.device_config_tab.run(my_id, me).await
+            }
+            MenuTabs::ModulesConfig => {
+                let my_id = self.nodes_tab.my_node_id;
+                self.modules_config_tab.run(my_id).await
+            }
+            _ => {}
+        }
+    }
 
-            // tend to our threads
-            if join_handle.is_finished() {
-                (fromradio_thread_tx, fromradio_thread_rx) =
-                    mpsc::channel::<IPCMessage>(consts::MPSC_BUFFER_SIZE);
-                (toradio_thread_tx, toradio_thread_rx) =
-                    mpsc::channel::<IPCMessage>(consts::MPSC_BUFFER_SIZE);
-                let fromradio_tx = fromradio_thread_tx.clone();
-                {
-                    let mut trm = crate::TO_RADIO_MPSC.write().await;
-                    *trm = Some(toradio_thread_tx.clone());
+    /// Points the active radio somewhere else, forgetting what we learned from the old one.
+    async fn switch_connection(&mut self, connection: Connection) {
+        info!("Switching to {:?}", connection);
+        if self.connections.is_empty() {
+            util::set_active_radio(0);
+            self.connections.push(connection);
+            return;
+        }
+        let radio = util::active_radio();
+        self.connections[radio] = connection;
+        self.nodes_tab.node_list.forget_radio(radio);
+        self.nodes_tab.my_node_id = 0;
+        // the node on show may have been the old radio's alone.
+        self.nodes_tab.display_mode = nodes::DisplayMode::List;
+        self.nodes_tab.selected_node_id = 0;
+        self.my_node_ids.remove(&radio);
+        if self.connections.len() == 1 {
+            self.messages_tab.messages.write().unwrap().clear();
+        }
+        self.queue_status = None;
+        {
+            let mut dc = crate::DEVICE_CONFIGS.write().await;
+            dc.remove(&radio);
+        }
+        self.mode = Mode::RestartComms;
+    }
+
+    /// Makes the next radio the one the config tabs show and that messages go out through.
+    fn next_radio(&mut self) {
+        if self.connections.len() < 2 {
+            return;
+        }
+        let radio = (util::active_radio() + 1) % self.connections.len();
+        util::set_active_radio(radio);
+        self.nodes_tab.my_node_id = self.my_node_ids.get(&radio).copied().unwrap_or_default();
+        self.queue_status = None;
+        info!("Radio {} is now the active radio.", radio + 1);
+    }
+
+    async fn handle_key(&mut self, press: KeyEvent) {
+        use KeyCode::*;
+        if let Some(picker) = self.picker.as_mut() {
+            match picker.handle_key(press) {
+                Some(PickerAction::Connect(connection)) => {
+                    self.picker = None;
+                    self.switch_connection(connection).await;
                 }
-                let conn = self.connection.clone();
-                join_handle = tokio::task::spawn(async move {
-                    meshtastic_loop(conn, fromradio_tx, toradio_thread_rx).await
-                });
+                Some(PickerAction::Cancel) => self.picker = None,
+                Some(PickerAction::Quit) => self.mode = Mode::Exiting,
+                None => {}
             }
+            return;
+        }
+        if self.tab == MenuTabs::Waypoints && self.waypoints_tab.is_editing() {
+            self.waypoints_tab.handle_key(press).await;
+            return;
+        }
+        if self.tab == MenuTabs::DeviceConfig && self.device_config_tab.is_editing() {
+            self.device_config_tab.handle_key(press).await;
+            return;
+        }
+        if self.tab == MenuTabs::ModulesConfig && self.modules_config_tab.is_editing() {
+            self.modules_config_tab.handle_key(press).await;
+            return;
         }
+        match self.input_mode {
+            InputMode::Normal => match press.code {
+                Char('q') | Esc => self.escape(),
+                Char('h') | Left => self.left(),
+                Char('l') | Right => self.right(),
+                Char('k') | Up => self.prev(),
+                Char('j') | Down => self.next(),
+                PageUp => self.prev_page(),
+                PageDown => self.next_page(),
+                KeyCode::Enter => self.enter_key().await,
+                KeyCode::BackTab => self.prev_tab(),
+                KeyCode::Tab => self.next_tab(),
+                KeyCode::F(n) => self.function_key(n).await,
+                _ => {}
+            },
+            InputMode::Editing => match press.code {
+                KeyCode::Enter => self.enter_key().await,
+                KeyCode::Char(to_insert) => self.enter_char(to_insert),
+                KeyCode::Backspace => {
+                    self.delete_char();
+                }
+                KeyCode::Left => {
+                    self.move_cursor_left();
+                }
+                KeyCode::Right => {
+                    self.move_cursor_right();
+                }
+                KeyCode::Esc => {
+                    self.input_mode = InputMode::Normal;
+                }
+                _ => {}
+            },
+        }
+    }
 
-        let _ = tui.exit(); // stops event handler, exits raw mode, exits alternate screen
-        join_handle.abort();
-        Ok(())
+    pub(crate) async fn handle_packet(&mut self, radio: RadioId, mut packet: IPCMessage) {
+        if let IPCMessage::FromRadio(fr) = &mut packet {
+            crypto::decrypt_frame(radio, fr).await;
+            // nobody listening is fine.
+            let _ = crate::PACKET_FEED.send(fr.clone());
+        }
+        let active = radio == util::active_radio();
+        if let Some(update) = process_packet(radio, packet, &self.nodes_tab.node_list).await {
+            match update {
+                PacketResponse::InboundMessage(envelope) => {
+                    let mut messages = self.messages_tab.messages.write().unwrap();
+                    if messages.iter().any(|m| envelope.is_copy_of(m)) {
+                        debug!("Dropping a copy of a message we already have.");
+                        return;
+                    }
+                    messages.push_back(envelope);
+                    while messages.len() > self.user_prefs.message_retention {
+                        messages.pop_front();
+                    }
+                }
+                PacketResponse::OurAddress(id) => {
+                    self.my_node_ids.insert(radio, id);
+                    if active {
+                        self.nodes_tab.my_node_id = id;
+                    }
+                }
+                PacketResponse::QueueStatus(qs) => {
+                    if active {
+                        self.queue_status = Some(qs);
+                    }
+                }
+                PacketResponse::StoreForward(from, sf) => {
+                    self.store_forward.handle(from, sf).await;
+                }
+                PacketResponse::Detection(from, text) => {
+                    let name = self
+                        .nodes_tab
+                        .node_list
+                        .with_node(from, |cn| cn.node_info.user.clone())
+                        .flatten()
+                        .map(|u| u.long_name)
+                        .unwrap_or_else(|| format!("!{from:x}"));
+                    match self.user_prefs.detection_alert {
+                        true => {
+                            warn!("Detection sensor on {name}: {text}");
+                            self.ring_bell = true;
+                        }
+                        false => info!("Detection sensor on {name}: {text}"),
+                    }
+                }
+                PacketResponse::RangeTest(mut sample) => {
+                    let position = |id: u32| {
+                        self.nodes_tab
+                            .node_list
+                            .with_node(id, |cn| cn.node_info.position.clone())
+                            .flatten()
+                    };
+                    let us = self.my_node_ids.get(&radio).copied().unwrap_or_default();
+                    sample.locate(position(sample.from), position(us));
+                    range_test::record(&self.range_test_tab.log, sample);
+                }
+                PacketResponse::Rebooted => {
+                    if !self.rebooted.contains(&radio) {
+                        self.rebooted.push(radio);
+                    }
+                }
+                PacketResponse::Waypoint(waypoint, from, channel) => {
+                    waypoints::file_waypoint(
+                        &self.waypoints_tab.waypoints,
+                        waypoint,
+                        from,
+                        channel,
+                    );
+                }
+            }
+        }
     }
 
     fn draw(&self, terminal: &mut Terminal<impl Backend>) -> Result<()> {
@@ -324,6 +788,8 @@ impl App {
             MenuTabs::Nodes => self.nodes_tab.prev_row(),
             MenuTabs::Messages => self.messages_tab.prev_row(),
             MenuTabs::Channels => self.channels_tab.prev_row(),
+            MenuTabs::Waypoints => self.waypoints_tab.prev_row(),
+            MenuTabs::RangeTest => self.range_test_tab.prev_row(),
             MenuTabs::DeviceConfig => self.device_config_tab.prev_row(),
             MenuTabs::ModulesConfig => self.modules_config_tab.prev_row(),
             MenuTabs::About => self.about_tab.prev_row(),
@@ -346,6 +812,8 @@ impl App {
             MenuTabs::Nodes => self.nodes_tab.next_row(),
             MenuTabs::Messages => self.messages_tab.next_row(),
             MenuTabs::Channels => self.channels_tab.next_row(),
+            MenuTabs::Waypoints => self.waypoints_tab.next_row(),
+            MenuTabs::RangeTest => self.range_test_tab.next_row(),
             MenuTabs::DeviceConfig => self.device_config_tab.next_row(),
             MenuTabs::ModulesConfig => self.modules_config_tab.next_row(),
             MenuTabs::About => self.about_tab.next_row(),
@@ -396,6 +864,9 @@ impl App {
             MenuTabs::Nodes => self.nodes_tab.enter_key(),
             MenuTabs::Messages => self.enter_key_messages().await,
             MenuTabs::Channels => self.channels_tab.enter_key(),
+            MenuTabs::Waypoints => self.waypoints_tab.enter_key(),
+            MenuTabs::DeviceConfig => self.device_config_tab.enter_key(),
+            MenuTabs::ModulesConfig => self.modules_config_tab.enter_key(),
             _ => {}
         }
     }
@@ -452,15 +923,93 @@ impl App {
         TuiLoggerWidget::default().block(block).render(area, buf)
     }
 
-    fn render_bottom_bar(area: Rect, buf: &mut Buffer) {
-        let keys = [
+    fn render_status_bar(&self, area: Rect, buf: &mut Buffer) {
+        let mut spans: Vec<Span> = vec![];
+
+        let me = self
+            .nodes_tab
+            .node_list
+            .with_node(self.nodes_tab.my_node_id, |cn| {
+                (cn.node_info.user.clone(), cn.node_info.device_metrics.clone())
+            });
+        match me {
+            Some((user, device_metrics)) => {
+                let user = user.unwrap_or_default();
+                spans.push(Span::styled(
+                    format!(" {} ({}) ", user.long_name, user.id),
+                    THEME.key_binding.key,
+                ));
+                if let Some(dm) = device_metrics {
+                    spans.push(Span::styled(
+                        format!("| ChUtil {:.1}% ", dm.channel_utilization),
+                        THEME.date_display,
+                    ));
+                }
+            }
+            None => {
+                spans.push(Span::styled(" No device info yet ", THEME.key_binding.key));
+            }
+        }
+
+        // we're mid-render, so this can't wait on the lock.
+        if let Some(config) = util::try_device_config() {
+            if !config.metadata.firmware_version.is_empty() {
+                spans.push(Span::styled(
+                    format!("| FW {} ", config.metadata.firmware_version),
+                    THEME.date_display,
+                ));
+            }
+            spans.push(Span::styled(
+                format!(
+                    "| {:?} {} ",
+                    config.lora.region(),
+                    util::preset_name(&config.lora)
+                ),
+                THEME.date_display,
+            ));
+            let warnings = config_check::check(&config).len();
+            if warnings > 0 {
+                spans.push(Span::styled(
+                    format!(" ! {warnings} config warning(s) "),
+                    THEME.warning_highlight,
+                ));
+            }
+        }
+
+        if let Some(qs) = &self.queue_status {
+            spans.push(Span::styled(
+                format!("| TX queue {}/{} free ", qs.free, qs.maxlen),
+                THEME.date_display,
+            ));
+        }
+
+        if let Some((id, router)) = self.store_forward.best_router() {
+            let saved = match &router.stats {
+                Some(stats) => format!(" {}/{} saved", stats.messages_saved, stats.messages_max),
+                None => String::new(),
+            };
+            spans.push(Span::styled(format!("| S&F !{id:x}{saved} "), THEME.date_display));
+        }
+
+        Line::from(spans)
+            .centered()
+            .style((Color::Indexed(236), Color::Indexed(232)))
+            .render(area, buf);
+    }
+
+    fn render_bottom_bar(&self, area: Rect, buf: &mut Buffer) {
+        let mut keys = vec![
             ("H/←", "Left"),
             ("L/→", "Right"),
             ("K/↑", "Up"),
             ("J/↓", "Down"),
             ("Enter", "Interact/Send"),
+            ("F10", "Switch radio"),
             ("Q/Esc", "Quit"),
         ];
+        if self.connections.len() > 1 {
+            keys.insert(5, ("F9", "Next radio"));
+        }
         let dt: OffsetDateTime = OffsetDateTime::now_utc();
 
         let mut spans = keys
@@ -471,6 +1020,14 @@ impl App {
                 [key, desc]
             })
             .collect_vec();
+        let radio = match self.connections.len() {
+            0 | 1 => String::new(),
+            count => format!("Radio {}/{count} ", util::active_radio() + 1),
+        };
+        spans.push(Span::styled(
+            format!("| {radio}{} ", self.connection_state.status_text()),
+            THEME.date_display,
+        ));
         spans.push(Span::styled(
             format!("| {}", dt.format(consts::DATE_FORMAT).unwrap()),
             THEME.date_display,
@@ -497,6 +1054,8 @@ impl App {
             MenuTabs::Nodes => self.nodes_tab.clone().render(area, buf),
             MenuTabs::Messages => self.messages_tab.clone().render(area, buf),
             MenuTabs::Channels => self.channels_tab.clone().render(area, buf),
+            MenuTabs::Waypoints => self.waypoints_tab.clone().render(area, buf),
+            MenuTabs::RangeTest => self.range_test_tab.clone().render(area, buf),
             MenuTabs::DeviceConfig => self.device_config_tab.clone().render(area, buf),
             MenuTabs::ModulesConfig => self.modules_config_tab.clone().render(area, buf),
             MenuTabs::About => self.about_tab.render(area, buf),
@@ -513,16 +1072,19 @@ impl Widget for &App {
                 Constraint::Min(0),
                 Constraint::Length(12),
                 Constraint::Length(1),
+                Constraint::Length(1),
             ]);
-        let [tabs, middle, event_log, bottom_bar] = layout.areas(area);
+        let [tabs, middle, event_log, status_bar, bottom_bar] = layout.areas(area);
         Block::new().style(THEME.root).render(area, buf);
         self.render_tabs(tabs, buf);
-        match self.input_mode {
-            InputMode::Editing => self.render_send_message_popup(middle, buf),
-            InputMode::Normal => self.render_selected_tab(middle, buf),
+        match (&self.picker, self.input_mode) {
+            (Some(picker), _) => picker.render(middle, buf),
+            (None, InputMode::Editing) => self.render_send_message_popup(middle, buf),
+            (None, InputMode::Normal) => self.render_selected_tab(middle, buf),
         }
         self.render_event_log(event_log, buf);
-        App::render_bottom_bar(bottom_bar, buf);
+        self.render_status_bar(status_bar, buf);
+        self.render_bottom_bar(bottom_bar, buf);
     }
 }
 
@@ -556,6 +1118,8 @@ pub enum MenuTabs {
     Messages,
     Nodes,
     Channels,
+    Waypoints,
+    RangeTest,
     DeviceConfig,
     ModulesConfig,
     About,
@@ -611,5 +1175,59 @@ pub struct DeviceConfiguration {
     pub detection_sensor: DetectionSensorConfig,
     pub paxcounter: PaxcounterConfig,
     pub channels: HashMap<i32, Channel>,
+    pub metadata: DeviceMetadata,
+    /// Only filled in for remote nodes; our own radios' owners are in the node list.
+    pub owner: User,
+    /// What a remote node on firmware 2.5 or later last handed us to prove our `set_*`
+    /// messages are answers to its replies, and when it arrived.
+    pub session_passkey: Vec<u8>,
+    pub passkey_received: u64,
     pub last_update: u64,
 }
+
+impl DeviceConfiguration {
+    pub fn file_config(&mut self, section: config::PayloadVariant) {
+        use config::PayloadVariant;
+        match section {
+            PayloadVariant::Device(d) => self.device = d,
+            PayloadVariant::Position(p) => self.position = p,
+            PayloadVariant::Power(p) => self.power = p,
+            PayloadVariant::Network(n) => self.network = n,
+            PayloadVariant::Display(d) => self.display = d,
+            PayloadVariant::Lora(l) => self.lora = l,
+            PayloadVariant::Bluetooth(b) => self.bluetooth = b,
+        }
+        self.last_update = util::get_secs();
+    }
+
+    pub fn file_module_config(&mut self, module: module_config::PayloadVariant) {
+        use module_config::PayloadVariant as mpv;
+        match module {
+            mpv::Mqtt(o) => self.mqtt = o,
+            mpv::Serial(o) => self.serial = o,
+            mpv::ExternalNotification(o) => self.external_notification = o,
+            mpv::StoreForward(o) => self.store_forward = o,
+            mpv::RangeTest(o) => self.range_test = o,
+            mpv::Telemetry(o) => self.telemetry = o,
+            mpv::CannedMessage(o) => self.canned_message = o,
+            mpv::Audio(o) => self.audio = o,
+            mpv::RemoteHardware(o) => self.remote_hardware = o,
+            mpv::NeighborInfo(o) => self.neighbor_info = o,
+            mpv::AmbientLighting(o) => self.ambient_lighting = o,
+            mpv::DetectionSensor(o) => self.detection_sensor = o,
+            mpv::Paxcounter(o) => self.paxcounter = o,
+        }
+        self.last_update = util::get_secs();
+    }
+
+    pub fn file_channel(&mut self, channel: Channel) {
+        if let Some(settings) = channel.settings.as_ref() {
+            info!(
+                "Storing channel config for {} (Ch: {})",
+                settings.name, channel.index
+            );
+            self.channels.insert(channel.index, channel);
+            self.last_update = util::get_secs();
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! A quick way to see how packet processing holds up on a big mesh.
+//!
+//! `meshtui --benchmark 1000` builds a synthetic 1000-node mesh, plays a NodeInfo dump and a few
+//! rounds of position and telemetry traffic through `process_packet`, and prints how long it
+//! took.  For comparison it plays the same packets through the way nodes used to be updated:
+//! each packet got a deep clone of the whole node list to work on, and the node it changed was
+//! copied back out afterwards.
+use crate::ipc::IPCMessage;
+use crate::node_store::NodeStore;
+use crate::packet_handler::process_packet;
+use crate::simulator::{radio_frame, sim_data, sim_packet};
+use crate::tabs::nodes::ComprehensiveNode;
+use crate::util::get_secs;
+use meshtastic::protobufs::*;
+use meshtastic::Message;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 5_usize;
+const FIRST_NODE_NUM: u32 = 0x0b00_0000;
+
+fn synthetic_mesh(node_count: usize) -> Vec<FromRadio> {
+    let mut packets = vec![];
+    for i in 0..node_count as u32 {
+        let num = FIRST_NODE_NUM + i;
+        packets.push(radio_frame(from_radio::PayloadVariant::NodeInfo(
+            NodeInfo {
+                num,
+                user: Some(User {
+                    id: format!("!{:08x}", num),
+                    long_name: format!("Bench Node {i}"),
+                    short_name: format!("{:04x}", i & 0xffff),
+                    ..Default::default()
+                }),
+                last_heard: get_secs() as u32,
+                hops_away: i % 4,
+                ..Default::default()
+            },
+        )));
+    }
+    for round in 0..ROUNDS as u32 {
+        for i in 0..node_count as u32 {
+            let num = FIRST_NODE_NUM + i;
+            let position = Position {
+                latitude_i: 400_000_000 + (i * 1000 + round) as i32,
+                longitude_i: -740_000_000 - (i * 1000 + round) as i32,
+                time: get_secs() as u32,
+                ..Default::default()
+            };
+            packets.push(sim_packet(
+                num,
+                u32::MAX,
+                i % 4,
+                sim_data(PortNum::PositionApp, position.encode_to_vec()),
+            ));
+            let telemetry = Telemetry {
+                time: get_secs() as u32,
+                variant: Some(telemetry::Variant::DeviceMetrics(DeviceMetrics {
+                    battery_level: 50 + (i + round) % 50,
+                    voltage: 3.7,
+                    channel_utilization: 12.5,
+                    air_util_tx: 1.5,
+                    ..Default::default()
+                })),
+            };
+            packets.push(sim_packet(
+                num,
+                u32::MAX,
+                i % 4,
+                sim_data(PortNum::TelemetryApp, telemetry.encode_to_vec()),
+            ));
+        }
+    }
+    packets
+}
+
+/// The node a frame is about, which is the one the old way copied back into the list.
+fn node_of(fr: &FromRadio) -> Option<u32> {
+    match fr.payload_variant.as_ref()? {
+        from_radio::PayloadVariant::NodeInfo(ni) => Some(ni.num),
+        from_radio::PayloadVariant::Packet(mp) => Some(mp.from),
+        _ => None,
+    }
+}
+
+fn report(label: &str, packets: usize, elapsed: Duration) {
+    let per_packet = elapsed.as_secs_f64() * 1_000_000.0 / packets.max(1) as f64;
+    println!(
+        "{label:<40} {packets:>8} packets in {:>10.3}ms ({per_packet:.2}µs/packet)",
+        elapsed.as_secs_f64() * 1000.0
+    );
+}
+
+pub async fn run_benchmark(node_count: usize) {
+    let packets = synthetic_mesh(node_count);
+    println!(
+        "Synthetic mesh: {node_count} nodes, NodeInfo dump plus {ROUNDS} rounds of position and telemetry."
+    );
+
+    let store = NodeStore::default();
+    let started = Instant::now();
+    for fr in packets.iter().cloned() {
+        process_packet(0, IPCMessage::FromRadio(fr), &store).await;
+    }
+    report(
+        "process_packet, node store",
+        packets.len(),
+        started.elapsed(),
+    );
+
+    let mut node_list: HashMap<u32, ComprehensiveNode> = HashMap::new();
+    let started = Instant::now();
+    for fr in packets.iter().cloned() {
+        let id = node_of(&fr);
+        let copy = NodeStore::from(node_list.clone());
+        process_packet(0, IPCMessage::FromRadio(fr), &copy).await;
+        if let Some(cn) = id.and_then(|id| copy.get(id)) {
+            node_list.insert(cn.id, cn);
+        }
+    }
+    report(
+        "node_list clone per packet (old)",
+        packets.len(),
+        started.elapsed(),
+    );
+}
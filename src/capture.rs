@@ -0,0 +1,123 @@
+//! Capture files let us record a radio session and play it back later without hardware.
+//!
+//! A capture is a flat sequence of frames.  Each frame is an 8-byte little-endian timestamp
+//! (milliseconds since the unix epoch, taken when we received the packet) followed by the
+//! `FromRadio` protobuf, length-delimited the same way prost does it.
+use crate::app::{ConnectionState, RadioId};
+use crate::ipc::IPCMessage;
+use crate::util::set_connection_state;
+use anyhow::{bail, Result};
+use meshtastic::protobufs::FromRadio;
+use meshtastic::Message;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+const TIMESTAMP_LEN: usize = 8;
+
+pub struct CaptureWriter {
+    writer: BufWriter<File>,
+}
+
+impl CaptureWriter {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(CaptureWriter {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write_frame(&mut self, packet: &FromRadio) -> Result<()> {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        self.writer.write_all(&ts.to_le_bytes())?;
+        self.writer
+            .write_all(packet.encode_length_delimited_to_vec().as_slice())?;
+        // flush every frame so a crash doesn't eat the packets that led up to it.
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureFrame {
+    pub timestamp: u64,
+    pub packet: FromRadio,
+}
+
+pub fn read_capture(path: &str) -> Result<Vec<CaptureFrame>> {
+    let contents = std::fs::read(path)?;
+    let mut frames: Vec<CaptureFrame> = vec![];
+    let mut buf = contents.as_slice();
+    while !buf.is_empty() {
+        if buf.len() < TIMESTAMP_LEN {
+            bail!(
+                "Capture file {path} is truncated after {} frames",
+                frames.len()
+            );
+        }
+        let (ts_bytes, rest) = buf.split_at(TIMESTAMP_LEN);
+        let timestamp = u64::from_le_bytes(ts_bytes.try_into().unwrap());
+        buf = rest;
+        let packet = match FromRadio::decode_length_delimited(&mut buf) {
+            Ok(p) => p,
+            Err(e) => {
+                bail!(
+                    "Capture file {path} has a bad frame at #{}: {e}",
+                    frames.len()
+                );
+            }
+        };
+        frames.push(CaptureFrame { timestamp, packet });
+    }
+    Ok(frames)
+}
+
+/// Feeds a capture file back into the app as if a radio was sending it.  A speed of 1.0 plays
+/// back in real time, 10.0 is ten times faster, and 0.0 sends everything as fast as possible.
+pub(crate) async fn replay_loop(
+    radio: RadioId,
+    path: String,
+    speed: f64,
+    tx: Sender<IPCMessage>,
+    mut rx: Receiver<IPCMessage>,
+) -> Result<()> {
+    let frames = read_capture(&path)?;
+    info!("Replaying {} frames from {path} at {speed}x", frames.len());
+    set_connection_state(radio, ConnectionState::Connected).await;
+
+    let mut last_ts: Option<u64> = None;
+    for frame in frames {
+        if let Some(last) = last_ts {
+            if speed > 0.0 {
+                let gap = frame.timestamp.saturating_sub(last) as f64 / speed;
+                tokio::time::sleep(tokio::time::Duration::from_millis(gap as u64)).await;
+            }
+        }
+        last_ts = Some(frame.timestamp);
+        if let Err(e) = tx.send(IPCMessage::FromRadio(frame.packet)).await {
+            error!("Couldn't send FromRadio packet to mpsc: {e}");
+        }
+        while let Ok(outbound) = rx.try_recv() {
+            drop_outbound(outbound);
+        }
+    }
+    info!("Replay of {path} complete.");
+
+    // if we returned here the app would restart us and replay the whole thing again, so just
+    // sit quietly until we're aborted.
+    while let Some(outbound) = rx.recv().await {
+        drop_outbound(outbound);
+    }
+    std::future::pending::<Result<()>>().await
+}
+
+fn drop_outbound(outbound: IPCMessage) {
+    warn!(
+        "Replay mode has no radio, dropping outbound message: {:?}",
+        outbound
+    );
+}
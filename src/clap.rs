@@ -1,18 +1,174 @@
-use clap::Parser;
+use crate::app::Units;
+use clap::{Parser, Subcommand};
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
 pub struct CliArgs {
-    #[arg(short, long, help = "The ip of the host to connect to")]
-    pub ip: Option<String>,
-    #[arg(short, long, help = "The serial port to connect to")]
-    pub serial_port: Option<String>,
     #[arg(
         short,
         long,
-        help = "The tcp port for stream api (defaults to 4403)",
-        default_value_t = 4403
+        help = "The ip (or ip:port, [ipv6]:port) of the host to connect to; repeat for more radios"
     )]
-    pub tcp_port: u16,
+    pub ip: Vec<String>,
+    #[arg(
+        short,
+        long,
+        help = "The serial port to connect to; repeat for more radios"
+    )]
+    pub serial_port: Vec<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Watch a Meshtastic MQTT broker, mqtt://[user[:pass]@]host[:port][/root]; repeat for more"
+    )]
+    pub mqtt: Vec<String>,
+    #[arg(short, long, help = "The tcp port for stream api (defaults to 4403)")]
+    pub tcp_port: Option<u16>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Connect using a profile from the config file; repeat for more radios"
+    )]
+    pub profile: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read this config file instead of ~/.config/meshtui/config.toml"
+    )]
+    pub config: Option<String>,
+    #[arg(long, value_name = "TAB", help = "The tab to start on")]
+    pub tab: Option<String>,
+    #[arg(long, value_enum, help = "Show distances and altitudes in these units")]
+    pub units: Option<Units>,
+    #[arg(long, help = "The color theme to use (default or mono)")]
+    pub theme: Option<String>,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "How many text messages to keep in memory"
+    )]
+    pub message_retention: Option<usize>,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "How many telemetry samples to keep per node"
+    )]
+    pub timeseries_retention: Option<usize>,
+    #[arg(long, value_name = "HZ", help = "Radio housekeeping passes per second")]
+    pub tick_rate: Option<f64>,
+    #[arg(long, value_name = "HZ", help = "Redraws per second")]
+    pub frame_rate: Option<f64>,
+    #[arg(
+        long,
+        value_name = "NAME=PSK",
+        help = "Decrypt packets on another channel with its base64 key; repeat for more"
+    )]
+    pub channel_key: Vec<String>,
     #[arg(short = 'm', long, help = "Show MQTT nodes in output?", action)]
     pub show_mqtt: bool,
+    #[arg(long, help = "Ring the terminal bell when a detection sensor goes off", action)]
+    pub detection_alert: bool,
+    #[arg(long, help = "Record every packet from the radio to a capture file")]
+    pub record: Option<String>,
+    #[arg(long, help = "Replay a capture file instead of connecting to a radio")]
+    pub replay: Option<String>,
+    #[arg(
+        long,
+        help = "Playback speed multiplier for --replay (0 plays as fast as possible)",
+        default_value_t = 1.0
+    )]
+    pub replay_speed: f64,
+    #[arg(long, help = "Connect to a built-in simulated radio instead of real hardware", action)]
+    pub simulate: bool,
+    #[arg(
+        long,
+        value_name = "SOCKET",
+        help = "Run headless, keeping the radio connection up and serving it on a unix socket"
+    )]
+    pub daemon: Option<String>,
+    #[arg(
+        long,
+        value_name = "SOCKET",
+        help = "Attach to a running meshtui daemon instead of connecting to a radio"
+    )]
+    pub attach: Option<String>,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        num_args = 0..=1,
+        default_missing_value = "127.0.0.1:4404",
+        help = "Serve nodes, messages and config as JSON over HTTP (defaults to 127.0.0.1:4404)"
+    )]
+    pub http: Option<String>,
+    #[arg(
+        long,
+        value_name = "NODES",
+        help = "Time packet processing against a synthetic mesh of this many nodes, then exit"
+    )]
+    pub benchmark: Option<usize>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Send a text message and exit")]
+    Send {
+        #[arg(long, help = "The channel index to send on", default_value_t = 0)]
+        channel: u32,
+        #[arg(
+            long,
+            help = "The node id to send to, like !a1b2c3d4 (defaults to broadcast)"
+        )]
+        dest: Option<String>,
+        #[arg(help = "The message to send")]
+        text: String,
+    },
+    #[command(about = "Print the node database and exit")]
+    Nodes {
+        #[arg(long, help = "Print JSON instead of a table", action)]
+        json: bool,
+    },
+    #[command(about = "Print the channel list and exit")]
+    Channels {
+        #[arg(long, help = "Print JSON instead of a table", action)]
+        json: bool,
+    },
+    #[command(about = "Work with the device config")]
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    #[command(about = "Traceroute to a node, print the route and exit")]
+    Traceroute {
+        #[arg(help = "The node id to trace, like !a1b2c3d4")]
+        dest: String,
+    },
+    #[command(about = "Print packets from the mesh as they arrive")]
+    Listen {
+        #[arg(long, help = "Print one JSON object per line", action)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    #[command(about = "Print the whole device and module config")]
+    Dump,
+    #[command(about = "Save the config as YAML, in the Python CLI's --export-config layout")]
+    Export {
+        #[arg(help = "The file to write (defaults to stdout)")]
+        file: Option<String>,
+    },
+    #[command(about = "Print what applying a saved config would change, without changing it")]
+    Diff {
+        #[arg(help = "The YAML file to compare with the radio")]
+        file: String,
+    },
+    #[command(about = "Print the changes in a saved config, and apply them if --yes is given")]
+    Import {
+        #[arg(help = "The YAML file to apply")]
+        file: String,
+        #[arg(long, help = "Apply the changes instead of only printing them", action)]
+        yes: bool,
+    },
 }
@@ -0,0 +1,392 @@
+//! One-shot subcommands for scripting: connect, do one thing, print the result and exit.
+//!
+//! These run the same `meshtastic_loop` and `process_packet` as the TUI, just without a `Tui`
+//! on top.  Results go to stdout and logging goes to stderr, so output can be piped.
+use crate::admin;
+use crate::app::{Connection, RadioId};
+use crate::clap::{Command, ConfigCommand};
+use crate::config_backup;
+use crate::consts;
+use crate::crypto;
+use crate::ipc::IPCMessage;
+use crate::json::{channel_json, node_json, packet_json, payload_json};
+use crate::meshtastic_interaction::meshtastic_loop;
+use crate::node_store::NodeStore;
+use crate::packet_handler::{process_packet, PacketResponse};
+use crate::util::{self, parse_node_id};
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use meshtastic::protobufs::*;
+use serde_json::Value;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
+
+/// Commands only talk to one radio, which is also the active one.
+const COMMAND_RADIO: RadioId = 0;
+
+/// A radio connection with nobody watching it but us.
+struct Session {
+    node_list: NodeStore,
+    my_node_num: u32,
+    rx: mpsc::Receiver<IPCMessage>,
+    join_handle: JoinHandle<Result<()>>,
+}
+
+impl Session {
+    /// Connects and waits until the radio has sent us its whole config.
+    async fn connect(connection: Connection) -> Result<Self> {
+        let (fromradio_thread_tx, fromradio_thread_rx) =
+            mpsc::channel::<IPCMessage>(consts::MPSC_BUFFER_SIZE);
+        let (toradio_thread_tx, toradio_thread_rx) =
+            mpsc::channel::<IPCMessage>(consts::MPSC_BUFFER_SIZE);
+        {
+            let mut trm = crate::TO_RADIO_MPSC.write().await;
+            trm.insert(COMMAND_RADIO, toradio_thread_tx);
+        }
+        let join_handle: JoinHandle<Result<()>> = tokio::task::spawn(async move {
+            meshtastic_loop(
+                COMMAND_RADIO,
+                connection,
+                fromradio_thread_tx,
+                toradio_thread_rx,
+            )
+            .await
+        });
+        let mut session = Session {
+            node_list: NodeStore::default(),
+            my_node_num: 0,
+            rx: fromradio_thread_rx,
+            join_handle,
+        };
+        let configured = timeout(
+            Duration::from_secs(consts::CLI_CONFIG_TIMEOUT_SECS),
+            async {
+                loop {
+                    let (fr, _) = session.next().await?;
+                    if let Some(from_radio::PayloadVariant::ConfigCompleteId(_)) =
+                        fr.payload_variant
+                    {
+                        return Ok::<(), anyhow::Error>(());
+                    }
+                }
+            },
+        )
+        .await;
+        match configured {
+            Ok(result) => result?,
+            Err(_) => bail!(
+                "The radio didn't finish sending its config within {}s.",
+                consts::CLI_CONFIG_TIMEOUT_SECS
+            ),
+        }
+        Ok(session)
+    }
+
+    /// The next frame from the radio, after `process_packet` has had its way with it.
+    async fn next(&mut self) -> Result<(FromRadio, Option<PacketResponse>)> {
+        loop {
+            match self.rx.recv().await {
+                Some(IPCMessage::FromRadio(mut fr)) => {
+                    crypto::decrypt_frame(COMMAND_RADIO, &mut fr).await;
+                    let response = process_packet(
+                        COMMAND_RADIO,
+                        IPCMessage::FromRadio(fr.clone()),
+                        &self.node_list,
+                    )
+                    .await;
+                    if let Some(PacketResponse::OurAddress(num)) = response {
+                        self.my_node_num = num;
+                    }
+                    return Ok((fr, response));
+                }
+                Some(_) => {}
+                None => {
+                    // the radio task hung up on us, so find out why.
+                    match (&mut self.join_handle).await {
+                        Ok(Ok(())) => bail!("The radio connection closed."),
+                        Ok(Err(e)) => bail!(e),
+                        Err(e) => bail!(e),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keeps processing packets until `done` is happy or `secs` run out.  Returns false on timeout.
+    async fn wait_for<F>(&mut self, secs: u64, mut done: F) -> Result<bool>
+    where
+        F: FnMut(&Session, &FromRadio, &Option<PacketResponse>) -> bool,
+    {
+        let waited = timeout(Duration::from_secs(secs), async {
+            loop {
+                let (fr, response) = self.next().await?;
+                if done(self, &fr, &response) {
+                    return Ok::<(), anyhow::Error>(());
+                }
+            }
+        })
+        .await;
+        match waited {
+            Ok(result) => result.map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.join_handle.abort();
+    }
+}
+
+pub(crate) async fn run_command(connection: Connection, command: Command) -> Result<()> {
+    let mut session = Session::connect(connection).await?;
+    match command {
+        Command::Send {
+            channel,
+            dest,
+            text,
+        } => send(&mut session, channel, dest, text).await,
+        Command::Nodes { json } => {
+            print_nodes(&session.node_list, json);
+            Ok(())
+        }
+        Command::Channels { json } => {
+            print_channels(json).await;
+            Ok(())
+        }
+        Command::Config { action } => match action {
+            ConfigCommand::Dump => {
+                let devcfg = util::device_config().await;
+                println!("{:#?}", devcfg.unwrap_or_default());
+                Ok(())
+            }
+            ConfigCommand::Export { file } => export_config(&session, file).await,
+            ConfigCommand::Diff { file } => diff_config(&session, &file).await.map(|_| ()),
+            ConfigCommand::Import { file, yes } => import_config(&mut session, &file, yes).await,
+        },
+        Command::Traceroute { dest } => traceroute(&mut session, dest).await,
+        Command::Listen { json } => listen(&mut session, json).await,
+    }
+}
+
+async fn send(
+    session: &mut Session,
+    channel: u32,
+    dest: Option<String>,
+    text: String,
+) -> Result<()> {
+    let message = util::text_envelope(text, channel, dest.as_deref())?;
+    util::send_to_radio(IPCMessage::SendMessage(message)).await?;
+    // hanging up before the radio has queued the packet would lose it.
+    let queued = session
+        .wait_for(consts::CLI_SEND_TIMEOUT_SECS, |_, _, response| {
+            matches!(response, Some(PacketResponse::QueueStatus(_)))
+        })
+        .await?;
+    if !queued {
+        bail!("The radio never confirmed it queued the message.");
+    }
+    Ok(())
+}
+
+/// Our own owner and position, which live in the node list rather than the config.
+fn owner_and_position(session: &Session) -> (User, Option<Position>) {
+    session
+        .node_list
+        .with_node(session.my_node_num, |cn| {
+            (
+                cn.node_info.user.clone().unwrap_or_default(),
+                cn.node_info.position.clone(),
+            )
+        })
+        .unwrap_or_default()
+}
+
+async fn export_config(session: &Session, file: Option<String>) -> Result<()> {
+    let devcfg = util::device_config().await.unwrap_or_default();
+    let (owner, position) = owner_and_position(session);
+    let yaml = config_backup::export(&devcfg, &owner, position.as_ref())?;
+    match file {
+        Some(path) => {
+            std::fs::write(&path, yaml)?;
+            eprintln!("Saved the config to {path}");
+        }
+        None => print!("{yaml}"),
+    }
+    Ok(())
+}
+
+/// Prints what `file` would change and hands back the parsed file.
+async fn diff_config(session: &Session, file: &str) -> Result<Value> {
+    let contents = std::fs::read_to_string(file)?;
+    let parsed = config_backup::parse(&contents)?;
+    let devcfg = util::device_config().await.unwrap_or_default();
+    let (owner, position) = owner_and_position(session);
+    let changes = config_backup::diff(&devcfg, &owner, position.as_ref(), &parsed);
+    if changes.is_empty() {
+        println!("The radio already matches {file}.");
+    }
+    for change in changes.iter() {
+        println!("{change}");
+    }
+    Ok(parsed)
+}
+
+async fn import_config(session: &mut Session, file: &str, yes: bool) -> Result<()> {
+    let parsed = diff_config(session, file).await?;
+    let devcfg = util::device_config().await.unwrap_or_default();
+    let (owner, _) = owner_and_position(session);
+    let messages = config_backup::changes(&devcfg, &owner, &parsed)?;
+    if !yes {
+        eprintln!("Nothing has been changed.  Run this again with --yes to apply it.");
+        return Ok(());
+    }
+    for message in messages {
+        admin::send(None, session.my_node_num, message).await?;
+        let queued = session
+            .wait_for(consts::CLI_SEND_TIMEOUT_SECS, |_, _, response| {
+                matches!(response, Some(PacketResponse::QueueStatus(_)))
+            })
+            .await?;
+        if !queued {
+            bail!("The radio stopped taking config changes partway through.");
+        }
+    }
+    eprintln!("Applied {file}; the radio will reboot if any of it needs one.");
+    Ok(())
+}
+
+async fn traceroute(session: &mut Session, dest: String) -> Result<()> {
+    let target = parse_node_id(&dest)?;
+    util::send_to_radio(IPCMessage::ToRadio(util::traceroute_request(target))).await?;
+    let answered = session
+        .wait_for(consts::CLI_TRACEROUTE_TIMEOUT_SECS, |s, _, _| {
+            s.node_list
+                .with_node(target, |cn| cn.route_list.contains_key(&s.my_node_num))
+                .unwrap_or(false)
+        })
+        .await?;
+    if !answered {
+        bail!(
+            "No traceroute reply from !{:08x} within {}s.",
+            target,
+            consts::CLI_TRACEROUTE_TIMEOUT_SECS
+        );
+    }
+    let route = session
+        .node_list
+        .with_node(target, |cn| {
+            cn.route_list.get(&session.my_node_num).cloned()
+        })
+        .flatten()
+        .unwrap_or_default();
+    let hops = std::iter::once(session.my_node_num)
+        .chain(route)
+        .chain(std::iter::once(target))
+        .map(|num| format!("!{:08x}", num))
+        .join(" -> ");
+    println!("{hops}");
+    Ok(())
+}
+
+async fn listen(session: &mut Session, json: bool) -> Result<()> {
+    loop {
+        let (fr, _) = session.next().await?;
+        let packet = match fr.payload_variant {
+            Some(from_radio::PayloadVariant::Packet(mp)) => mp,
+            _ => continue,
+        };
+        let data = match &packet.payload_variant {
+            Some(mesh_packet::PayloadVariant::Decoded(de)) => de.clone(),
+            _ => continue,
+        };
+        if json {
+            println!("{}", packet_json(&packet, &data));
+        } else {
+            println!(
+                "{} !{:08x} -> !{:08x} ch{} {}: {}",
+                format_time(packet.rx_time as u64),
+                packet.from,
+                packet.to,
+                packet.channel,
+                data.portnum().as_str_name(),
+                payload_summary(&data)
+            );
+        }
+    }
+}
+
+fn format_time(secs: u64) -> String {
+    match OffsetDateTime::from_unix_timestamp(secs as i64) {
+        Ok(dt) if secs > 0 => dt.format(consts::DATE_FORMAT).unwrap_or_default(),
+        _ => "-".to_string(),
+    }
+}
+
+fn print_nodes(node_list: &NodeStore, json: bool) {
+    let nodes = node_list.read();
+    let sorted = nodes
+        .values()
+        .sorted_by(|a, b| b.last_seen.cmp(&a.last_seen));
+    if json {
+        let all: Vec<Value> = sorted.map(node_json).collect();
+        println!("{}", Value::Array(all));
+        return;
+    }
+    println!(
+        "{:<10} {:<5} {:<36} {:>4} {:>7} {}",
+        "ID", "SHORT", "LONG", "HOPS", "SNR", "LAST HEARD"
+    );
+    for cn in sorted {
+        let user = cn.node_info.user.clone().unwrap_or_default();
+        println!(
+            "{:<10} {:<5} {:<36} {:>4} {:>7.2} {}",
+            format!("!{:08x}", cn.id),
+            user.short_name,
+            user.long_name,
+            cn.node_info.hops_away,
+            cn.last_snr,
+            format_time(cn.node_info.last_heard as u64)
+        );
+    }
+}
+
+async fn print_channels(json: bool) {
+    let (channels, lora): (Vec<Channel>, _) = match util::device_config().await {
+        Some(devcfg) => (
+            devcfg
+                .channels
+                .into_values()
+                .sorted_by_key(|c| c.index)
+                .collect(),
+            devcfg.lora,
+        ),
+        None => (vec![], Default::default()),
+    };
+    if json {
+        let all: Vec<Value> = channels.iter().map(channel_json).collect();
+        println!("{}", Value::Array(all));
+        return;
+    }
+    for c in channels.iter() {
+        let settings = c.settings.clone().unwrap_or_default();
+        println!(
+            "{:02} {:<10} {:<20} up:{} down:{}",
+            c.index,
+            c.role().as_str_name(),
+            util::channel_name(&lora, &settings),
+            settings.uplink_enabled,
+            settings.downlink_enabled
+        );
+    }
+}
+
+fn payload_summary(data: &Data) -> String {
+    match data.portnum() {
+        PortNum::TextMessageApp => String::from_utf8_lossy(&data.payload).to_string(),
+        _ => payload_json(data).to_string(),
+    }
+}
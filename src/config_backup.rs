@@ -0,0 +1,593 @@
+//! Saving a node's config to a file, comparing a file with a node, and applying one to it.
+//!
+//! Files use the layout of the Python CLI's `--export-config`, so they go back and forth with
+//! `meshtastic --configure`: `owner`, `owner_short`, `channel_url`, `location`, and `config` and
+//! `module_config` with a map per section.  Like the CLI we leave out fields at their defaults
+//! and write enums by name, and when applying a file only the fields it has are changed.
+//! Either snake_case or camelCase keys are fine coming in.
+use crate::app::DeviceConfiguration;
+use crate::consts;
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::{URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+use meshtastic::protobufs::config::{
+    bluetooth_config, device_config, display_config, lo_ra_config, network_config, position_config,
+};
+use meshtastic::protobufs::module_config::{audio_config, canned_message_config, serial_config};
+use meshtastic::protobufs::{
+    admin_message, channel, config, module_config, Channel, ChannelSet, Position, User,
+};
+use meshtastic::Message;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+const FILE_HEADER: &str = "# start of Meshtastic configure yaml\n";
+const CHANNEL_URL_PREFIX: &str = "https://meshtastic.org/e/#";
+
+type EnumNames = (fn(i32) -> Option<&'static str>, fn(&str) -> Option<i32>);
+
+macro_rules! enum_names {
+    ($e:ty) => {
+        (
+            (|i: i32| <$e>::try_from(i).ok().map(|e| e.as_str_name()))
+                as fn(i32) -> Option<&'static str>,
+            (|s: &str| <$e>::from_str_name(s).map(|e| e as i32)) as fn(&str) -> Option<i32>,
+        )
+    };
+}
+
+/// The fields that hold enums, which go in files by name rather than number.
+fn enum_field(section: &str, field: &str) -> Option<EnumNames> {
+    Some(match (section, field) {
+        ("device", "role") => enum_names!(device_config::Role),
+        ("device", "rebroadcast_mode") => enum_names!(device_config::RebroadcastMode),
+        ("position", "gps_mode") => enum_names!(position_config::GpsMode),
+        ("network", "address_mode") => enum_names!(network_config::AddressMode),
+        ("display", "gps_format") => enum_names!(display_config::GpsCoordinateFormat),
+        ("display", "units") => enum_names!(display_config::DisplayUnits),
+        ("display", "oled") => enum_names!(display_config::OledType),
+        ("display", "displaymode") => enum_names!(display_config::DisplayMode),
+        ("lora", "region") => enum_names!(lo_ra_config::RegionCode),
+        ("lora", "modem_preset") => enum_names!(lo_ra_config::ModemPreset),
+        ("bluetooth", "mode") => enum_names!(bluetooth_config::PairingMode),
+        ("serial", "baud") => enum_names!(serial_config::SerialBaud),
+        ("serial", "mode") => enum_names!(serial_config::SerialMode),
+        ("audio", "bitrate") => enum_names!(audio_config::AudioBaud),
+        ("canned_message", f) if f.starts_with("inputbroker_event_") => {
+            enum_names!(canned_message_config::InputEventChar)
+        }
+        _ => return None,
+    })
+}
+
+fn snake(key: &str) -> String {
+    let mut out = String::new();
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            out.push('_');
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn to_json<T: Serialize>(section: &T) -> Value {
+    serde_json::to_value(section).unwrap_or_default()
+}
+
+fn config_sections(c: &DeviceConfiguration) -> Vec<(&'static str, Value)> {
+    vec![
+        ("device", to_json(&c.device)),
+        ("position", to_json(&c.position)),
+        ("power", to_json(&c.power)),
+        ("network", to_json(&c.network)),
+        ("display", to_json(&c.display)),
+        ("lora", to_json(&c.lora)),
+        ("bluetooth", to_json(&c.bluetooth)),
+    ]
+}
+
+fn module_sections(c: &DeviceConfiguration) -> Vec<(&'static str, Value)> {
+    vec![
+        ("mqtt", to_json(&c.mqtt)),
+        ("serial", to_json(&c.serial)),
+        ("external_notification", to_json(&c.external_notification)),
+        ("store_forward", to_json(&c.store_forward)),
+        ("range_test", to_json(&c.range_test)),
+        ("telemetry", to_json(&c.telemetry)),
+        ("canned_message", to_json(&c.canned_message)),
+        ("audio", to_json(&c.audio)),
+        ("remote_hardware", to_json(&c.remote_hardware)),
+        ("neighbor_info", to_json(&c.neighbor_info)),
+        ("ambient_lighting", to_json(&c.ambient_lighting)),
+        ("detection_sensor", to_json(&c.detection_sensor)),
+        ("paxcounter", to_json(&c.paxcounter)),
+    ]
+}
+
+fn config_variant(name: &str, value: Value) -> Result<config::PayloadVariant> {
+    use config::PayloadVariant::*;
+    Ok(match name {
+        "device" => Device(serde_json::from_value(value)?),
+        "position" => Position(serde_json::from_value(value)?),
+        "power" => Power(serde_json::from_value(value)?),
+        "network" => Network(serde_json::from_value(value)?),
+        "display" => Display(serde_json::from_value(value)?),
+        "lora" => Lora(serde_json::from_value(value)?),
+        "bluetooth" => Bluetooth(serde_json::from_value(value)?),
+        _ => bail!("There's no config section called {name}."),
+    })
+}
+
+fn module_variant(name: &str, value: Value) -> Result<module_config::PayloadVariant> {
+    use module_config::PayloadVariant::*;
+    Ok(match name {
+        "mqtt" => Mqtt(serde_json::from_value(value)?),
+        "serial" => Serial(serde_json::from_value(value)?),
+        "external_notification" => ExternalNotification(serde_json::from_value(value)?),
+        "store_forward" => StoreForward(serde_json::from_value(value)?),
+        "range_test" => RangeTest(serde_json::from_value(value)?),
+        "telemetry" => Telemetry(serde_json::from_value(value)?),
+        "canned_message" => CannedMessage(serde_json::from_value(value)?),
+        "audio" => Audio(serde_json::from_value(value)?),
+        "remote_hardware" => RemoteHardware(serde_json::from_value(value)?),
+        "neighbor_info" => NeighborInfo(serde_json::from_value(value)?),
+        "ambient_lighting" => AmbientLighting(serde_json::from_value(value)?),
+        "detection_sensor" => DetectionSensor(serde_json::from_value(value)?),
+        "paxcounter" => Paxcounter(serde_json::from_value(value)?),
+        _ => bail!("There's no module called {name}."),
+    })
+}
+
+/// A section the way files have it: snake_case keys and enums by name.
+fn file_form(section: &str, value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let key = snake(k);
+                    let v = match (enum_field(section, &key), v.as_i64()) {
+                        (Some((name, _)), Some(i)) => name(i as i32)
+                            .map(|n| Value::String(n.to_string()))
+                            .unwrap_or_else(|| v.clone()),
+                        _ => file_form(section, v),
+                    };
+                    (key, v)
+                })
+                .collect(),
+        ),
+        v => v.clone(),
+    }
+}
+
+/// Whether the Python CLI would leave a value out for being the default.
+fn is_default(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::Number(n) => n.as_f64() == Some(0.0),
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(_) => false,
+    }
+}
+
+fn strip_defaults(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !is_default(v))
+                .map(|(k, v)| (k, strip_defaults(v)))
+                .collect(),
+        ),
+        v => v,
+    }
+}
+
+/// The url the apps share channels with: every enabled channel's settings, plus the LoRa config.
+fn channel_url(devcfg: &DeviceConfiguration) -> String {
+    let mut channels: Vec<&Channel> = devcfg
+        .channels
+        .values()
+        .filter(|c| c.role() != channel::Role::Disabled)
+        .collect();
+    channels.sort_by_key(|c| c.index);
+    let settings = channels.iter().filter_map(|c| c.settings.clone()).collect();
+    let set = ChannelSet {
+        settings,
+        lora_config: Some(devcfg.lora.clone()),
+    };
+    format!(
+        "{CHANNEL_URL_PREFIX}{}",
+        URL_SAFE_NO_PAD.encode(set.encode_to_vec())
+    )
+}
+
+fn parse_channel_url(url: &str) -> Result<ChannelSet> {
+    let encoded = match url.rsplit_once('#') {
+        Some((_, e)) => e.trim_end_matches('='),
+        None => bail!("{url} doesn't look like a channel url."),
+    };
+    let bytes = match URL_SAFE_NO_PAD.decode(encoded) {
+        Ok(b) => b,
+        Err(_) => match URL_SAFE.decode(encoded) {
+            Ok(b) => b,
+            Err(e) => bail!("The channel url isn't valid base64: {e}"),
+        },
+    };
+    match ChannelSet::decode(bytes.as_slice()) {
+        Ok(set) => Ok(set),
+        Err(e) => bail!("The channel url doesn't hold a channel set: {e}"),
+    }
+}
+
+/// The whole config in file form, with the defaults left out.
+pub(crate) fn export_value(
+    devcfg: &DeviceConfiguration,
+    owner: &User,
+    position: Option<&Position>,
+) -> Value {
+    let sections = |list: Vec<(&str, Value)>| -> Map<String, Value> {
+        list.into_iter()
+            .map(|(name, v)| (name.to_string(), strip_defaults(file_form(name, &v))))
+            .collect()
+    };
+    let mut file = Map::new();
+    if !owner.long_name.is_empty() {
+        file.insert("owner".to_string(), json!(owner.long_name));
+    }
+    if !owner.short_name.is_empty() {
+        file.insert("owner_short".to_string(), json!(owner.short_name));
+    }
+    if !devcfg.channels.is_empty() {
+        file.insert("channel_url".to_string(), json!(channel_url(devcfg)));
+    }
+    if let Some(p) = position.filter(|p| p.latitude_i != 0 || p.longitude_i != 0) {
+        file.insert(
+            "location".to_string(),
+            json!({
+                "lat": p.latitude_i as f64 * consts::GPS_PRECISION_FACTOR as f64,
+                "lon": p.longitude_i as f64 * consts::GPS_PRECISION_FACTOR as f64,
+                "alt": p.altitude,
+            }),
+        );
+    }
+    file.insert(
+        "config".to_string(),
+        Value::Object(sections(config_sections(devcfg))),
+    );
+    file.insert(
+        "module_config".to_string(),
+        Value::Object(sections(module_sections(devcfg))),
+    );
+    Value::Object(file)
+}
+
+pub(crate) fn export(
+    devcfg: &DeviceConfiguration,
+    owner: &User,
+    position: Option<&Position>,
+) -> Result<String> {
+    let yaml = serde_yaml::to_string(&export_value(devcfg, owner, position))?;
+    Ok(format!("{FILE_HEADER}{yaml}"))
+}
+
+/// Reads a file written by `export` or the Python CLI, with every key made snake_case.
+pub(crate) fn parse(contents: &str) -> Result<Value> {
+    let yaml: serde_yaml::Value = match serde_yaml::from_str(contents) {
+        Ok(y) => y,
+        Err(e) => bail!("That isn't a config file: {e}"),
+    };
+    let value = serde_json::to_value(yaml)?;
+    if !value.is_object() {
+        bail!("That isn't a config file: there's no settings in it.");
+    }
+    Ok(snake_keys(value))
+}
+
+fn snake_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (snake(&k), snake_keys(v)))
+                .collect(),
+        ),
+        v => v,
+    }
+}
+
+fn section_of<'a>(file: &'a Value, group: &str, name: &str) -> Option<&'a Map<String, Value>> {
+    file.get(group)?.get(name)?.as_object()
+}
+
+/// Writes `from`'s fields over `into`, which has the protobuf crate's own keys.
+fn overlay(section: &str, into: &mut Value, from: &Map<String, Value>, path: &str) -> Result<()> {
+    let into = match into.as_object_mut() {
+        Some(o) => o,
+        None => bail!("{path} isn't a section."),
+    };
+    for (key, value) in from.iter() {
+        let crate_key = match into.keys().find(|k| snake(k) == *key) {
+            Some(k) => k.clone(),
+            None => bail!("{path} has no setting called {key}."),
+        };
+        let slot = &mut into[crate_key.as_str()];
+        match (value, enum_field(section, key)) {
+            // an unset sub-message takes the file's as it is.
+            (Value::Object(_), _) if slot.is_null() => *slot = value.clone(),
+            (Value::Object(inner), _) => overlay(section, slot, inner, &format!("{path}.{key}"))?,
+            (Value::String(name), Some((_, number))) => match number(name) {
+                Some(n) => *slot = json!(n),
+                None => bail!("{name} isn't one of the choices for {path}.{key}."),
+            },
+            (v, _) => *slot = v.clone(),
+        }
+    }
+    Ok(())
+}
+
+fn numbers_match(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => (x - y).abs() < 1e-6,
+        _ => a == b,
+    }
+}
+
+/// `path: old -> new` for every setting in `file` that isn't what `current` has.
+fn diff_into(lines: &mut Vec<String>, path: &str, current: &Value, file: &Value) {
+    match file {
+        Value::Object(map) => {
+            for (key, value) in map.iter() {
+                let current = current.get(key).unwrap_or(&Value::Null);
+                diff_into(lines, &format!("{path}.{key}"), current, value);
+            }
+        }
+        _ if numbers_match(current, file) => {}
+        // an unset value and a default one are the same thing to the device.
+        _ if current.is_null() && is_default(file) => {}
+        _ => lines.push(format!("{path}: {current} -> {file}")),
+    }
+}
+
+/// What applying `file` would change, one line per setting.
+pub(crate) fn diff(
+    devcfg: &DeviceConfiguration,
+    owner: &User,
+    position: Option<&Position>,
+    file: &Value,
+) -> Vec<String> {
+    let mut lines = vec![];
+    if let Some(name) = file.get("owner") {
+        diff_into(&mut lines, "owner", &json!(owner.long_name), name);
+    }
+    if let Some(name) = file.get("owner_short") {
+        diff_into(&mut lines, "owner_short", &json!(owner.short_name), name);
+    }
+    if let Some(url) = file.get("channel_url").and_then(|u| u.as_str()) {
+        let names = |set: &ChannelSet| {
+            set.settings
+                .iter()
+                .map(|s| s.name.clone())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let ours = parse_channel_url(&channel_url(devcfg)).unwrap_or_default();
+        match parse_channel_url(url) {
+            Ok(theirs) if theirs.settings != ours.settings => lines.push(format!(
+                "channels: [{}] -> [{}]",
+                names(&ours),
+                names(&theirs)
+            )),
+            Ok(_) => {}
+            Err(e) => lines.push(format!("channels: {e}")),
+        }
+    }
+    if let Some(location) = file.get("location") {
+        let current = export_value(devcfg, owner, position);
+        diff_into(
+            &mut lines,
+            "location",
+            current.get("location").unwrap_or(&Value::Null),
+            location,
+        );
+    }
+    let groups = [
+        ("config", config_sections(devcfg)),
+        ("module_config", module_sections(devcfg)),
+    ];
+    for (group, sections) in groups.iter() {
+        for (name, value) in sections.iter() {
+            if let Some(section) = section_of(file, group, name) {
+                diff_into(
+                    &mut lines,
+                    &format!("{group}.{name}"),
+                    &file_form(name, value),
+                    &file_form(name, &Value::Object(section.clone())),
+                );
+            }
+        }
+    }
+    lines
+}
+
+/// The admin messages that apply `file`, in one edit transaction.
+pub(crate) fn changes(
+    devcfg: &DeviceConfiguration,
+    owner: &User,
+    file: &Value,
+) -> Result<Vec<admin_message::PayloadVariant>> {
+    use admin_message::PayloadVariant::*;
+    let mut messages = vec![BeginEditSettings(true)];
+    let long_name = file.get("owner").and_then(|o| o.as_str());
+    let short_name = file.get("owner_short").and_then(|o| o.as_str());
+    if long_name.is_some() || short_name.is_some() {
+        messages.push(SetOwner(User {
+            long_name: long_name.unwrap_or(&owner.long_name).to_string(),
+            short_name: short_name.unwrap_or(&owner.short_name).to_string(),
+            is_licensed: owner.is_licensed,
+            ..Default::default()
+        }));
+    }
+    for (name, mut value) in config_sections(devcfg) {
+        if let Some(section) = section_of(file, "config", name) {
+            overlay(name, &mut value, section, &format!("config.{name}"))?;
+            messages.push(SetConfig(meshtastic::protobufs::Config {
+                payload_variant: Some(config_variant(name, value)?),
+            }));
+        }
+    }
+    for (name, mut value) in module_sections(devcfg) {
+        if let Some(section) = section_of(file, "module_config", name) {
+            overlay(name, &mut value, section, &format!("module_config.{name}"))?;
+            messages.push(SetModuleConfig(meshtastic::protobufs::ModuleConfig {
+                payload_variant: Some(module_variant(name, value)?),
+            }));
+        }
+    }
+    if let Some(url) = file.get("channel_url").and_then(|u| u.as_str()) {
+        let set = parse_channel_url(url)?;
+        if set.settings.len() > consts::MAX_CHANNEL_INDEX as usize + 1 {
+            bail!("The channel url has more channels than a radio can hold.");
+        }
+        // the url stands for the whole channel list, so anything past it gets turned off.
+        for index in 0..=consts::MAX_CHANNEL_INDEX as i32 {
+            let channel = match set.settings.get(index as usize) {
+                Some(settings) => Channel {
+                    index,
+                    settings: Some(settings.clone()),
+                    role: match index {
+                        0 => channel::Role::Primary as i32,
+                        _ => channel::Role::Secondary as i32,
+                    },
+                },
+                None => Channel {
+                    index,
+                    settings: None,
+                    role: channel::Role::Disabled as i32,
+                },
+            };
+            messages.push(SetChannel(channel));
+        }
+        if section_of(file, "config", "lora").is_none() {
+            if let Some(lora) = set.lora_config {
+                messages.push(SetConfig(meshtastic::protobufs::Config {
+                    payload_variant: Some(config::PayloadVariant::Lora(lora)),
+                }));
+            }
+        }
+    }
+    // a saved location only means something for a node that stays put.
+    let fixed = section_of(file, "config", "position")
+        .and_then(|p| p.get("fixed_position"))
+        .and_then(|f| f.as_bool())
+        .unwrap_or(false);
+    if let (true, Some(location)) = (fixed, file.get("location")) {
+        let degrees = |key: &str| location.get(key).and_then(|v| v.as_f64());
+        if let (Some(lat), Some(lon)) = (degrees("lat"), degrees("lon")) {
+            messages.push(SetFixedPosition(Position {
+                latitude_i: (lat * 1e7).round() as i32,
+                longitude_i: (lon * 1e7).round() as i32,
+                altitude: degrees("alt").unwrap_or_default() as i32,
+                ..Default::default()
+            }));
+        }
+    }
+    messages.push(CommitEditSettings(true));
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use admin_message::PayloadVariant::*;
+    use meshtastic::protobufs::ChannelSettings;
+
+    fn node() -> (DeviceConfiguration, User, Position) {
+        let mut devcfg = DeviceConfiguration::default();
+        devcfg.lora.use_preset = true;
+        devcfg.lora.set_region(lo_ra_config::RegionCode::Eu868);
+        devcfg.lora.hop_limit = 5;
+        devcfg.device.set_role(device_config::Role::Router);
+        devcfg.position.fixed_position = true;
+        devcfg.mqtt.enabled = true;
+        devcfg.file_channel(Channel {
+            index: 0,
+            settings: Some(ChannelSettings {
+                psk: vec![1],
+                ..Default::default()
+            }),
+            role: channel::Role::Primary as i32,
+        });
+        let owner = User {
+            long_name: "Hilltop relay".to_string(),
+            short_name: "HILL".to_string(),
+            ..Default::default()
+        };
+        let position = Position {
+            latitude_i: 515_000_000,
+            longitude_i: -1_250_000,
+            altitude: 120,
+            ..Default::default()
+        };
+        (devcfg, owner, position)
+    }
+
+    #[test]
+    fn an_export_matches_what_it_came_from() {
+        let (devcfg, owner, position) = node();
+        let exported = export(&devcfg, &owner, Some(&position)).unwrap();
+        assert!(exported.starts_with(FILE_HEADER));
+        let file = parse(&exported).unwrap();
+        assert_eq!(
+            diff(&devcfg, &owner, Some(&position), &file),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn applying_an_export_sets_it_all_in_one_transaction() {
+        let (devcfg, owner, position) = node();
+        let file = parse(&export(&devcfg, &owner, Some(&position)).unwrap()).unwrap();
+        let messages = changes(&DeviceConfiguration::default(), &User::default(), &file).unwrap();
+        assert_eq!(messages.first(), Some(&BeginEditSettings(true)));
+        assert_eq!(messages.last(), Some(&CommitEditSettings(true)));
+        assert!(messages.contains(&SetConfig(meshtastic::protobufs::Config {
+            payload_variant: Some(config::PayloadVariant::Lora(devcfg.lora.clone())),
+        })));
+        // the url stands for every channel slot, not just the one in use.
+        let channels = messages
+            .iter()
+            .filter(|m| matches!(m, SetChannel(_)))
+            .count();
+        assert_eq!(channels, consts::MAX_CHANNEL_INDEX as usize + 1);
+        assert!(messages.iter().any(|m| matches!(m, SetFixedPosition(_))));
+    }
+
+    #[test]
+    fn python_cli_files_diff_by_name() {
+        let (devcfg, owner, _) = node();
+        let file =
+            parse("config:\n  lora:\n    hopLimit: 3\n  device:\n    role: CLIENT\n").unwrap();
+        let mut lines = diff(&devcfg, &owner, None, &file);
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "config.device.role: \"ROUTER\" -> \"CLIENT\"",
+                "config.lora.hop_limit: 5 -> 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_settings_are_refused() {
+        let (devcfg, owner, _) = node();
+        let file = parse("config:\n  lora:\n    warpDrive: true\n").unwrap();
+        assert!(changes(&devcfg, &owner, &file).is_err());
+        let file = parse("config:\n  device:\n    role: CAPTAIN\n").unwrap();
+        assert!(changes(&devcfg, &owner, &file).is_err());
+        assert!(parse("just a string").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Spotting the config mistakes new members tend to make, so the config tabs can point at them.
+//!
+//! None of these stop the node working as configured; they're settings that usually don't do
+//! what whoever set them wanted, or that cost the rest of the mesh.
+use crate::airtime::{self, Modulation};
+use crate::app::DeviceConfiguration;
+use crate::{consts, util};
+use itertools::Itertools;
+use meshtastic::protobufs::config::device_config::Role;
+use meshtastic::protobufs::config::lo_ra_config::RegionCode;
+use meshtastic::protobufs::{channel, ChannelSettings};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Warning {
+    /// The fields it's about, as `(section, field)` with sections named as in config files.
+    pub places: Vec<(&'static str, &'static str)>,
+    pub message: String,
+}
+
+impl Warning {
+    fn new(places: &[(&'static str, &'static str)], message: String) -> Self {
+        Warning {
+            places: places.to_vec(),
+            message,
+        }
+    }
+
+    pub fn in_section(&self, section: &str) -> bool {
+        self.places.iter().any(|(s, _)| *s == section)
+    }
+
+    pub fn is_about(&self, section: &str, field: &str) -> bool {
+        self.places
+            .iter()
+            .any(|(s, f)| *s == section && *f == field)
+    }
+}
+
+/// The default key in any of its forms: `AQ==` and its bumped variants, or spelled out.
+fn is_default_key(psk: &[u8]) -> bool {
+    (psk.len() == 1 && psk[0] != 0) || psk == consts::DEFAULT_PSK
+}
+
+fn is_unencrypted(psk: &[u8]) -> bool {
+    psk.is_empty() || psk == [0]
+}
+
+/// Every enabled channel's settings, with unnamed ones going by the modem preset's name.
+fn enabled_channels(config: &DeviceConfiguration) -> Vec<(i32, String, ChannelSettings)> {
+    config
+        .channels
+        .values()
+        .filter(|c| c.role() != channel::Role::Disabled)
+        .sorted_by_key(|c| c.index)
+        .filter_map(|c| {
+            let settings = c.settings.clone()?;
+            Some((
+                c.index,
+                util::channel_name(&config.lora, &settings),
+                settings,
+            ))
+        })
+        .collect()
+}
+
+fn check_lora(config: &DeviceConfiguration, warnings: &mut Vec<Warning>) {
+    if config.lora.region() == RegionCode::Unset {
+        warnings.push(Warning::new(
+            &[("lora", "region")],
+            "The region is UNSET, so the radio won't transmit at all.".to_string(),
+        ));
+    }
+    if config.lora.hop_limit > consts::MAX_HOP_LIMIT {
+        warnings.push(Warning::new(
+            &[("lora", "hop_limit")],
+            format!(
+                "A hop limit of {} is more than the firmware allows; it'll use {}.",
+                config.lora.hop_limit,
+                consts::MAX_HOP_LIMIT
+            ),
+        ));
+    }
+}
+
+fn check_role(config: &DeviceConfiguration, warnings: &mut Vec<Warning>) {
+    let role = config.device.role();
+    let relays = matches!(role, Role::Router | Role::RouterClient | Role::Repeater);
+    if relays && config.power.is_power_saving {
+        warnings.push(Warning::new(
+            &[("device", "role"), ("power", "is_power_saving")],
+            format!(
+                "A {} sleeps through packets with power saving on, so it can't relay them.",
+                role.as_str_name()
+            ),
+        ));
+    }
+}
+
+fn check_position(config: &DeviceConfiguration, warnings: &mut Vec<Warning>) {
+    let interval = match config.position.position_broadcast_secs {
+        0 => consts::DEFAULT_POSITION_BROADCAST_SECS,
+        secs => secs,
+    };
+    let airtime_ms =
+        Modulation::from_config(&config.lora).time_on_air_ms(airtime::POSITION_PACKET_BYTES);
+    let share = airtime_ms / (interval as f64 * 1000.0);
+    if share > consts::POSITION_AIRTIME_SHARE_MAX {
+        warnings.push(Warning::new(
+            &[("position", "position_broadcast_secs")],
+            format!(
+                "A position every {interval}s keeps this preset on the air {:.1}% of the time \
+                 for positions alone; try {}s or more.",
+                share * 100.0,
+                (airtime_ms / 1000.0 / consts::POSITION_AIRTIME_SHARE_MAX).ceil()
+            ),
+        ));
+    }
+}
+
+fn check_channels(config: &DeviceConfiguration, warnings: &mut Vec<Warning>) {
+    let channels = enabled_channels(config);
+    if config.mqtt.enabled {
+        for (index, name, settings) in channels.iter().filter(|(_, _, s)| s.uplink_enabled) {
+            let problem = match &settings.psk {
+                psk if is_unencrypted(psk) => "isn't encrypted",
+                psk if is_default_key(psk) => "uses the default key",
+                _ => continue,
+            };
+            warnings.push(Warning::new(
+                &[("mqtt", "enabled")],
+                format!(
+                    "Channel {index} ({name}) uplinks to MQTT and {problem}, so anyone on \
+                     the broker can read it."
+                ),
+            ));
+        }
+    }
+    let duplicates = |key: fn(&(i32, String, ChannelSettings)) -> Option<Vec<u8>>| {
+        channels
+            .iter()
+            .filter_map(|c| Some((key(c)?, c.0)))
+            .into_group_map()
+            .into_values()
+            .filter(|indexes| indexes.len() > 1)
+            .map(|indexes| indexes.iter().map(|i| i.to_string()).join(", "))
+            .sorted()
+            .collect_vec()
+    };
+    for indexes in duplicates(|(_, name, _)| Some(name.to_lowercase().into_bytes())) {
+        warnings.push(Warning::new(
+            &[("channels", "name")],
+            format!("Channels {indexes} have the same name, so the mesh can't tell them apart."),
+        ));
+    }
+    // channels without encryption all share "no key", which isn't worth a warning.
+    let key = |(_, _, s): &(i32, String, ChannelSettings)| {
+        (!is_unencrypted(&s.psk)).then(|| s.psk.clone())
+    };
+    for indexes in duplicates(key) {
+        warnings.push(Warning::new(
+            &[("channels", "psk")],
+            format!("Channels {indexes} share a key, so either can read the other."),
+        ));
+    }
+}
+
+/// Everything that looks wrong with `config`.  A config that hasn't arrived yet has nothing wrong.
+pub(crate) fn check(config: &DeviceConfiguration) -> Vec<Warning> {
+    let mut warnings = vec![];
+    if config.last_update == 0 {
+        return warnings;
+    }
+    check_lora(config, &mut warnings);
+    check_role(config, &mut warnings);
+    check_position(config, &mut warnings);
+    check_channels(config, &mut warnings);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meshtastic::protobufs::config::lo_ra_config::ModemPreset;
+    use meshtastic::protobufs::Channel;
+
+    /// A config that has arrived, with its region set so that isn't all it warns about.
+    fn arrived() -> DeviceConfiguration {
+        let mut config = DeviceConfiguration {
+            last_update: 1,
+            ..Default::default()
+        };
+        config.lora.set_region(RegionCode::Us);
+        config.lora.use_preset = true;
+        config
+    }
+
+    fn add_channel(config: &mut DeviceConfiguration, index: i32, name: &str, psk: &[u8]) {
+        let mut c = Channel {
+            index,
+            settings: Some(ChannelSettings {
+                name: name.to_string(),
+                psk: psk.to_vec(),
+                uplink_enabled: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        c.set_role(match index {
+            0 => channel::Role::Primary,
+            _ => channel::Role::Secondary,
+        });
+        config.file_channel(c);
+    }
+
+    #[test]
+    fn nothing_until_the_config_arrives() {
+        assert!(check(&DeviceConfiguration::default()).is_empty());
+    }
+
+    #[test]
+    fn a_sensible_config_passes() {
+        let mut config = arrived();
+        add_channel(&mut config, 0, "", &[1]);
+        add_channel(&mut config, 1, "friends", &[7; 32]);
+        assert_eq!(check(&config), vec![]);
+    }
+
+    #[test]
+    fn unset_region() {
+        let mut config = arrived();
+        config.lora.set_region(RegionCode::Unset);
+        let warnings = check(&config);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].is_about("lora", "region"));
+    }
+
+    #[test]
+    fn hop_limit_over_the_maximum() {
+        let mut config = arrived();
+        config.lora.hop_limit = consts::MAX_HOP_LIMIT + 1;
+        assert!(check(&config)
+            .iter()
+            .any(|w| w.is_about("lora", "hop_limit")));
+        config.lora.hop_limit = consts::MAX_HOP_LIMIT;
+        assert!(check(&config).is_empty());
+    }
+
+    #[test]
+    fn power_saving_router() {
+        let mut config = arrived();
+        config.device.set_role(Role::Router);
+        config.power.is_power_saving = true;
+        let warnings = check(&config);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].is_about("device", "role"));
+        assert!(warnings[0].is_about("power", "is_power_saving"));
+        assert!(warnings[0].in_section("power"));
+    }
+
+    #[test]
+    fn frequent_positions_on_a_slow_preset() {
+        let mut config = arrived();
+        config.lora.set_modem_preset(ModemPreset::VeryLongSlow);
+        config.position.position_broadcast_secs = 60;
+        assert!(check(&config)
+            .iter()
+            .any(|w| w.is_about("position", "position_broadcast_secs")));
+    }
+
+    #[test]
+    fn mqtt_uplink_on_the_default_key() {
+        let mut config = arrived();
+        config.mqtt.enabled = true;
+        add_channel(&mut config, 0, "", &[1]);
+        let warnings = check(&config);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("LongFast"));
+        assert!(warnings[0].message.contains("default key"));
+    }
+
+    #[test]
+    fn duplicate_names_and_keys() {
+        let mut config = arrived();
+        // an unnamed channel goes by the preset's name, so this clashes with it.
+        add_channel(&mut config, 0, "", &[1]);
+        add_channel(&mut config, 1, "longfast", &[1]);
+        let warnings = check(&config);
+        assert!(warnings.iter().any(|w| w.is_about("channels", "name")));
+        assert!(warnings.iter().any(|w| w.is_about("channels", "psk")));
+    }
+
+    #[test]
+    fn unencrypted_channels_dont_share_a_key() {
+        let mut config = arrived();
+        add_channel(&mut config, 1, "open", &[]);
+        add_channel(&mut config, 2, "also open", &[0]);
+        assert!(check(&config).is_empty());
+    }
+}
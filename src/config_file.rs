@@ -0,0 +1,241 @@
+//! The optional config file, `$XDG_CONFIG_HOME/meshtui/config.toml` (which is usually
+//! `~/.config/meshtui/config.toml`).
+//!
+//! Everything in it is optional.  Anything left out keeps its built-in default, and command
+//! line flags win over whatever the file says.
+use crate::app::{Connection, MenuTabs, Preferences, Units};
+use crate::crypto::ChannelKey;
+use crate::mqtt::MqttSettings;
+use crate::{consts, theme, util};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use strum::IntoEnumIterator;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    pub show_mqtt: Option<bool>,
+    pub default_tab: Option<String>,
+    pub message_retention: Option<usize>,
+    pub timeseries_retention: Option<usize>,
+    pub tick_rate: Option<f64>,
+    pub frame_rate: Option<f64>,
+    pub units: Option<Units>,
+    pub theme: Option<String>,
+    pub detection_alert: Option<bool>,
+    /// Channel name to base64 psk.
+    pub channel_keys: BTreeMap<String, String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named way of reaching a radio: `host` (and optionally `port`), `serial`, or an `mqtt` url.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub serial: Option<String>,
+    pub mqtt: Option<String>,
+}
+
+impl Profile {
+    pub fn connection(&self) -> Result<Option<Connection>> {
+        if let Some(host) = &self.host {
+            return Ok(Some(Connection::TCP(
+                host.clone(),
+                self.port.unwrap_or(consts::DEFAULT_TCP_PORT),
+            )));
+        }
+        if let Some(url) = &self.mqtt {
+            return Ok(Some(Connection::Mqtt(MqttSettings::parse(url)?)));
+        }
+        Ok(self.serial.clone().map(Connection::Serial))
+    }
+}
+
+/// `$<var>/meshtui`, or `~/<fallback>/meshtui` when the variable isn't set.
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(fallback),
+    };
+    Some(base.join("meshtui"))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(xdg_dir("XDG_CONFIG_HOME", ".config")?.join("config.toml"))
+}
+
+fn recent_hosts_path() -> Option<PathBuf> {
+    Some(xdg_dir("XDG_STATE_HOME", ".local/state")?.join("recent_hosts"))
+}
+
+/// Hosts we've managed to connect to, most recent first.
+pub fn recent_hosts() -> Vec<(String, u16)> {
+    let contents = match recent_hosts_path().map(std::fs::read_to_string) {
+        Some(Ok(c)) => c,
+        _ => return vec![],
+    };
+    contents
+        .lines()
+        .filter_map(|line| util::parse_host(line).ok())
+        .collect()
+}
+
+pub fn remember_host(host: &str, port: u16) -> Result<()> {
+    let path = match recent_hosts_path() {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    let mut hosts = recent_hosts();
+    hosts.retain(|(h, p)| !(h == host && *p == port));
+    hosts.insert(0, (host.to_string(), port));
+    hosts.truncate(consts::MAX_RECENT_HOSTS);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let contents: String = hosts
+        .iter()
+        .map(|(h, p)| format!("{}\n", util::host_port(h, *p)))
+        .collect();
+    std::fs::write(&path, contents)?;
+    Ok(())
+}
+
+fn last_connected_path() -> Option<PathBuf> {
+    Some(xdg_dir("XDG_STATE_HOME", ".local/state")?.join("last_connected"))
+}
+
+/// When we were last connected to a radio, as unix seconds, or 0 if we don't know.
+pub fn last_connected() -> u64 {
+    match last_connected_path().map(std::fs::read_to_string) {
+        Some(Ok(c)) => c.trim().parse().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+pub fn remember_last_connected(secs: u64) -> Result<()> {
+    let path = match last_connected_path() {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, format!("{secs}\n"))?;
+    Ok(())
+}
+
+/// Reads the config file.  Not having one at the default location is fine; not having the one
+/// we were explicitly pointed at is not.
+pub fn load(path: Option<&str>) -> Result<ConfigFile> {
+    let (path, required) = match path {
+        Some(p) => (PathBuf::from(p), true),
+        None => match default_path() {
+            Some(p) => (p, false),
+            None => return Ok(ConfigFile::default()),
+        },
+    };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if !required && e.kind() == ErrorKind::NotFound => {
+            return Ok(ConfigFile::default());
+        }
+        Err(e) => bail!("Couldn't read config file {}: {e}", path.display()),
+    };
+    match toml::from_str(&contents) {
+        Ok(cfg) => Ok(cfg),
+        Err(e) => bail!("{} isn't a valid config file: {e}", path.display()),
+    }
+}
+
+pub fn parse_tab(name: &str) -> Result<MenuTabs> {
+    match MenuTabs::iter().find(|t| t.to_string().eq_ignore_ascii_case(name)) {
+        Some(tab) => Ok(tab),
+        None => bail!(
+            "There's no {name} tab, try one of: {}",
+            MenuTabs::iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+pub fn check_theme(name: &str) -> Result<()> {
+    if !theme::THEME_NAMES.contains(&name) {
+        bail!(
+            "There's no {name} theme, try one of: {}",
+            theme::THEME_NAMES.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Keeping nothing would throw every message or sample away as soon as it arrived.
+pub fn check_retention(name: &str, count: usize) -> Result<()> {
+    if count == 0 {
+        bail!("{name} has to be at least 1.");
+    }
+    Ok(())
+}
+
+pub fn check_rate(name: &str, rate: f64) -> Result<()> {
+    if rate <= 0.0 || !rate.is_finite() {
+        bail!("{name} has to be more than zero.");
+    }
+    Ok(())
+}
+
+impl ConfigFile {
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile),
+            None => bail!("There's no connection profile named {name} in the config file."),
+        }
+    }
+
+    /// Lays the file's settings over `prefs`.
+    pub fn apply(&self, prefs: &mut Preferences) -> Result<()> {
+        if let Some(show_mqtt) = self.show_mqtt {
+            prefs.show_mqtt = show_mqtt;
+        }
+        if let Some(tab) = &self.default_tab {
+            prefs.default_tab = parse_tab(tab)?;
+        }
+        if let Some(retention) = self.message_retention {
+            check_retention("message_retention", retention)?;
+            prefs.message_retention = retention;
+        }
+        if let Some(retention) = self.timeseries_retention {
+            check_retention("timeseries_retention", retention)?;
+            prefs.timeseries_retention = retention;
+        }
+        if let Some(rate) = self.tick_rate {
+            check_rate("tick_rate", rate)?;
+            prefs.tick_rate = rate;
+        }
+        if let Some(rate) = self.frame_rate {
+            check_rate("frame_rate", rate)?;
+            prefs.frame_rate = rate;
+        }
+        if let Some(units) = self.units {
+            prefs.units = units;
+        }
+        if let Some(name) = &self.theme {
+            check_theme(name)?;
+            prefs.theme = name.clone();
+        }
+        if let Some(alert) = self.detection_alert {
+            prefs.detection_alert = alert;
+        }
+        for (name, psk) in self.channel_keys.iter() {
+            prefs.channel_keys.push(ChannelKey::new(name, psk)?);
+        }
+        Ok(())
+    }
+}
@@ -8,8 +8,87 @@ pub const TICK_RATE: f64 = 4.0_f64;
 pub const FRAME_RATE: f64 = 2.0_f64;
 
 pub const MPSC_BUFFER_SIZE: usize = 100_usize;
+pub const PACKET_FEED_SIZE: usize = 1024_usize;
+pub const RECONNECT_BACKOFF_MIN_SECS: u64 = 1_u64;
+pub const RECONNECT_BACKOFF_MAX_SECS: u64 = 60_u64;
+pub const RECONNECT_BACKOFF_MAX_DOUBLINGS: u32 = 6_u32;
+/// How long the reason a radio went away stays in the status bar before the retry countdown.
+pub const DISCONNECTED_SHOWN_SECS: u64 = 3_u64;
 pub const GPS_PRECISION_FACTOR: f32 = 0.0000001_f32;
 pub const MAX_MSG_RETENTION: usize = 128_usize;
+pub const MAX_TIMESERIES_RETENTION: usize = 128_usize;
+pub const DEFAULT_TCP_PORT: u16 = 4403_u16;
+pub const MAX_RECENT_HOSTS: usize = 8_usize;
+pub const MQTT_DEFAULT_PORT: u16 = 1883_u16;
+pub const MQTT_DEFAULT_ROOT: &str = "msh";
+pub const MQTT_DEFAULT_CHANNEL: &str = "LongFast";
+pub const MQTT_KEEPALIVE_SECS: u64 = 30_u64;
+pub const MQTT_DEDUPE_WINDOW: usize = 256_usize;
+pub const MQTT_HOP_LIMIT: u32 = 3_u32;
+/// The well-known key a channel psk of `AQ==` stands for; `0x02` and up bump its last byte.
+pub const DEFAULT_PSK: [u8; 16] = [
+    0xd4, 0xf1, 0xbb, 0x3a, 0x20, 0x29, 0x07, 0x59, 0xf0, 0xbc, 0xff, 0xab, 0xcf, 0x4e, 0x69, 0x01,
+];
+pub const MAX_CHANNEL_INDEX: u32 = 7_u32;
+pub const MAX_HOP_LIMIT: u32 = 7_u32;
+/// What the firmware uses when the position broadcast interval is left at 0.
+pub const DEFAULT_POSITION_BROADCAST_SECS: u32 = 900_u32;
+/// More of the airtime than this on position reports alone gets a warning.
+pub const POSITION_AIRTIME_SHARE_MAX: f64 = 0.01_f64;
+/// What the firmware uses when the telemetry intervals are left at 0.
+pub const DEFAULT_TELEMETRY_INTERVAL_SECS: u32 = 1800_u32;
+pub const DEFAULT_NEIGHBORINFO_INTERVAL_SECS: u32 = 900_u32;
+/// Nodes send their node info this often whether asked or not.
+pub const NODEINFO_BROADCAST_SECS: u32 = 10800_u32;
+/// The EU's duty-cycle limits, as a share of the hour, and the parts of the band they cover.
+pub const EU_DUTY_CYCLE_LIMITS: [(f64, &str); 2] = [
+    (0.10_f64, "869.4-869.65MHz, where EU_868 sits by default"),
+    (0.01_f64, "most of the rest of 863-870MHz"),
+];
+/// Firmware before session passkeys only takes admin messages from other nodes on this channel.
+pub const ADMIN_CHANNEL_NAME: &str = "admin";
+/// `AdminMessage.session_passkey`, from the 2.5 protobufs.
+pub const SESSION_PASSKEY_FIELD: u64 = 101_u64;
+/// Firmware stops taking a passkey this long after handing it out.
+pub const SESSION_PASSKEY_LIFETIME_SECS: u64 = 300_u64;
+/// How far apart batches of admin messages go out to a node on the mesh, and to our own radio.
+pub const ADMIN_REMOTE_SPACING_SECS: u64 = 3_u64;
+pub const ADMIN_LOCAL_SPACING_MS: u64 = 250_u64;
+pub const OWNER_LONG_NAME_MAX: usize = 39_usize;
+pub const OWNER_SHORT_NAME_MAX: usize = 4_usize;
+/// The map picker opens this many degrees wide on a node we know the position of.
+pub const MAP_PICKER_NEAR_SPAN: f64 = 2.0_f64;
+/// About 100m across, which is as close as the map's coastlines are worth looking at.
+pub const MAP_PICKER_MIN_SPAN: f64 = 0.001_f64;
+/// Arrow presses to cross the map.
+pub const MAP_PICKER_STEPS: f64 = 40.0_f64;
+pub const WAYPOINT_NAME_MAX: usize = 30_usize;
+pub const WAYPOINT_DESCRIPTION_MAX: usize = 100_usize;
+pub const WAYPOINT_DEFAULT_HOURS: u32 = 24_u32;
+pub const LAST_CONNECTED_SAVE_SECS: u64 = 60_u64;
+/// How long a node waits before rebooting or shutting down when we ask, so it can ack first.
+pub const DEVICE_ACTION_DELAY_SECS: i32 = 5_i32;
+/// How long we give a radio that says it rebooted before we reconnect and fetch its config.
+pub const REBOOT_RECONNECT_DELAY_SECS: u64 = 3_u64;
+/// How far apart two copies of the same text from the same node can be heard and still count
+/// as one message, since a Store & Forward replay carries the router's receive time, not ours.
+pub const DUPLICATE_MESSAGE_WINDOW_SECS: u32 = 120_u32;
+pub const RANGE_TEST_DUPLICATE_WINDOW: usize = 8_usize;
+pub const CLI_CONFIG_TIMEOUT_SECS: u64 = 30_u64;
+pub const CLI_SEND_TIMEOUT_SECS: u64 = 10_u64;
+pub const CLI_TRACEROUTE_TIMEOUT_SECS: u64 = 60_u64;
+
+/// USB vendor ids, and product ids where the vendor makes other things too, that Meshtastic
+/// radios show up with: nRF52 and RP2040 boards, native ESP32-S3 USB, and the usb-serial chips
+/// on most ESP32 boards.
+pub const MESHTASTIC_USB_IDS: [(u16, Option<u16>); 6] = [
+    (0x239a, None),         // Adafruit bootloader nRF52 boards (RAK4631, T-Echo)
+    (0x2e8a, None),         // Raspberry Pi RP2040
+    (0x303a, None),         // Espressif native USB
+    (0x10c4, Some(0xea60)), // Silicon Labs CP210x
+    (0x1a86, Some(0x7523)), // WCH CH340
+    (0x1a86, Some(0x55d4)), // WCH CH9102
+];
 
 pub const NODE_HELP_TEXT: &str = r######"
 The node screen shows a list of nodes as reported by your device.  The list is constantly sorted by
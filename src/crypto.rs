@@ -0,0 +1,293 @@
+//! Decrypting packets that reach us still encrypted, and encrypting the ones we publish to MQTT.
+//!
+//! The firmware hands along packets for channels it has no key for as they were sent, and MQTT
+//! gateways and captures are full of them.  Meshtastic encrypts with AES-CTR (128 or 256 bit,
+//! depending on the key), using a nonce made of the packet id and the sender's node number.
+//! Encrypted packets carry an 8-bit hash of the channel name and key instead of a channel index,
+//! so we only try the keys whose hash matches.
+//!
+//! Keys come from the channels of every connected radio, from `[channel_keys]` in the config
+//! file and `--channel-key` on the command line, plus the public LongFast default.
+use crate::app::RadioId;
+use crate::{consts, util, DEVICE_CONFIGS, PREFERENCES};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use meshtastic::protobufs::{
+    channel, from_radio, mesh_packet, Data, FromRadio, MeshPacket, PortNum,
+};
+use meshtastic::Message;
+use std::fmt;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+#[derive(Clone)]
+pub struct ChannelKey {
+    pub name: String,
+    key: Vec<u8>,
+    hash: u8,
+    /// The radio and channel index this key came from, if it came from a radio.
+    slot: Option<(RadioId, u32)>,
+}
+
+// written by hand so keys stay out of the logs.
+impl fmt::Debug for ChannelKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChannelKey({}, hash {:#04x})", self.name, self.hash)
+    }
+}
+
+/// Turns a channel psk into the AES key it stands for, or None when the channel isn't encrypted.
+fn expand_psk(psk: &[u8]) -> Option<Vec<u8>> {
+    match psk.len() {
+        0 => None,
+        1 if psk[0] == 0 => None,
+        1 => {
+            let mut key = consts::DEFAULT_PSK.to_vec();
+            key[15] = key[15].wrapping_add(psk[0] - 1);
+            Some(key)
+        }
+        // the firmware zero-pads short keys up to the next AES key size.
+        n if n <= 16 => {
+            let mut key = psk.to_vec();
+            key.resize(16, 0);
+            Some(key)
+        }
+        n if n <= 32 => {
+            let mut key = psk.to_vec();
+            key.resize(32, 0);
+            Some(key)
+        }
+        _ => None,
+    }
+}
+
+fn xor_hash(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |acc, b| acc ^ b)
+}
+
+impl ChannelKey {
+    pub fn from_psk(name: &str, psk: &[u8]) -> Option<Self> {
+        let key = expand_psk(psk)?;
+        Some(ChannelKey {
+            name: name.to_string(),
+            hash: xor_hash(name.as_bytes()) ^ xor_hash(&key),
+            key,
+            slot: None,
+        })
+    }
+
+    /// A channel name and a base64 psk, as the apps show them (`AQ==` is the default key).
+    pub fn new(name: &str, psk: &str) -> Result<Self> {
+        let psk = match STANDARD.decode(psk.trim()) {
+            Ok(p) => p,
+            Err(e) => bail!("The key for channel {name} isn't valid base64: {e}"),
+        };
+        if psk.len() > 32 {
+            bail!("The key for channel {name} is longer than 32 bytes.");
+        }
+        match ChannelKey::from_psk(name, &psk) {
+            Some(key) => Ok(key),
+            None => bail!("Channel {name} has no key, so there's nothing to decrypt."),
+        }
+    }
+
+    /// `NAME=PSK`, as given to `--channel-key`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec.split_once('=') {
+            Some((name, psk)) => ChannelKey::new(name, psk),
+            None => bail!("{spec} should look like NAME=PSK, e.g. LongFast=AQ=="),
+        }
+    }
+
+    /// AES-CTR runs the same both ways, so this encrypts and decrypts `buf` in place.
+    fn apply_keystream(&self, packet: &MeshPacket, buf: &mut [u8]) -> Option<()> {
+        let mut nonce = [0_u8; 16];
+        nonce[0..8].copy_from_slice(&(packet.id as u64).to_le_bytes());
+        nonce[8..12].copy_from_slice(&packet.from.to_le_bytes());
+        match self.key.len() {
+            16 => Aes128Ctr::new_from_slices(&self.key, &nonce)
+                .ok()?
+                .apply_keystream(buf),
+            32 => Aes256Ctr::new_from_slices(&self.key, &nonce)
+                .ok()?
+                .apply_keystream(buf),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn decrypt(&self, packet: &MeshPacket, encrypted: &[u8]) -> Option<Data> {
+        let mut buf = encrypted.to_vec();
+        self.apply_keystream(packet, &mut buf)?;
+        // a wrong key that happens to share the hash gives garbage, which rarely decodes.
+        match Data::decode(buf.as_slice()) {
+            Ok(data) if data.portnum() != PortNum::UnknownApp => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Encrypts a decoded packet the way a radio would before it goes out, which means the
+    /// packet's id and sender have to be set already.  The channel becomes this key's hash.
+    pub fn encrypt(&self, packet: &mut MeshPacket) -> Result<()> {
+        let mut buf = match &packet.payload_variant {
+            Some(mesh_packet::PayloadVariant::Decoded(data)) => data.encode_to_vec(),
+            _ => bail!(
+                "Packet {} isn't decoded, so there's nothing to encrypt.",
+                packet.id
+            ),
+        };
+        if self.apply_keystream(packet, &mut buf).is_none() {
+            bail!("The {} key isn't one AES can use.", self.name);
+        }
+        packet.channel = self.hash as u32;
+        packet.payload_variant = Some(mesh_packet::PayloadVariant::Encrypted(buf));
+        Ok(())
+    }
+}
+
+/// The key we know for the channel called `name`, if any.
+pub async fn key_for(name: &str) -> Option<ChannelKey> {
+    known_keys().await.into_iter().find(|k| k.name == name)
+}
+
+async fn known_keys() -> Vec<ChannelKey> {
+    let mut keys = vec![];
+    for (radio, config) in DEVICE_CONFIGS.read().await.iter() {
+        for c in config.channels.values() {
+            if c.role() == channel::Role::Disabled {
+                continue;
+            }
+            if let Some(settings) = &c.settings {
+                let name = util::channel_name(&config.lora, settings);
+                if let Some(mut key) = ChannelKey::from_psk(&name, &settings.psk) {
+                    key.slot = Some((*radio, c.index as u32));
+                    keys.push(key);
+                }
+            }
+        }
+    }
+    keys.extend(PREFERENCES.read().await.channel_keys.iter().cloned());
+    keys.extend(ChannelKey::from_psk(consts::MQTT_DEFAULT_CHANNEL, &[1]));
+    keys
+}
+
+/// `decrypt` for a whole frame from `radio`.  This runs before anything else sees the frame, so
+/// the packet feed, the daemon's clients and the CLI get what we could read too.
+pub async fn decrypt_frame(radio: RadioId, fr: &mut FromRadio) {
+    if let Some(from_radio::PayloadVariant::Packet(packet)) = fr.payload_variant.as_mut() {
+        decrypt(radio, packet).await;
+    }
+}
+
+/// Swaps an encrypted payload for the decoded one if any key we know opens it.  When the key is
+/// one of `radio`'s own channels, the packet gets that channel's index in place of the hash.
+pub async fn decrypt(radio: RadioId, packet: &mut MeshPacket) {
+    let encrypted = match &packet.payload_variant {
+        Some(mesh_packet::PayloadVariant::Encrypted(bytes)) => bytes.clone(),
+        _ => return,
+    };
+    let keys = known_keys().await;
+    for key in keys.iter().filter(|k| k.hash as u32 == packet.channel) {
+        if let Some(data) = key.decrypt(packet, &encrypted) {
+            debug!(
+                "Decrypted packet {} from !{:08x} with the {} key",
+                packet.id, packet.from, key.name
+            );
+            if let Some((r, index)) = key.slot {
+                if r == radio {
+                    packet.channel = index;
+                }
+            }
+            packet.payload_variant = Some(mesh_packet::PayloadVariant::Decoded(data));
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_packet(text: &str) -> (MeshPacket, Data) {
+        let data = Data {
+            portnum: PortNum::TextMessageApp as i32,
+            payload: text.as_bytes().to_vec(),
+            ..Default::default()
+        };
+        let packet = MeshPacket {
+            id: 0x1234_5678,
+            from: 0xdead_beef,
+            payload_variant: Some(mesh_packet::PayloadVariant::Decoded(data.clone())),
+            ..Default::default()
+        };
+        (packet, data)
+    }
+
+    #[test]
+    fn short_psks_expand() {
+        assert_eq!(expand_psk(&[]), None);
+        assert_eq!(expand_psk(&[0]), None);
+        assert_eq!(expand_psk(&[1]), Some(consts::DEFAULT_PSK.to_vec()));
+        assert_eq!(expand_psk(&[2]).unwrap()[15], consts::DEFAULT_PSK[15] + 1);
+        assert_eq!(expand_psk(&[9; 5]).unwrap().len(), 16);
+        assert_eq!(expand_psk(&[9; 20]).unwrap().len(), 32);
+        assert_eq!(expand_psk(&[9; 33]), None);
+    }
+
+    #[test]
+    fn long_fast_hash() {
+        // what the firmware puts in `channel` for the public default channel.
+        let key = ChannelKey::from_psk("LongFast", &[1]).unwrap();
+        assert_eq!(key.hash, 0x08);
+        assert_eq!(ChannelKey::new("LongFast", "AQ==").unwrap().hash, 0x08);
+    }
+
+    #[test]
+    fn bad_keys_are_refused() {
+        assert!(ChannelKey::parse("LongFast").is_err());
+        assert!(ChannelKey::parse("LongFast=not base64!").is_err());
+        assert!(ChannelKey::parse("open=AA==").is_err());
+        let too_long = STANDARD.encode([1_u8; 33]);
+        assert!(ChannelKey::new("big", &too_long).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        for psk in [vec![1], vec![3; 16], vec![5; 32]] {
+            let key = ChannelKey::from_psk("friends", &psk).unwrap();
+            let (mut packet, data) = text_packet("hello mesh");
+            key.encrypt(&mut packet).unwrap();
+            assert_eq!(packet.channel, key.hash as u32);
+            let encrypted = match &packet.payload_variant {
+                Some(mesh_packet::PayloadVariant::Encrypted(bytes)) => bytes.clone(),
+                other => panic!("expected an encrypted payload, got {other:?}"),
+            };
+            assert_ne!(encrypted, data.encode_to_vec());
+            assert_eq!(key.decrypt(&packet, &encrypted), Some(data));
+        }
+    }
+
+    #[test]
+    fn the_nonce_depends_on_the_packet() {
+        let key = ChannelKey::from_psk("LongFast", &[1]).unwrap();
+        let (mut a, _) = text_packet("same text");
+        let (mut b, _) = text_packet("same text");
+        b.id += 1;
+        key.encrypt(&mut a).unwrap();
+        key.encrypt(&mut b).unwrap();
+        assert_ne!(a.payload_variant, b.payload_variant);
+    }
+
+    #[test]
+    fn only_decoded_packets_encrypt() {
+        let key = ChannelKey::from_psk("LongFast", &[1]).unwrap();
+        let mut packet = MeshPacket {
+            payload_variant: Some(mesh_packet::PayloadVariant::Encrypted(vec![1, 2, 3])),
+            ..Default::default()
+        };
+        assert!(key.encrypt(&mut packet).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Headless mode: keep the radio connection and our node/message state alive with no terminal,
+//! and let TUI instances attach to it over a Unix domain socket.
+//!
+//! The socket speaks the same language as a radio.  Each frame is a big-endian u32 length
+//! followed by a protobuf; the daemon sends `FromRadio` frames and clients send `ToRadio`
+//! frames.  When a client attaches it gets a config dump built from what the daemon already
+//! knows (our node, every node in the store, channels, configs and recent messages), then
+//! every packet from the radio as it arrives.  Whatever a client sends goes straight to the
+//! radio, so several operators can share one device.  With more than one radio, clients see
+//! the active radio's config and what every radio hears, and send through the active one.
+use crate::app::{recv_any, App, ConnectionState, DeviceConfiguration, RadioId, RadioLink};
+use crate::crypto;
+use crate::ipc::IPCMessage;
+use crate::node_store::NodeStore;
+use crate::packet_handler::MessageEnvelope;
+use crate::simulator::radio_frame;
+use crate::util::{self, set_connection_state};
+use crate::PACKET_FEED;
+use anyhow::{bail, Result};
+use futures::{SinkExt, StreamExt};
+use meshtastic::packet::PacketDestination;
+use meshtastic::protobufs::config::PayloadVariant as cpv;
+use meshtastic::protobufs::module_config::PayloadVariant as mpv;
+use meshtastic::protobufs::*;
+use meshtastic::{utils, Message};
+use std::collections::VecDeque;
+use std::os::unix::fs::FileTypeExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::Duration;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+type DaemonSocket = Framed<UnixStream, LengthDelimitedCodec>;
+
+/// Runs the radio and packet processing without a `Tui` until we're interrupted or terminated.
+pub(crate) async fn run_daemon(app: &mut App, socket_path: &str) -> Result<()> {
+    // systemd and friends stop services with SIGTERM, which should clean up like ctrl-c does.
+    let mut terminate = signal(SignalKind::terminate())?;
+    clear_stale_socket(socket_path)?;
+    let listener = UnixListener::bind(socket_path)?;
+    info!("meshtui daemon listening on {socket_path}");
+
+    let mut recent_messages: VecDeque<FromRadio> = VecDeque::new();
+    let mut links: Vec<RadioLink> = vec![];
+    app.tend_radios(&mut links).await;
+    let mut tick = tokio::time::interval(Duration::from_secs_f64(1.0 / app.user_prefs.tick_rate));
+
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {
+                app.tend_radios(&mut links).await;
+            }
+            (radio, mut packet) = recv_any(&mut links) => {
+                if let IPCMessage::FromRadio(fr) = &mut packet {
+                    // so messages on channels we have a key for count as messages too.
+                    crypto::decrypt_frame(radio, fr).await;
+                    if is_text_message(fr) {
+                        if recent_messages.len() >= app.user_prefs.message_retention {
+                            recent_messages.pop_front();
+                        }
+                        recent_messages.push_back(fr.clone());
+                    }
+                }
+                app.handle_packet(radio, packet).await;
+            }
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    // subscribe before taking the snapshot so nothing slips between the two.
+                    let updates = PACKET_FEED.subscribe();
+                    let snapshot = state_snapshot(
+                        app.nodes_tab.my_node_id,
+                        &app.nodes_tab.node_list,
+                        &recent_messages,
+                    )
+                    .await;
+                    info!("Client attached to daemon.");
+                    tokio::spawn(async move {
+                        match serve_client(stream, snapshot, updates).await {
+                            Ok(()) => info!("Client detached from daemon."),
+                            Err(e) => warn!("Client detached from daemon: {e}"),
+                        }
+                    });
+                }
+                Err(e) => {
+                    error!("Couldn't accept connection on daemon socket: {e}");
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutting down meshtui daemon.");
+                break;
+            }
+            _ = terminate.recv() => {
+                info!("Terminated, shutting down meshtui daemon.");
+                break;
+            }
+        }
+    }
+
+    for link in links.iter() {
+        link.join_handle.abort();
+    }
+    let _ = std::fs::remove_file(socket_path);
+    Ok(())
+}
+
+/// A daemon that didn't get to clean up leaves its socket behind, which would make `bind` fail.
+/// Only ever remove something that is actually a socket.
+fn clear_stale_socket(socket_path: &str) -> Result<()> {
+    match std::fs::symlink_metadata(socket_path) {
+        Ok(md) if md.file_type().is_socket() => {
+            std::fs::remove_file(socket_path)?;
+            Ok(())
+        }
+        Ok(_) => bail!("{socket_path} exists and is not a socket, refusing to replace it."),
+        Err(_) => Ok(()),
+    }
+}
+
+fn is_text_message(fr: &FromRadio) -> bool {
+    if let Some(from_radio::PayloadVariant::Packet(mp)) = &fr.payload_variant {
+        if let Some(mesh_packet::PayloadVariant::Decoded(de)) = &mp.payload_variant {
+            return de.portnum() == PortNum::TextMessageApp;
+        }
+    }
+    false
+}
+
+/// Everything a freshly attached client needs to look like it just configured a radio.
+async fn state_snapshot(
+    my_node_num: u32,
+    node_list: &NodeStore,
+    recent_messages: &VecDeque<FromRadio>,
+) -> Vec<FromRadio> {
+    let mut frames = vec![];
+    if my_node_num != 0 {
+        frames.push(radio_frame(from_radio::PayloadVariant::MyInfo(
+            MyNodeInfo {
+                my_node_num,
+                ..Default::default()
+            },
+        )));
+    }
+    {
+        let nodes = node_list.read();
+        for cn in nodes.values() {
+            frames.push(radio_frame(from_radio::PayloadVariant::NodeInfo(
+                cn.node_info.clone(),
+            )));
+        }
+    }
+    if let Some(devcfg) = util::device_config().await {
+        frames.extend(config_frames(devcfg));
+    }
+    frames.extend(recent_messages.iter().cloned());
+    frames.push(radio_frame(from_radio::PayloadVariant::ConfigCompleteId(
+        utils::generate_rand_id(),
+    )));
+    frames
+}
+
+fn config_frames(devcfg: DeviceConfiguration) -> Vec<FromRadio> {
+    let mut frames = vec![radio_frame(from_radio::PayloadVariant::Metadata(
+        devcfg.metadata,
+    ))];
+    let mut channels: Vec<Channel> = devcfg.channels.into_values().collect();
+    channels.sort_by_key(|c| c.index);
+    for channel in channels {
+        frames.push(radio_frame(from_radio::PayloadVariant::Channel(channel)));
+    }
+    let configs = [
+        cpv::Device(devcfg.device),
+        cpv::Position(devcfg.position),
+        cpv::Power(devcfg.power),
+        cpv::Network(devcfg.network),
+        cpv::Display(devcfg.display),
+        cpv::Lora(devcfg.lora),
+        cpv::Bluetooth(devcfg.bluetooth),
+    ];
+    for cfg in configs {
+        frames.push(radio_frame(from_radio::PayloadVariant::Config(Config {
+            payload_variant: Some(cfg),
+        })));
+    }
+    let modules = [
+        mpv::Mqtt(devcfg.mqtt),
+        mpv::Serial(devcfg.serial),
+        mpv::ExternalNotification(devcfg.external_notification),
+        mpv::StoreForward(devcfg.store_forward),
+        mpv::RangeTest(devcfg.range_test),
+        mpv::Telemetry(devcfg.telemetry),
+        mpv::CannedMessage(devcfg.canned_message),
+        mpv::Audio(devcfg.audio),
+        mpv::RemoteHardware(devcfg.remote_hardware),
+        mpv::NeighborInfo(devcfg.neighbor_info),
+        mpv::AmbientLighting(devcfg.ambient_lighting),
+        mpv::DetectionSensor(devcfg.detection_sensor),
+        mpv::Paxcounter(devcfg.paxcounter),
+    ];
+    for module in modules {
+        frames.push(radio_frame(from_radio::PayloadVariant::ModuleConfig(
+            ModuleConfig {
+                payload_variant: Some(module),
+            },
+        )));
+    }
+    frames
+}
+
+async fn serve_client(
+    stream: UnixStream,
+    snapshot: Vec<FromRadio>,
+    mut updates: broadcast::Receiver<FromRadio>,
+) -> Result<()> {
+    let mut socket: DaemonSocket = Framed::new(stream, LengthDelimitedCodec::new());
+    for fr in snapshot {
+        socket.send(fr.encode_to_vec().into()).await?;
+    }
+    loop {
+        tokio::select! {
+            incoming = socket.next() => match incoming {
+                Some(frame) => {
+                    let tr = ToRadio::decode(frame?.as_ref())?;
+                    util::send_to_radio(IPCMessage::ToRadio(tr)).await?;
+                }
+                None => return Ok(()),
+            },
+            update = updates.recv() => match update {
+                Ok(fr) => socket.send(fr.encode_to_vec().into()).await?,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Attached client fell behind and missed {missed} packets.");
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+/// The client half: talks to a daemon as if it were the radio.
+pub(crate) async fn attach_loop(
+    radio: RadioId,
+    path: String,
+    tx: Sender<IPCMessage>,
+    mut rx: Receiver<IPCMessage>,
+) -> Result<()> {
+    let stream = UnixStream::connect(&path).await?;
+    let mut socket: DaemonSocket = Framed::new(stream, LengthDelimitedCodec::new());
+    set_connection_state(radio, ConnectionState::Configuring).await;
+    info!("Attached to meshtui daemon at {path}");
+    loop {
+        tokio::select! {
+            incoming = socket.next() => {
+                let frame = match incoming {
+                    Some(frame) => frame?,
+                    None => {
+                        bail!("The daemon closed the connection.");
+                    }
+                };
+                let fr = FromRadio::decode(frame.as_ref())?;
+                if let Some(from_radio::PayloadVariant::ConfigCompleteId(_)) = fr.payload_variant {
+                    set_connection_state(radio, ConnectionState::Connected).await;
+                }
+                if let Err(e) = tx.send(IPCMessage::FromRadio(fr)).await {
+                    error!("Couldn't send FromRadio packet to mpsc: {e}");
+                }
+            }
+            Some(outbound) = rx.recv() => {
+                let tr = match outbound {
+                    IPCMessage::SendMessage(message) => text_message(message),
+                    IPCMessage::ToRadio(tr) => tr,
+                    _ => {
+                        warn!("Unknown ipc message sent into daemon client.");
+                        continue;
+                    }
+                };
+                socket.send(tr.encode_to_vec().into()).await?;
+            }
+        }
+    }
+}
+
+/// What `send_text` would have put on the wire, for when there's no stream api to call it on.
+pub(crate) fn text_message(message: MessageEnvelope) -> ToRadio {
+    let to = match message.destination {
+        // the packet router we hand to send_text doesn't know our node number either.
+        PacketDestination::Local => 0,
+        PacketDestination::Broadcast => u32::MAX,
+        PacketDestination::Node(id) => id.id(),
+    };
+    let mesh_packet = MeshPacket {
+        to,
+        channel: message.channel.channel(),
+        id: utils::generate_rand_id(),
+        want_ack: true,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+            portnum: PortNum::TextMessageApp as i32,
+            payload: message.message.into_bytes(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    ToRadio {
+        payload_variant: Some(to_radio::PayloadVariant::Packet(mesh_packet)),
+    }
+}
@@ -0,0 +1,213 @@
+//! A small local HTTP api, so dashboards and home automation can use meshtui as their gateway
+//! to the mesh.
+//!
+//! Everything is read from the same state the tabs use.  `GET /api/events` is a server-sent
+//! events stream with one `packet` event per decoded packet we receive, `GET /api/nodes/events`
+//! has a `node` event each time a node changes, and `GET /metrics` is for Prometheus.
+use crate::ipc::IPCMessage;
+use crate::json::{channel_json, config_json, message_json, node_json, packet_json};
+use crate::metrics;
+use crate::node_store::NodeStore;
+use crate::tabs::messages::MessageLog;
+use crate::util::{self, parse_node_id};
+use crate::PACKET_FEED;
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::Stream;
+use itertools::Itertools;
+use meshtastic::protobufs::{from_radio, mesh_packet, Channel};
+use serde::Deserialize;
+use serde_json::Value;
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+
+type ApiResult = std::result::Result<(StatusCode, Json<Value>), (StatusCode, String)>;
+
+#[derive(Clone)]
+struct ApiState {
+    node_list: NodeStore,
+    messages: MessageLog,
+}
+
+#[derive(Deserialize)]
+struct SendRequest {
+    text: String,
+    #[serde(default)]
+    channel: u32,
+    dest: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TracerouteRequest {
+    dest: String,
+}
+
+pub(crate) async fn serve(addr: String, node_list: NodeStore, messages: MessageLog) -> Result<()> {
+    let state = ApiState {
+        node_list,
+        messages,
+    };
+    let router = Router::new()
+        .route("/api/nodes", get(nodes))
+        .route("/api/nodes/events", get(node_events))
+        .route("/api/nodes/:id", get(node))
+        .route("/api/messages", get(messages).post(send_message))
+        .route("/api/channels", get(channels))
+        .route("/api/config", get(config))
+        .route("/api/traceroute", post(traceroute))
+        .route("/api/events", get(events))
+        .route("/metrics", get(prometheus))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("HTTP api listening on http://{addr}");
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+fn ok(body: Value) -> ApiResult {
+    Ok((StatusCode::OK, Json(body)))
+}
+
+fn bad_request(e: anyhow::Error) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, e.to_string())
+}
+
+async fn nodes(State(state): State<ApiState>) -> ApiResult {
+    let nodes = state.node_list.read();
+    let all = nodes
+        .values()
+        .sorted_by(|a, b| b.last_seen.cmp(&a.last_seen))
+        .map(node_json)
+        .collect();
+    ok(Value::Array(all))
+}
+
+async fn node(State(state): State<ApiState>, Path(id): Path<String>) -> ApiResult {
+    let num = parse_node_id(&id).map_err(bad_request)?;
+    match state.node_list.with_node(num, node_json) {
+        Some(body) => ok(body),
+        None => Err((StatusCode::NOT_FOUND, format!("No node {id}"))),
+    }
+}
+
+async fn messages(State(state): State<ApiState>) -> ApiResult {
+    let messages = state.messages.read().unwrap();
+    let all = messages
+        .iter()
+        .sorted_by_key(|m| m.timestamp)
+        .map(message_json)
+        .collect();
+    ok(Value::Array(all))
+}
+
+async fn channels() -> ApiResult {
+    let channels: Vec<Channel> = match util::device_config().await {
+        Some(devcfg) => devcfg
+            .channels
+            .into_values()
+            .sorted_by_key(|c| c.index)
+            .collect(),
+        None => vec![],
+    };
+    ok(Value::Array(channels.iter().map(channel_json).collect()))
+}
+
+async fn config() -> ApiResult {
+    match util::device_config().await {
+        Some(devcfg) => match config_json(&devcfg) {
+            Ok(body) => ok(body),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        },
+        None => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "We haven't received the config from the radio yet.".to_string(),
+        )),
+    }
+}
+
+async fn send_message(Json(request): Json<SendRequest>) -> ApiResult {
+    let message = util::text_envelope(request.text, request.channel, request.dest.as_deref())
+        .map_err(bad_request)?;
+    to_radio(IPCMessage::SendMessage(message)).await
+}
+
+async fn traceroute(Json(request): Json<TracerouteRequest>) -> ApiResult {
+    let target = parse_node_id(&request.dest).map_err(bad_request)?;
+    to_radio(IPCMessage::ToRadio(util::traceroute_request(target))).await
+}
+
+async fn to_radio(ipc: IPCMessage) -> ApiResult {
+    match util::send_to_radio(ipc).await {
+        Ok(()) => Ok((StatusCode::ACCEPTED, Json(Value::Null))),
+        Err(e) => Err((StatusCode::SERVICE_UNAVAILABLE, e.to_string())),
+    }
+}
+
+async fn prometheus(State(state): State<ApiState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&state.node_list),
+    )
+}
+
+async fn events() -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let stream = futures::stream::unfold(PACKET_FEED.subscribe(), |mut feed| async move {
+        loop {
+            match feed.recv().await {
+                Ok(fr) => {
+                    let packet = match fr.payload_variant {
+                        Some(from_radio::PayloadVariant::Packet(mp)) => mp,
+                        _ => continue,
+                    };
+                    let data = match &packet.payload_variant {
+                        Some(mesh_packet::PayloadVariant::Decoded(de)) => de.clone(),
+                        _ => continue,
+                    };
+                    let event = Event::default()
+                        .event("packet")
+                        .data(packet_json(&packet, &data).to_string());
+                    return Some((Ok(event), feed));
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("HTTP event stream fell behind and missed {missed} packets.");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn node_events(
+    State(state): State<ApiState>,
+) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let changes = state.node_list.subscribe();
+    let stream = futures::stream::unfold(
+        (changes, state.node_list),
+        |(mut changes, node_list)| async move {
+            loop {
+                match changes.recv().await {
+                    Ok(id) => {
+                        // it may have been forgotten since.
+                        let body = match node_list.with_node(id, node_json) {
+                            Some(body) => body,
+                            None => continue,
+                        };
+                        let event = Event::default().event("node").data(body.to_string());
+                        return Some((Ok(event), (changes, node_list)));
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("HTTP node stream fell behind and missed {missed} changes.");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
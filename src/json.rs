@@ -0,0 +1,193 @@
+//! JSON views of our state, shared by the one-shot commands and the HTTP api.
+//!
+//! These are hand-built rather than serialized straight from the protobufs so that ids come out
+//! the way people write them (`!a1b2c3d4`), positions come out in degrees, and enums by name.
+use crate::app::DeviceConfiguration;
+use crate::consts::GPS_PRECISION_FACTOR;
+use crate::packet_handler::MessageEnvelope;
+use crate::tabs::nodes::ComprehensiveNode;
+use anyhow::Result;
+use itertools::Itertools;
+use meshtastic::packet::PacketDestination;
+use meshtastic::protobufs::*;
+use meshtastic::Message;
+use serde_json::{json, Value};
+
+pub(crate) fn node_json(cn: &ComprehensiveNode) -> Value {
+    let user = cn.node_info.user.clone().unwrap_or_default();
+    let position = cn.node_info.position.clone().unwrap_or_default();
+    let metrics = cn.node_info.device_metrics.clone().unwrap_or_default();
+    json!({
+        "num": cn.id,
+        "id": format!("!{:08x}", cn.id),
+        "long_name": user.long_name,
+        "short_name": user.short_name,
+        "hw_model": user.hw_model().as_str_name(),
+        "hops_away": cn.node_info.hops_away,
+        "via_mqtt": cn.node_info.via_mqtt,
+        "last_heard": cn.node_info.last_heard,
+        "last_seen": cn.last_seen,
+        "snr": cn.last_snr,
+        "rssi": cn.last_rssi,
+        "latitude": position.latitude_i as f32 * GPS_PRECISION_FACTOR,
+        "longitude": position.longitude_i as f32 * GPS_PRECISION_FACTOR,
+        "altitude": position.altitude,
+        "battery_level": metrics.battery_level,
+        "voltage": metrics.voltage,
+        "channel_utilization": metrics.channel_utilization,
+        "air_util_tx": metrics.air_util_tx,
+        "neighbors": cn.neighbors.iter().map(|n| n.node_id).collect::<Vec<u32>>(),
+        // numbered from 1, like the radios on screen.
+        "heard_by": cn.heard_by.iter().map(|r| r + 1).collect::<Vec<usize>>(),
+        "routes": cn
+            .route_list
+            .iter()
+            .map(|(to, via)| json!({ "to": format!("!{:08x}", to), "via": via }))
+            .collect::<Vec<Value>>(),
+    })
+}
+
+pub(crate) fn channel_json(c: &Channel) -> Value {
+    let settings = c.settings.clone().unwrap_or_default();
+    json!({
+        "index": c.index,
+        "role": c.role().as_str_name(),
+        "name": settings.name,
+        "uplink_enabled": settings.uplink_enabled,
+        "downlink_enabled": settings.downlink_enabled,
+    })
+}
+
+/// The interesting parts of a payload we know how to decode, and the raw bytes otherwise.
+pub(crate) fn payload_json(data: &Data) -> Value {
+    match data.portnum() {
+        PortNum::TextMessageApp => json!({
+            "text": String::from_utf8_lossy(&data.payload),
+        }),
+        PortNum::PositionApp => match Position::decode(data.payload.as_slice()) {
+            Ok(p) => json!({
+                "latitude": p.latitude_i as f32 * GPS_PRECISION_FACTOR,
+                "longitude": p.longitude_i as f32 * GPS_PRECISION_FACTOR,
+                "altitude": p.altitude,
+                "time": p.time,
+            }),
+            Err(_) => raw_json(data),
+        },
+        PortNum::NodeinfoApp => match User::decode(data.payload.as_slice()) {
+            Ok(u) => json!({
+                "id": u.id,
+                "long_name": u.long_name,
+                "short_name": u.short_name,
+                "hw_model": u.hw_model().as_str_name(),
+            }),
+            Err(_) => raw_json(data),
+        },
+        PortNum::TelemetryApp => match Telemetry::decode(data.payload.as_slice()) {
+            Ok(t) => match t.variant {
+                Some(telemetry::Variant::DeviceMetrics(dm)) => json!({
+                    "battery_level": dm.battery_level,
+                    "voltage": dm.voltage,
+                    "channel_utilization": dm.channel_utilization,
+                    "air_util_tx": dm.air_util_tx,
+                    "uptime_seconds": dm.uptime_seconds,
+                }),
+                Some(telemetry::Variant::EnvironmentMetrics(env)) => json!({
+                    "temperature": env.temperature,
+                    "relative_humidity": env.relative_humidity,
+                    "barometric_pressure": env.barometric_pressure,
+                }),
+                _ => raw_json(data),
+            },
+            Err(_) => raw_json(data),
+        },
+        PortNum::TracerouteApp => match RouteDiscovery::decode(data.payload.as_slice()) {
+            Ok(rd) => json!({ "route": rd.route }),
+            Err(_) => raw_json(data),
+        },
+        PortNum::NeighborinfoApp => match NeighborInfo::decode(data.payload.as_slice()) {
+            Ok(ni) => json!({
+                "node_id": ni.node_id,
+                "neighbors": ni
+                    .neighbors
+                    .iter()
+                    .map(|n| json!({ "node_id": n.node_id, "snr": n.snr }))
+                    .collect::<Vec<Value>>(),
+            }),
+            Err(_) => raw_json(data),
+        },
+        _ => raw_json(data),
+    }
+}
+
+fn raw_json(data: &Data) -> Value {
+    json!({
+        "bytes": data.payload.iter().map(|b| format!("{:02x}", b)).join(""),
+    })
+}
+
+pub(crate) fn packet_json(packet: &MeshPacket, data: &Data) -> Value {
+    json!({
+        "id": packet.id,
+        "from": format!("!{:08x}", packet.from),
+        "to": format!("!{:08x}", packet.to),
+        "channel": packet.channel,
+        "rx_time": packet.rx_time,
+        "rx_snr": packet.rx_snr,
+        "rx_rssi": packet.rx_rssi,
+        "hop_limit": packet.hop_limit,
+        "hop_start": packet.hop_start,
+        "via_mqtt": packet.via_mqtt,
+        "portnum": data.portnum().as_str_name(),
+        "request_id": data.request_id,
+        "payload": payload_json(data),
+    })
+}
+
+pub(crate) fn message_json(message: &MessageEnvelope) -> Value {
+    let source = message.source.as_ref();
+    let to = match &message.destination {
+        PacketDestination::Local => "local".to_string(),
+        PacketDestination::Broadcast => "broadcast".to_string(),
+        PacketDestination::Node(id) => format!("!{:08x}", id.id()),
+    };
+    json!({
+        "timestamp": message.timestamp,
+        "from": source.map(|ni| format!("!{:08x}", ni.num)),
+        "from_name": source.and_then(|ni| ni.user.as_ref()).map(|u| u.long_name.clone()),
+        "to": to,
+        "channel": message.channel.channel(),
+        "text": message.message,
+        "rx_rssi": message.rx_rssi,
+        "rx_snr": message.rx_snr,
+    })
+}
+
+/// The config sections, as the device sent them.
+pub(crate) fn config_json(devcfg: &DeviceConfiguration) -> Result<Value> {
+    Ok(json!({
+        "device": serde_json::to_value(&devcfg.device)?,
+        "position": serde_json::to_value(&devcfg.position)?,
+        "power": serde_json::to_value(&devcfg.power)?,
+        "network": serde_json::to_value(&devcfg.network)?,
+        "display": serde_json::to_value(&devcfg.display)?,
+        "lora": serde_json::to_value(&devcfg.lora)?,
+        "bluetooth": serde_json::to_value(&devcfg.bluetooth)?,
+        "modules": {
+            "mqtt": serde_json::to_value(&devcfg.mqtt)?,
+            "serial": serde_json::to_value(&devcfg.serial)?,
+            "external_notification": serde_json::to_value(&devcfg.external_notification)?,
+            "store_forward": serde_json::to_value(&devcfg.store_forward)?,
+            "range_test": serde_json::to_value(&devcfg.range_test)?,
+            "telemetry": serde_json::to_value(&devcfg.telemetry)?,
+            "canned_message": serde_json::to_value(&devcfg.canned_message)?,
+            "audio": serde_json::to_value(&devcfg.audio)?,
+            "remote_hardware": serde_json::to_value(&devcfg.remote_hardware)?,
+            "neighbor_info": serde_json::to_value(&devcfg.neighbor_info)?,
+            "ambient_lighting": serde_json::to_value(&devcfg.ambient_lighting)?,
+            "detection_sensor": serde_json::to_value(&devcfg.detection_sensor)?,
+            "paxcounter": serde_json::to_value(&devcfg.paxcounter)?,
+        },
+        "firmware_version": devcfg.metadata.firmware_version,
+        "last_update": devcfg.last_update,
+    }))
+}
@@ -2,29 +2,55 @@ extern crate tokio;
 #[macro_use]
 extern crate tracing;
 
+mod admin;
+mod airtime;
 pub mod app;
+mod benchmark;
+mod capture;
 mod clap;
+mod commands;
+mod config_backup;
+mod config_check;
+mod config_file;
+mod crypto;
 pub mod consts;
+mod daemon;
+mod http_api;
 mod ipc;
+mod json;
 mod meshtastic_interaction;
+mod metrics;
+mod mqtt;
+mod node_store;
 mod packet_handler;
+mod picker;
+mod simulator;
+mod store_forward;
 mod tabs;
 mod theme;
 pub mod tui;
 mod util;
 
 use crate::app::Preferences;
-use crate::app::{Connection, DeviceConfiguration};
+use crate::app::{Connection, ConnectionState, DeviceConfiguration, RadioId};
 use crate::clap::CliArgs;
+use crate::config_file::ConfigFile;
+use crate::crypto::ChannelKey;
+use crate::mqtt::MqttSettings;
+use crate::store_forward::StoreForwardClient;
 use ::clap::Parser;
 use app::App;
 use lazy_static::lazy_static;
 
+use std::collections::HashMap;
 use std::process;
+use std::sync::atomic::{AtomicU32, AtomicUsize};
 
 use crate::ipc::IPCMessage;
 use ratatui::prelude::*;
 use tokio::io;
+use meshtastic::protobufs::FromRadio;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
 use tracing_subscriber::filter::EnvFilter;
@@ -34,42 +60,203 @@ use tui_logger::TuiTracingSubscriberLayer;
 lazy_static! {
     static ref PREFERENCES: RwLock<Preferences> = RwLock::new(Preferences::default());
     static ref PAGE_SIZE: RwLock<u16> = RwLock::new(0_u16);
-    static ref TO_RADIO_MPSC: RwLock<Option<Sender<IPCMessage>>> = RwLock::new(None);
+    static ref TO_RADIO_MPSC: RwLock<HashMap<RadioId, Sender<IPCMessage>>> =
+        RwLock::new(HashMap::new());
     static ref FIFTY_FIFTY: Vec<Constraint> =
         vec![Constraint::Percentage(50), Constraint::Percentage(50)];
-    static ref DEVICE_CONFIG: RwLock<Option<DeviceConfiguration>> = RwLock::new(None);
+    static ref DEVICE_CONFIGS: RwLock<HashMap<RadioId, DeviceConfiguration>> =
+        RwLock::new(HashMap::new());
+    /// Config we've fetched from other nodes over the mesh, by node number.
+    static ref REMOTE_CONFIGS: RwLock<HashMap<u32, DeviceConfiguration>> =
+        RwLock::new(HashMap::new());
+    static ref CONNECTION_STATES: RwLock<HashMap<RadioId, ConnectionState>> =
+        RwLock::new(HashMap::new());
+    static ref PACKET_FEED: broadcast::Sender<FromRadio> =
+        broadcast::channel(consts::PACKET_FEED_SIZE).0;
 }
+/// The radio the config tabs show and outgoing packets go through.
+static ACTIVE_RADIO: AtomicUsize = AtomicUsize::new(0);
+/// The node the config tabs show and edit instead of the active radio, or 0 for none.
+static ADMIN_TARGET: AtomicU32 = AtomicU32::new(0);
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
+    let cli = CliArgs::parse();
     if let Err(_e) = std::env::var("RUST_LOG") {
-        std::env::set_var("RUST_LOG", "info");
+        // one-shot commands are for scripts, which only want to hear about trouble.
+        match cli.command {
+            Some(_) => std::env::set_var("RUST_LOG", "warn"),
+            None => std::env::set_var("RUST_LOG", "info"),
+        }
     }
 
+    // with no tui to show the log widget, headless modes log to stderr instead.
+    let headless = cli.daemon.is_some() || cli.command.is_some();
     let collector = tracing_subscriber::registry()
         .with(EnvFilter::from_default_env())
-        .with(TuiTracingSubscriberLayer);
+        .with((!headless).then_some(TuiTracingSubscriberLayer))
+        .with(headless.then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr)));
     tracing::subscriber::set_global_default(collector).expect("Could not initialize logging.");
-    let cli = CliArgs::parse();
-    let mut app = App::default();
-    if cli.ip.is_some() {
-        app.connection = Connection::TCP(cli.ip.unwrap(), cli.tcp_port);
-    } else if cli.serial_port.is_some() {
-        app.connection = Connection::Serial(cli.serial_port.unwrap());
-    } else {
-        println!("You must specify an ip via -i, or a serial port v ia -s.");
+    if let Some(node_count) = cli.benchmark {
+        benchmark::run_benchmark(node_count).await;
+        return Ok(());
+    }
+    let config = match config_file::load(cli.config.as_deref()) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("{e}");
+            process::exit(1);
+        }
+    };
+    let prefs = match preferences_from(&config, &cli) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let connections = match connections_from(&config, &cli) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("{e}");
+            process::exit(1);
+        }
+    };
+    if connections.is_empty() && (cli.daemon.is_some() || cli.command.is_some()) {
+        println!("You must specify an ip via -i, a serial port via -s, a broker via --mqtt, a profile via --profile, a capture via --replay, a daemon via --attach, or --simulate.");
         process::exit(1);
     }
 
+    let mut app = App::default();
+    app.connections = connections;
+    // with no connection at all, the tui starts on the connection picker.
+    app.profiles = config.profiles.clone();
+    app.store_forward = StoreForwardClient::new();
+
+    app.tab = prefs.default_tab;
+    app.user_prefs = prefs.clone();
     {
-        let mut prefs = PREFERENCES.write().await;
-        // setting this to a nonzero length String to help indicate we're a bona-fide
-        // preferences struct and not a ::default() generated one.
-        prefs.initialized = "Yes".to_owned();
-        prefs.show_mqtt = cli.show_mqtt;
+        let mut global_prefs = PREFERENCES.write().await;
+        *global_prefs = prefs;
     }
     assert!(!PREFERENCES.read().await.initialized.is_empty());
+    if let Some(command) = cli.command {
+        // one-shot commands only ever talk to the first radio.
+        let connection = app.connections.swap_remove(0);
+        if let Err(e) = commands::run_command(connection, command).await {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(addr) = cli.http {
+        let node_list = app.nodes_tab.node_list.clone();
+        let messages = app.messages_tab.messages.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http_api::serve(addr, node_list, messages).await {
+                error!("HTTP api stopped: {e}");
+            }
+        });
+    }
+    if let Some(socket_path) = cli.daemon {
+        if let Err(e) = daemon::run_daemon(&mut app, &socket_path).await {
+            error!("meshtui daemon stopped: {e}");
+            process::exit(1);
+        }
+        return Ok(());
+    }
     let _ = app.run().await;
 
     Ok(())
 }
+
+/// Every radio asked for on the command line, or failing that the config file's default profile.
+fn connections_from(config: &ConfigFile, cli: &CliArgs) -> anyhow::Result<Vec<Connection>> {
+    if let Some(path) = &cli.attach {
+        return Ok(vec![Connection::Attach(path.clone())]);
+    }
+    if cli.simulate {
+        return Ok(vec![Connection::Simulated]);
+    }
+    if let Some(path) = &cli.replay {
+        return Ok(vec![Connection::Replay(path.clone(), cli.replay_speed)]);
+    }
+    let mut connections = vec![];
+    for ip in cli.ip.iter() {
+        let (host, port) = util::split_host(ip)?;
+        // a port given right in the address beats --tcp-port.
+        let port = match (port, cli.tcp_port) {
+            (Some(port), Some(t)) if port != t => {
+                warn!("{ip} has its own port, so --tcp-port {t} doesn't apply to it.");
+                port
+            }
+            (Some(port), _) => port,
+            (None, t) => t.unwrap_or(consts::DEFAULT_TCP_PORT),
+        };
+        connections.push(Connection::TCP(host, port));
+    }
+    for device in cli.serial_port.iter() {
+        connections.push(Connection::Serial(device.clone()));
+    }
+    for url in cli.mqtt.iter() {
+        connections.push(Connection::Mqtt(MqttSettings::parse(url)?));
+    }
+    let mut profiles = cli.profile.clone();
+    if connections.is_empty() && profiles.is_empty() {
+        profiles.extend(config.default_profile.clone());
+    }
+    for name in profiles.iter() {
+        match config.profile(name)?.connection()? {
+            Some(conn) => connections.push(conn),
+            None => anyhow::bail!("Profile {name} needs a host, a serial device or an mqtt url."),
+        }
+    }
+    Ok(connections)
+}
+
+/// Built-in defaults, then the config file, then whatever was given on the command line.
+fn preferences_from(config: &ConfigFile, cli: &CliArgs) -> anyhow::Result<Preferences> {
+    let mut prefs = Preferences::default();
+    config.apply(&mut prefs)?;
+    // setting this to a nonzero length String to help indicate we're a bona-fide
+    // preferences struct and not a ::default() generated one.
+    prefs.initialized = "Yes".to_owned();
+    if cli.show_mqtt {
+        prefs.show_mqtt = true;
+    }
+    if cli.detection_alert {
+        prefs.detection_alert = true;
+    }
+    prefs.record_path = cli.record.clone();
+    if let Some(tab) = &cli.tab {
+        prefs.default_tab = config_file::parse_tab(tab)?;
+    }
+    if let Some(units) = cli.units {
+        prefs.units = units;
+    }
+    if let Some(theme) = &cli.theme {
+        config_file::check_theme(theme)?;
+        prefs.theme = theme.clone();
+    }
+    if let Some(retention) = cli.message_retention {
+        config_file::check_retention("--message-retention", retention)?;
+        prefs.message_retention = retention;
+    }
+    if let Some(retention) = cli.timeseries_retention {
+        config_file::check_retention("--timeseries-retention", retention)?;
+        prefs.timeseries_retention = retention;
+    }
+    if let Some(rate) = cli.tick_rate {
+        config_file::check_rate("--tick-rate", rate)?;
+        prefs.tick_rate = rate;
+    }
+    if let Some(rate) = cli.frame_rate {
+        config_file::check_rate("--frame-rate", rate)?;
+        prefs.frame_rate = rate;
+    }
+    for spec in cli.channel_key.iter() {
+        prefs.channel_keys.push(ChannelKey::parse(spec)?);
+    }
+    Ok(prefs)
+}
@@ -1,10 +1,16 @@
-use crate::app::Connection;
+use crate::app::{Connection, ConnectionState, RadioId};
+use crate::capture::{replay_loop, CaptureWriter};
+use crate::daemon::attach_loop;
 use crate::ipc::IPCMessage;
+use crate::mqtt::mqtt_loop;
+use crate::simulator::simulated_loop;
+use crate::util::{self, set_connection_state};
+use crate::PREFERENCES;
 use anyhow::{bail, Result};
 
 use meshtastic::packet::PacketRouter;
 
-use meshtastic::protobufs::{FromRadio, MeshPacket};
+use meshtastic::protobufs::{from_radio, FromRadio, MeshPacket};
 use meshtastic::types::NodeId;
 use meshtastic::{api::StreamApi, utils};
 use strum::Display;
@@ -57,16 +63,19 @@ impl PacketRouter<(), DeviceUpdateError> for MyPacketRouter {
 }
 
 pub(crate) async fn meshtastic_loop(
+    radio: RadioId,
     connection: Connection,
     tx: tokio::sync::mpsc::Sender<IPCMessage>,
     mut rx: tokio::sync::mpsc::Receiver<IPCMessage>,
 ) -> Result<()> {
+    set_connection_state(radio, ConnectionState::Connecting).await;
     let stream_api = StreamApi::new();
     let mut decoded_listener;
     let connected_stream_api;
     match connection {
         Connection::TCP(ip, port) => {
-            let tcp_stream = match utils::stream::build_tcp_stream(format!("{ip}:{port}")).await {
+            let addr = util::host_port(&ip, port);
+            let tcp_stream = match utils::stream::build_tcp_stream(addr).await {
                 Ok(sh) => sh,
                 Err(e) => {
                     bail!(e);
@@ -75,50 +84,100 @@ pub(crate) async fn meshtastic_loop(
             (decoded_listener, connected_stream_api) = stream_api.connect(tcp_stream).await;
         }
         Connection::Serial(device) => {
-            let serial_stream = utils::stream::build_serial_stream(device, None, None, None)
-                .expect("Unable to open serial port.");
+            let serial_stream = match utils::stream::build_serial_stream(device, None, None, None)
+            {
+                Ok(sh) => sh,
+                Err(e) => {
+                    bail!(e);
+                }
+            };
             (decoded_listener, connected_stream_api) = stream_api.connect(serial_stream).await;
         }
+        Connection::Replay(path, speed) => {
+            return replay_loop(radio, path, speed, tx, rx).await;
+        }
+        Connection::Simulated => {
+            return simulated_loop(radio, tx, rx).await;
+        }
+        Connection::Attach(path) => {
+            return attach_loop(radio, path, tx, rx).await;
+        }
+        Connection::Mqtt(settings) => {
+            return mqtt_loop(radio, settings, tx, rx).await;
+        }
         Connection::None => {
-            panic!("Neither tcp nor serial selected for connection.");
+            bail!("No radio was chosen for this connection, so there's nothing to connect to.");
         }
     }
+    set_connection_state(radio, ConnectionState::Configuring).await;
     let config_id = utils::generate_rand_id();
     let mut _stream_api = connected_stream_api.configure(config_id).await?;
     info!("Connected to meshtastic node!");
     let mut packet_router = MyPacketRouter::new(0);
-    loop {
-        if let Ok(fr) = decoded_listener.try_recv() {
-            if let Err(e) = tx.send(IPCMessage::FromRadio(fr)).await {
-                error!("Couldn't send FromRadio packet to mpsc: {e}");
-            }
+    // every radio past the first records next to the first one's capture.
+    let record_path = PREFERENCES
+        .read()
+        .await
+        .record_path
+        .clone()
+        .map(|path| match radio {
+            0 => path,
+            _ => format!("{path}.{}", radio + 1),
+        });
+    let mut recorder = match record_path {
+        Some(path) => {
+            info!("Recording radio session to {path}");
+            Some(CaptureWriter::open(&path)?)
         }
-        if let Ok(inbound) = rx.try_recv() {
-            match inbound {
-                IPCMessage::SendMessage(message) => {
-                    if let Err(e) = _stream_api
-                        .send_text(
-                            &mut packet_router,
-                            message.message,
-                            message.destination,
-                            true,
-                            message.channel,
-                        )
-                        .await
-                    {
-                        error!("We tried to send a message but... nope: {e}");
+        None => None,
+    };
+    loop {
+        tokio::select! {
+            maybe_fr = decoded_listener.recv() => {
+                let fr = match maybe_fr {
+                    Some(fr) => fr,
+                    None => {
+                        bail!("Lost connection to the radio.");
                     }
+                };
+                if let Some(from_radio::PayloadVariant::ConfigCompleteId(_)) = fr.payload_variant {
+                    set_connection_state(radio, ConnectionState::Connected).await;
                 }
-                IPCMessage::ToRadio(tr) => {
-                    if let Err(e) = _stream_api.send_to_radio_packet(tr.payload_variant).await {
-                        error!("We tried to send a ToRadio message directly but errored: {e}");
+                if let Some(rec) = recorder.as_mut() {
+                    if let Err(e) = rec.write_frame(&fr) {
+                        error!("Couldn't write packet to capture file: {e}");
                     }
                 }
-                _ => {
-                    warn!("Unknown ipc message sent into comms thread.");
+                if let Err(e) = tx.send(IPCMessage::FromRadio(fr)).await {
+                    error!("Couldn't send FromRadio packet to mpsc: {e}");
+                }
+            }
+            Some(inbound) = rx.recv() => {
+                match inbound {
+                    IPCMessage::SendMessage(message) => {
+                        if let Err(e) = _stream_api
+                            .send_text(
+                                &mut packet_router,
+                                message.message,
+                                message.destination,
+                                true,
+                                message.channel,
+                            )
+                            .await
+                        {
+                            error!("We tried to send a message but... nope: {e}");
+                        }
+                    }
+                    IPCMessage::ToRadio(tr) => {
+                        if let Err(e) = _stream_api.send_to_radio_packet(tr.payload_variant).await {
+                            error!("We tried to send a ToRadio message directly but errored: {e}");
+                        }
+                    }
+                    _ => {
+                        warn!("Unknown ipc message sent into comms thread.");
+                    }
                 }
             }
         }
-        tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
     }
 }
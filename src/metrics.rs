@@ -0,0 +1,171 @@
+//! Prometheus metrics, served as `/metrics` by the HTTP api.
+//!
+//! Node gauges are read out of the node store when scraped; the only thing we keep for this
+//! is a count of packets by port.
+use crate::node_store::NodeStore;
+use crate::util::get_secs;
+use lazy_static::lazy_static;
+use meshtastic::protobufs::{EnvironmentMetrics, PortNum};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref PACKETS_BY_PORT: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+}
+
+pub fn count_packet(portnum: PortNum) {
+    let mut counts = PACKETS_BY_PORT.lock().unwrap();
+    *counts.entry(portnum.as_str_name()).or_insert(0) += 1;
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    samples: Vec<(String, f64)>,
+}
+
+impl Gauge {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        Gauge {
+            name,
+            help,
+            samples: vec![],
+        }
+    }
+}
+
+pub fn render(node_list: &NodeStore) -> String {
+    let mut battery = Gauge::new(
+        "meshtastic_node_battery_level",
+        "Battery percent (101 is powered)",
+    );
+    let mut voltage = Gauge::new("meshtastic_node_voltage", "Supply voltage in volts");
+    let mut chutil = Gauge::new(
+        "meshtastic_node_channel_utilization",
+        "Percent of airtime the node hears in use",
+    );
+    let mut airtx = Gauge::new(
+        "meshtastic_node_air_util_tx",
+        "Percent of airtime the node spent transmitting in the last hour",
+    );
+    let mut temperature = Gauge::new("meshtastic_node_temperature_celsius", "Temperature");
+    let mut humidity = Gauge::new(
+        "meshtastic_node_relative_humidity",
+        "Relative humidity in percent",
+    );
+    let mut pressure = Gauge::new(
+        "meshtastic_node_barometric_pressure_hpa",
+        "Barometric pressure",
+    );
+    let mut snr = Gauge::new("meshtastic_node_snr_db", "SNR of the last packet we heard");
+    let mut rssi = Gauge::new(
+        "meshtastic_node_rssi_dbm",
+        "RSSI of the last packet we heard",
+    );
+    let mut hops = Gauge::new("meshtastic_node_hops_away", "Hops between us and the node");
+    let mut since = Gauge::new(
+        "meshtastic_node_last_heard_seconds",
+        "Seconds since we last heard anything about the node",
+    );
+
+    let now = get_secs();
+    {
+        let nodes = node_list.read();
+        for cn in nodes.values() {
+            let user = cn.node_info.user.clone().unwrap_or_default();
+            let labels = format!(
+                "node=\"!{:08x}\",short_name=\"{}\",long_name=\"{}\"",
+                cn.id,
+                escape_label(&user.short_name),
+                escape_label(&user.long_name)
+            );
+            if let Some(dm) = &cn.node_info.device_metrics {
+                battery
+                    .samples
+                    .push((labels.clone(), dm.battery_level as f64));
+                voltage.samples.push((labels.clone(), dm.voltage as f64));
+                chutil
+                    .samples
+                    .push((labels.clone(), dm.channel_utilization as f64));
+                airtx.samples.push((labels.clone(), dm.air_util_tx as f64));
+            }
+            let env = cn
+                .timeseries
+                .iter()
+                .rev()
+                .find(|d| d.environment != EnvironmentMetrics::default())
+                .map(|d| d.environment.clone());
+            if let Some(env) = env {
+                temperature
+                    .samples
+                    .push((labels.clone(), env.temperature as f64));
+                humidity
+                    .samples
+                    .push((labels.clone(), env.relative_humidity as f64));
+                pressure
+                    .samples
+                    .push((labels.clone(), env.barometric_pressure as f64));
+            }
+            // a node we've never heard over the air, only from the node db, has no signal yet.
+            if cn.last_rssi != 0 {
+                snr.samples.push((labels.clone(), cn.last_snr as f64));
+                rssi.samples.push((labels.clone(), cn.last_rssi as f64));
+            }
+            if !cn.node_info.via_mqtt {
+                hops.samples
+                    .push((labels.clone(), cn.node_info.hops_away as f64));
+            }
+            if cn.last_seen > 0 {
+                since
+                    .samples
+                    .push((labels, now.saturating_sub(cn.last_seen) as f64));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for gauge in [
+        battery,
+        voltage,
+        chutil,
+        airtx,
+        temperature,
+        humidity,
+        pressure,
+        snr,
+        rssi,
+        hops,
+        since,
+    ] {
+        let _ = writeln!(out, "# HELP {} {}", gauge.name, gauge.help);
+        let _ = writeln!(out, "# TYPE {} gauge", gauge.name);
+        for (labels, value) in gauge.samples {
+            let _ = writeln!(out, "{}{{{}}} {}", gauge.name, labels, value);
+        }
+    }
+
+    let _ = writeln!(out, "# HELP meshtastic_nodes Nodes in our node database");
+    let _ = writeln!(out, "# TYPE meshtastic_nodes gauge");
+    let _ = writeln!(out, "meshtastic_nodes {}", node_list.len());
+
+    let _ = writeln!(
+        out,
+        "# HELP meshtastic_packets_total Decoded packets received, by port"
+    );
+    let _ = writeln!(out, "# TYPE meshtastic_packets_total counter");
+    for (port, count) in PACKETS_BY_PORT.lock().unwrap().iter() {
+        let _ = writeln!(
+            out,
+            "meshtastic_packets_total{{portnum=\"{port}\"}} {count}"
+        );
+    }
+    out
+}
@@ -0,0 +1,196 @@
+//! The one copy of our node database.
+//!
+//! Packets update nodes in place instead of cloning the whole map, and anybody who cares about
+//! changes can either compare `version()` against what they last saw, as the node list does, or
+//! subscribe to the ids of nodes as they change, as the HTTP api's node event stream does.
+use crate::app::RadioId;
+use crate::tabs::nodes::ComprehensiveNode;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use tokio::sync::broadcast;
+
+const CHANGE_CHANNEL_SIZE: usize = 1024_usize;
+
+#[derive(Clone)]
+pub struct NodeStore {
+    nodes: Arc<RwLock<HashMap<u32, ComprehensiveNode>>>,
+    version: Arc<AtomicU64>,
+    changes: broadcast::Sender<u32>,
+}
+
+impl Default for NodeStore {
+    fn default() -> Self {
+        let (changes, _) = broadcast::channel(CHANGE_CHANNEL_SIZE);
+        NodeStore {
+            nodes: Arc::new(RwLock::new(HashMap::new())),
+            version: Arc::new(AtomicU64::new(0)),
+            changes,
+        }
+    }
+}
+
+impl From<HashMap<u32, ComprehensiveNode>> for NodeStore {
+    fn from(nodes: HashMap<u32, ComprehensiveNode>) -> Self {
+        let store = NodeStore::default();
+        *store.nodes.write().unwrap() = nodes;
+        store
+    }
+}
+
+impl fmt::Debug for NodeStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeStore")
+            .field("nodes", &self.len())
+            .field("version", &self.version())
+            .finish()
+    }
+}
+
+impl NodeStore {
+    /// Applies `f` to the node, creating an empty record for it first if we've never seen it.
+    pub fn update<F: FnOnce(&mut ComprehensiveNode)>(&self, id: u32, f: F) {
+        {
+            let mut nodes = self.nodes.write().unwrap();
+            let cn = nodes
+                .entry(id)
+                .or_insert_with(|| ComprehensiveNode::with_id(id));
+            f(cn);
+        }
+        self.changed(id);
+    }
+
+    /// Like `update`, but leaves unknown nodes alone.  Returns whether the node existed.
+    pub fn update_existing<F: FnOnce(&mut ComprehensiveNode)>(&self, id: u32, f: F) -> bool {
+        let found = {
+            let mut nodes = self.nodes.write().unwrap();
+            match nodes.get_mut(&id) {
+                Some(cn) => {
+                    f(cn);
+                    true
+                }
+                None => false,
+            }
+        };
+        if found {
+            self.changed(id);
+        }
+        found
+    }
+
+    /// Reads from one node without copying it.
+    pub fn with_node<R, F: FnOnce(&ComprehensiveNode) -> R>(&self, id: u32, f: F) -> Option<R> {
+        self.nodes.read().unwrap().get(&id).map(f)
+    }
+
+    pub fn get(&self, id: u32) -> Option<ComprehensiveNode> {
+        self.with_node(id, |cn| cn.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.read().unwrap().is_empty()
+    }
+
+    /// Forgets that `radio` ever heard anything, and drops the nodes no other radio has heard.
+    pub fn forget_radio(&self, radio: RadioId) {
+        self.nodes.write().unwrap().retain(|_, cn| {
+            cn.heard_by.remove(&radio);
+            !cn.heard_by.is_empty()
+        });
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Holds the read lock for as long as the guard lives, so keep it short.
+    pub fn read(&self) -> RwLockReadGuard<'_, HashMap<u32, ComprehensiveNode>> {
+        self.nodes.read().unwrap()
+    }
+
+    /// Bumped on every change; cheap to poll from a render loop.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Relaxed)
+    }
+
+    /// Yields the id of every node that changes from here on.
+    pub fn subscribe(&self) -> broadcast::Receiver<u32> {
+        self.changes.subscribe()
+    }
+
+    fn changed(&self, id: u32) {
+        self.version.fetch_add(1, Ordering::Relaxed);
+        // nobody listening is fine.
+        let _ = self.changes.send(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heard_by(store: &NodeStore, id: u32, radios: &[RadioId]) {
+        store.update(id, |cn| cn.heard_by.extend(radios.iter().copied()));
+    }
+
+    #[test]
+    fn update_creates_nodes() {
+        let store = NodeStore::default();
+        store.update(7, |cn| cn.last_seen = 100);
+        assert_eq!(store.len(), 1);
+        assert_eq!(
+            store.with_node(7, |cn| (cn.id, cn.last_seen)),
+            Some((7, 100))
+        );
+    }
+
+    #[test]
+    fn update_existing_leaves_unknown_nodes_alone() {
+        let store = NodeStore::default();
+        assert!(!store.update_existing(7, |cn| cn.last_seen = 100));
+        assert!(store.is_empty());
+        assert_eq!(store.version(), 0);
+        store.update(7, |_| {});
+        assert!(store.update_existing(7, |cn| cn.last_seen = 100));
+        assert_eq!(store.get(7).map(|cn| cn.last_seen), Some(100));
+    }
+
+    #[test]
+    fn changes_bump_the_version_and_notify() {
+        let store = NodeStore::default();
+        let mut changes = store.subscribe();
+        store.update(1, |_| {});
+        store.update_existing(2, |_| {});
+        store.update_existing(1, |_| {});
+        assert_eq!(store.version(), 2);
+        assert_eq!(changes.try_recv().ok(), Some(1));
+        assert_eq!(changes.try_recv().ok(), Some(1));
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[test]
+    fn clones_share_the_nodes() {
+        let store = NodeStore::default();
+        let other = store.clone();
+        other.update(3, |_| {});
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.version(), other.version());
+    }
+
+    #[test]
+    fn forgetting_a_radio_keeps_nodes_another_radio_heard() {
+        let store = NodeStore::default();
+        heard_by(&store, 1, &[0]);
+        heard_by(&store, 2, &[0, 1]);
+        heard_by(&store, 3, &[1]);
+        let before = store.version();
+        store.forget_radio(0);
+        assert!(store.version() > before);
+        assert_eq!(store.get(1).map(|cn| cn.id), None);
+        let heard = store.with_node(2, |cn| cn.heard_by.iter().copied().collect::<Vec<_>>());
+        assert_eq!(heard, Some(vec![1]));
+        assert!(store.get(3).is_some());
+    }
+}
@@ -1,25 +1,34 @@
-use crate::app::DeviceConfiguration;
+use crate::app::RadioId;
 use crate::ipc::IPCMessage;
-use crate::tabs::nodes::{ComprehensiveNode, TimeSeriesData};
+use crate::node_store::NodeStore;
+use crate::tabs::nodes::{DetectionEvent, TimeSeriesData};
+use crate::tabs::range_test::RangeTestSample;
 use crate::util::get_secs;
-use crate::{util, DEVICE_CONFIG};
+use crate::{admin, consts, metrics, util, DEVICE_CONFIGS, PREFERENCES};
 use meshtastic::packet::PacketDestination;
-use meshtastic::protobufs::config::PayloadVariant;
 use meshtastic::protobufs::log_record::Level;
-use meshtastic::protobufs::module_config::PayloadVariant as mpv;
+use meshtastic::protobufs::store_and_forward::{self, RequestResponse};
 use meshtastic::protobufs::{
-    from_radio, mesh_packet, routing, telemetry, NeighborInfo, NodeInfo, PortNum, Position,
-    RouteDiscovery, Routing, User,
+    from_radio, mesh_packet, routing, telemetry, MeshPacket, NeighborInfo, NodeInfo, Paxcount,
+    PortNum, Position, QueueStatus, RouteDiscovery, Routing, StoreAndForward, User, Waypoint,
 };
 use meshtastic::types::MeshChannel;
 use meshtastic::Message;
-use std::collections::HashMap;
 
 pub(crate) enum PacketResponse {
-    NodeUpdate(u32, Box<ComprehensiveNode>),
-    UserUpdate(u32, User),
     InboundMessage(MessageEnvelope),
     OurAddress(u32),
+    QueueStatus(QueueStatus),
+    /// Something a Store & Forward router said about itself, and which node it is.
+    StoreForward(u32, StoreAndForward),
+    /// A detection sensor went off on this node.
+    Detection(u32, String),
+    /// A range test packet, still without the positions that go with it.
+    RangeTest(RangeTestSample),
+    /// A waypoint, who sent it, and the channel it came in on.
+    Waypoint(Waypoint, u32, u32),
+    /// The radio has just restarted, so what we know about its config may be stale.
+    Rebooted,
 }
 
 #[derive(Debug, Clone)]
@@ -33,122 +42,159 @@ pub struct MessageEnvelope {
     pub(crate) rx_snr: f32,
 }
 
+impl MessageEnvelope {
+    /// Whether this is another copy of `other`, say a Store & Forward replay of a message we
+    /// already heard ourselves.
+    pub(crate) fn is_copy_of(&self, other: &MessageEnvelope) -> bool {
+        let sender = |m: &MessageEnvelope| m.source.as_ref().map(|s| s.num);
+        sender(self) == sender(other)
+            && self.message == other.message
+            && self.timestamp.abs_diff(other.timestamp) <= consts::DUPLICATE_MESSAGE_WINDOW_SECS
+    }
+}
+
+fn destination_of(to: u32) -> PacketDestination {
+    match to {
+        0 => PacketDestination::Local,
+        u32::MAX => PacketDestination::Broadcast,
+        s => PacketDestination::Node(s.into()),
+    }
+}
+
+/// A text message for the messages tab, or None if we've never heard of the sender.
+fn inbound_message(
+    pa: &MeshPacket,
+    destination: PacketDestination,
+    message: String,
+    node_list: &NodeStore,
+) -> Option<PacketResponse> {
+    let source_ni = match node_list.with_node(pa.from, |cn| cn.node_info.clone()) {
+        Some(s) => s,
+        None => {
+            info!("Could not find node info for id {}", pa.from);
+            return None;
+        }
+    };
+    Some(PacketResponse::InboundMessage(MessageEnvelope {
+        timestamp: pa.rx_time,
+        source: Some(source_ni),
+        destination,
+        channel: MeshChannel::from(pa.channel),
+        message,
+        rx_rssi: pa.rx_rssi,
+        rx_snr: pa.rx_snr,
+    }))
+}
+
+/// Node updates are applied straight to `node_list`; anything else the app needs to act on
+/// comes back as a `PacketResponse`.  Encrypted packets should have been through
+/// `crypto::decrypt_frame` already.
 pub async fn process_packet(
+    radio: RadioId,
     packet: IPCMessage,
-    node_list: HashMap<u32, ComprehensiveNode>,
+    node_list: &NodeStore,
 ) -> Option<PacketResponse> {
     if let IPCMessage::FromRadio(fr) = packet {
         if let Some(some_fr) = fr.payload_variant {
             match some_fr {
                 from_radio::PayloadVariant::Packet(pa) => {
+                    node_list.update(pa.from, |cn| {
+                        cn.heard_by.insert(radio);
+                    });
                     if let Some(payload) = pa.clone().payload_variant {
                         match payload.clone() {
                             mesh_packet::PayloadVariant::Decoded(de) => {
+                                metrics::count_packet(de.portnum());
                                 match de.portnum() {
                                     PortNum::PositionApp => {
-                                        let data = Position::decode(de.payload.as_slice()).unwrap();
-                                        let mut cn = match node_list.contains_key(&pa.from) {
-                                            true => node_list.get(&pa.from).unwrap().to_owned(),
-                                            false => ComprehensiveNode::with_id(de.source),
+                                        let data = match Position::decode(de.payload.as_slice()) {
+                                            Ok(d) => d,
+                                            Err(e) => {
+                                                warn!("Couldn't decode a position from !{:x}: {e}", pa.from);
+                                                return None;
+                                            }
                                         };
                                         info!(
                                             "Updating Position for {} ({})",
-                                            cn.clone()
-                                                .node_info
-                                                .user
-                                                .unwrap_or_else(User::default)
-                                                .id,
+                                            node_list
+                                                .with_node(pa.from, |cn| cn.user_id())
+                                                .flatten()
+                                                .unwrap_or_default(),
                                             pa.from
                                         );
-                                        cn.node_info.position = Some(data);
-                                        cn.last_seen = util::get_secs();
-                                        cn.last_rssi = pa.rx_rssi;
-                                        cn.last_snr = pa.rx_snr;
-                                        return Some(PacketResponse::NodeUpdate(
-                                            cn.node_info.num,
-                                            Box::new(cn),
-                                        ));
+                                        node_list.update(pa.from, |cn| {
+                                            cn.node_info.position = Some(data);
+                                            cn.last_seen = util::get_secs();
+                                            cn.last_rssi = pa.rx_rssi;
+                                            cn.last_snr = pa.rx_snr;
+                                        });
+                                        return None;
                                     }
                                     PortNum::TelemetryApp => {
-                                        let data = meshtastic::protobufs::Telemetry::decode(
-                                            de.payload.as_slice(),
-                                        )
-                                        .unwrap();
+                                        let data = match meshtastic::protobufs::Telemetry::decode(de.payload.as_slice()) {
+                                            Ok(d) => d,
+                                            Err(e) => {
+                                                warn!("Couldn't decode telemetry from !{:x}: {e}", pa.from);
+                                                return None;
+                                            }
+                                        };
+                                        let retention =
+                                            PREFERENCES.read().await.timeseries_retention;
                                         if let Some(v) = data.variant {
                                             match v {
                                                 telemetry::Variant::EnvironmentMetrics(env) => {
-                                                    let mut cn = match node_list
-                                                        .contains_key(&pa.from)
-                                                    {
-                                                        true => node_list
-                                                            .get(&pa.from)
-                                                            .unwrap()
-                                                            .to_owned(),
-                                                        false => {
-                                                            ComprehensiveNode::with_id(pa.from)
-                                                        }
-                                                    };
                                                     info!("Received EnvironmentalMetrics from !{:x} ({})", pa.from, pa.from);
-                                                    cn.timeseries.push_back(
-                                                        TimeSeriesData {
-                                                            timestamp: get_secs(),
-                                                            environment: env.clone(),
-                                                            rssi: pa.rx_rssi as f64,
-                                                            snr: pa.rx_snr as f64,
-                                                            ..Default::default()
-                                                        });
-                                                    if cn.timeseries_start == 0 {
-                                                        cn.timeseries_start = get_secs();
-                                                    };
-                                                    cn.last_seen = util::get_secs();
-                                                    cn.last_rssi = pa.rx_rssi;
-                                                    cn.last_snr = pa.rx_snr;
-                                                    return Some(PacketResponse::NodeUpdate(
-                                                        cn.node_info.num,
-                                                        Box::new(cn),
-                                                    ));
-                                                    
+                                                    node_list.update(pa.from, |cn| {
+                                                        cn.timeseries.push_back(
+                                                            TimeSeriesData {
+                                                                timestamp: get_secs(),
+                                                                environment: env,
+                                                                rssi: pa.rx_rssi as f64,
+                                                                snr: pa.rx_snr as f64,
+                                                                ..Default::default()
+                                                            });
+                                                        while cn.timeseries.len() > retention {
+                                                            cn.timeseries.pop_front();
+                                                        }
+                                                        if cn.timeseries_start == 0 {
+                                                            cn.timeseries_start = get_secs();
+                                                        };
+                                                        cn.last_seen = util::get_secs();
+                                                        cn.last_rssi = pa.rx_rssi;
+                                                        cn.last_snr = pa.rx_snr;
+                                                    });
+                                                    return None;
                                                 }
                                                 telemetry::Variant::DeviceMetrics(dm) => {
-                                                    let mut cn = match node_list
-                                                        .contains_key(&pa.from)
-                                                    {
-                                                        true => node_list
-                                                            .get(&pa.from)
-                                                            .unwrap()
-                                                            .to_owned(),
-                                                        false => {
-                                                            ComprehensiveNode::with_id(pa.from)
-                                                        }
-                                                    };
                                                     info!(
                                                         "Updating DeviceMetrics for {} ({})",
-                                                        cn.clone()
-                                                            .node_info
-                                                            .user
-                                                            .unwrap_or_else(User::default)
-                                                            .id,
+                                                        node_list
+                                                            .with_node(pa.from, |cn| cn.user_id())
+                                                            .flatten()
+                                                            .unwrap_or_default(),
                                                         pa.from
                                                     );
-                                                    cn.node_info.device_metrics = Some(dm.clone());
-                                                    cn.timeseries.push_back(
-                                                        TimeSeriesData {
-                                                            timestamp: get_secs(),
-                                                            device: dm.clone(),
-                                                            rssi: pa.rx_rssi as f64,
-                                                            snr: pa.rx_snr as f64,
-                                                            ..Default::default()
-                                                        });
-                                                    if cn.timeseries_start == 0 {
-                                                        cn.timeseries_start = get_secs();
-                                                    };
-                                                    cn.last_seen = util::get_secs();
-                                                    cn.last_rssi = pa.rx_rssi;
-                                                    cn.last_snr = pa.rx_snr;
-                                                    return Some(PacketResponse::NodeUpdate(
-                                                        cn.node_info.num,
-                                                        Box::new(cn),
-                                                    ));
+                                                    node_list.update(pa.from, |cn| {
+                                                        cn.node_info.device_metrics = Some(dm.clone());
+                                                        cn.timeseries.push_back(
+                                                            TimeSeriesData {
+                                                                timestamp: get_secs(),
+                                                                device: dm,
+                                                                rssi: pa.rx_rssi as f64,
+                                                                snr: pa.rx_snr as f64,
+                                                                ..Default::default()
+                                                            });
+                                                        while cn.timeseries.len() > retention {
+                                                            cn.timeseries.pop_front();
+                                                        }
+                                                        if cn.timeseries_start == 0 {
+                                                            cn.timeseries_start = get_secs();
+                                                        };
+                                                        cn.last_seen = util::get_secs();
+                                                        cn.last_rssi = pa.rx_rssi;
+                                                        cn.last_snr = pa.rx_snr;
+                                                    });
+                                                    return None;
                                                 }
                                                 _ => {
                                                     return None;
@@ -160,44 +206,40 @@ pub async fn process_packet(
                                         return None;
                                     }
                                     PortNum::NeighborinfoApp => {
-                                        let data =
-                                            NeighborInfo::decode(de.payload.as_slice()).unwrap();
-                                        let empty = ComprehensiveNode::with_id(de.source);
-                                        for neighbor in data.neighbors.iter() {
-                                            let d_cn = node_list
-                                                .get(&data.node_id)
-                                                .map_or(empty.clone(), |v| v.clone());
-                                            let n_cn = node_list
-                                                .get(&neighbor.node_id)
-                                                .map_or(empty.clone(), |v| v.clone());
-
-                                            let mut hub = "Unknown".to_string();
-                                            let mut spoke = "Unknown".to_string();
-                                            if let Some(d_user) = d_cn.node_info.user {
-                                                hub = d_user.id;
-                                            }
-                                            if let Some(n_user) = n_cn.node_info.user {
-                                                spoke = n_user.id;
+                                        let data = match NeighborInfo::decode(de.payload.as_slice()) {
+                                            Ok(d) => d,
+                                            Err(e) => {
+                                                warn!("Couldn't decode neighbor info from !{:x}: {e}", pa.from);
+                                                return None;
                                             }
+                                        };
+                                        for neighbor in data.neighbors.iter() {
+                                            let hub = node_list
+                                                .with_node(data.node_id, |cn| cn.user_id())
+                                                .flatten()
+                                                .unwrap_or_else(|| "Unknown".to_string());
+                                            let spoke = node_list
+                                                .with_node(neighbor.node_id, |cn| cn.user_id())
+                                                .flatten()
+                                                .unwrap_or_else(|| "Unknown".to_string());
                                             info!("NeighborInfo: {hub} has neighbor {spoke}");
                                         }
-                                        let mut cn = match node_list.get(&data.node_id) {
-                                            None => {
-                                                ComprehensiveNode::with_id(pa.from)
-                                            }
-                                            Some(n) => n.clone(),
-                                        };
-                                        cn.neighbors = data.neighbors;
-                                        cn.last_seen = util::get_secs();
-                                        cn.last_rssi = pa.rx_rssi;
-                                        cn.last_snr = pa.rx_snr;
-                                        return Some(PacketResponse::NodeUpdate(
-                                            cn.node_info.num,
-                                            Box::new(cn),
-                                        ));
+                                        node_list.update(data.node_id, |cn| {
+                                            cn.neighbors = data.neighbors;
+                                            cn.last_seen = util::get_secs();
+                                            cn.last_rssi = pa.rx_rssi;
+                                            cn.last_snr = pa.rx_snr;
+                                        });
+                                        return None;
                                     }
                                     PortNum::NodeinfoApp => {
-                                        let data = User::decode(de.payload.as_slice()).unwrap();
+                                        let data = match User::decode(de.payload.as_slice()) {
+                                            Ok(d) => d,
+                                            Err(e) => {
+                                                warn!("Couldn't decode node info from !{:x}: {e}", pa.from);
+                                                return None;
+                                            }
+                                        };
                                         info!(
                                             "Received node info update for {} ({})",
                                             data.id, pa.from
@@ -212,10 +254,20 @@ pub async fn process_packet(
                                             return None;
                                         }
 
-                                        return Some(PacketResponse::UserUpdate(nid, data));
+                                        node_list.update(nid, |cn| {
+                                            cn.node_info.user = Some(data);
+                                            cn.last_seen = util::get_secs();
+                                        });
+                                        return None;
                                     }
                                     PortNum::RoutingApp => {
-                                        let data = Routing::decode(de.payload.as_slice()).unwrap();
+                                        let data = match Routing::decode(de.payload.as_slice()) {
+                                            Ok(d) => d,
+                                            Err(e) => {
+                                                warn!("Couldn't decode a routing message from !{:x}: {e}", pa.from);
+                                                return None;
+                                            }
+                                        };
                                         if let Some(v) = data.variant {
                                             match v {
                                                 routing::Variant::RouteRequest(_r) => {
@@ -244,56 +296,193 @@ pub async fn process_packet(
                                         if let Ok(route) = val_resp {
                                             let from_id = pa.clone().from;
                                             let to_id = pa.clone().to;
-                                            let mut cn = match node_list.get(&from_id) {
-                                                None => {
-                                                    error!("{:#?}", pa.clone());
-                                                    return None;
-                                                }
-                                                Some(n) => n.clone(),
-                                            };
-                                            cn.route_list.insert(to_id, route.clone().route);
                                             info!(
                                                 "updating route table to {:#?} for !{:x}->!{:x}",
                                                 route.route, from_id, to_id
                                             );
-                                            return Some(PacketResponse::NodeUpdate(cn.id, Box::new(cn)));
+                                            if !node_list.update_existing(from_id, |cn| {
+                                                cn.route_list.insert(to_id, route.route);
+                                            }) {
+                                                error!("{:#?}", pa.clone());
+                                            }
+                                            return None;
+                                        }
+                                    }
+                                    PortNum::WaypointApp => {
+                                        match Waypoint::decode(de.payload.as_slice()) {
+                                            Ok(waypoint) => {
+                                                info!(
+                                                    "Received waypoint {} from !{:x}",
+                                                    waypoint.name, pa.from
+                                                );
+                                                return Some(PacketResponse::Waypoint(
+                                                    waypoint, pa.from, pa.channel,
+                                                ));
+                                            }
+                                            Err(e) => {
+                                                warn!(
+                                                    "Couldn't decode waypoint from !{:x}: {e}",
+                                                    pa.from
+                                                );
+                                                return None;
+                                            }
+                                        }
+                                    }
+                                    PortNum::PaxcounterApp => {
+                                        let pax = match Paxcount::decode(de.payload.as_slice()) {
+                                            Ok(p) => p,
+                                            Err(e) => {
+                                                warn!(
+                                                    "Couldn't decode pax count from !{:x}: {e}",
+                                                    pa.from
+                                                );
+                                                return None;
+                                            }
+                                        };
+                                        info!(
+                                            "Pax count from !{:x}: {} wifi, {} ble",
+                                            pa.from, pax.wifi, pax.ble
+                                        );
+                                        let retention =
+                                            PREFERENCES.read().await.timeseries_retention;
+                                        node_list.update(pa.from, |cn| {
+                                            cn.timeseries.push_back(TimeSeriesData {
+                                                timestamp: get_secs(),
+                                                pax: Some(pax),
+                                                rssi: pa.rx_rssi as f64,
+                                                snr: pa.rx_snr as f64,
+                                                ..Default::default()
+                                            });
+                                            while cn.timeseries.len() > retention {
+                                                cn.timeseries.pop_front();
+                                            }
+                                            if cn.timeseries_start == 0 {
+                                                cn.timeseries_start = get_secs();
+                                            };
+                                            cn.last_seen = util::get_secs();
+                                            cn.last_rssi = pa.rx_rssi;
+                                            cn.last_snr = pa.rx_snr;
+                                        });
+                                        return None;
+                                    }
+                                    PortNum::DetectionSensorApp => {
+                                        let text = String::from_utf8_lossy(&de.payload).to_string();
+                                        let retention =
+                                            PREFERENCES.read().await.timeseries_retention;
+                                        node_list.update(pa.from, |cn| {
+                                            cn.detections.push_back(DetectionEvent {
+                                                timestamp: match pa.rx_time {
+                                                    0 => get_secs(),
+                                                    t => t as u64,
+                                                },
+                                                text: text.clone(),
+                                            });
+                                            while cn.detections.len() > retention {
+                                                cn.detections.pop_front();
+                                            }
+                                            cn.last_seen = util::get_secs();
+                                            cn.last_rssi = pa.rx_rssi;
+                                            cn.last_snr = pa.rx_snr;
+                                        });
+                                        return Some(PacketResponse::Detection(pa.from, text));
+                                    }
+                                    PortNum::RangeTestApp => {
+                                        let text = String::from_utf8_lossy(&de.payload);
+                                        let seq = match text
+                                            .trim()
+                                            .strip_prefix("seq ")
+                                            .map(|n| n.trim().parse::<u32>())
+                                        {
+                                            Some(Ok(seq)) => seq,
+                                            _ => {
+                                                warn!(
+                                                    "Couldn't make sense of range test packet {text} from !{:x}",
+                                                    pa.from
+                                                );
+                                                return None;
+                                            }
+                                        };
+                                        return Some(PacketResponse::RangeTest(RangeTestSample {
+                                            timestamp: match pa.rx_time {
+                                                0 => get_secs(),
+                                                t => t as u64,
+                                            },
+                                            from: pa.from,
+                                            seq,
+                                            snr: pa.rx_snr,
+                                            rssi: pa.rx_rssi,
+                                            hops: pa.hop_start.saturating_sub(pa.hop_limit),
+                                            ..Default::default()
+                                        }));
+                                    }
+                                    PortNum::StoreForwardApp => {
+                                        let sf = match StoreAndForward::decode(
+                                            de.payload.as_slice(),
+                                        ) {
+                                            Ok(sf) => sf,
+                                            Err(e) => {
+                                                warn!(
+                                                    "Couldn't decode Store & Forward packet from !{:x}: {e}",
+                                                    pa.from
+                                                );
+                                                return None;
+                                            }
+                                        };
+                                        // a router replaying history sends each message from
+                                        // its original sender, with the time it first heard it.
+                                        let destination = match sf.rr() {
+                                            RequestResponse::RouterTextBroadcast => {
+                                                PacketDestination::Broadcast
+                                            }
+                                            RequestResponse::RouterTextDirect => {
+                                                destination_of(pa.to)
+                                            }
+                                            _ => {
+                                                return Some(PacketResponse::StoreForward(
+                                                    pa.from, sf,
+                                                ))
+                                            }
+                                        };
+                                        if let Some(store_and_forward::Variant::Text(text)) =
+                                            sf.variant
+                                        {
+                                            let message = String::from_utf8_lossy(&text).to_string();
+                                            return inbound_message(
+                                                &pa,
+                                                destination,
+                                                message,
+                                                node_list,
+                                            );
                                         }
+                                        return None;
                                     }
                                     PortNum::ReplyApp => {
                                         info!("We were just pinged.");
                                     }
+                                    PortNum::AdminApp => {
+                                        if let Err(e) =
+                                            admin::file_response(pa.from, &de.payload).await
+                                        {
+                                            warn!(
+                                                "Couldn't decode admin packet from !{:x}: {e}",
+                                                pa.from
+                                            );
+                                        }
+                                        return None;
+                                    }
 
                                     PortNum::TextMessageApp => {
                                         if let Ok(message) = String::from_utf8(de.payload) {
-                                            let source_ni = match node_list.get(&pa.from) {
-                                                Some(s) => s.clone().node_info,
-                                                None => {
-                                                    info!(
-                                                        "Could not find node info for id {}",
-                                                        pa.from
-                                                    );
-                                                    return None;
-                                                }
-                                            };
-                                            let _dest_ni =
-                                                node_list.get(&pa.to).map(|s| s.clone().node_info);
-                                            let destinated: PacketDestination = match pa.to {
-                                                0 => PacketDestination::Local,
-                                                u32::MAX => PacketDestination::Broadcast,
-                                                s => PacketDestination::Node(s.into()),
-                                            };
-
-                                            return Some(PacketResponse::InboundMessage(
-                                                MessageEnvelope {
-                                                    timestamp: pa.rx_time,
-                                                    source: Some(source_ni),
-                                                    destination: destinated,
-                                                    channel: MeshChannel::from(pa.channel),
-                                                    message,
-                                                    rx_rssi: pa.rx_rssi,
-                                                    rx_snr: pa.rx_snr,
-                                                },
-                                            ));
+                                            node_list.update_existing(pa.from, |cn| {
+                                                cn.last_rssi = pa.rx_rssi;
+                                                cn.last_snr = pa.rx_snr;
+                                            });
+                                            return inbound_message(
+                                                &pa,
+                                                destination_of(pa.to),
+                                                message,
+                                                node_list,
+                                            );
                                         } else {
                                             warn!(
                                                 "Unable to decode text message to utf8 from ({})",
@@ -304,16 +493,11 @@ pub async fn process_packet(
                                     _ => {
                                         error!("{:#?}", de);
                                         return None;
-                                    } // PortNum::AdminApp => {}
-                                      // PortNum::WaypointApp => {}
-
-                                      // PortNum::PaxcounterApp => {}
-                                      // PortNum::StoreForwardApp => {}
-                                      // PortNum::RangeTestApp => {}
+                                    }
                                 }
                             }
                             mesh_packet::PayloadVariant::Encrypted(_) => {
-                                info!("Received an encrypted packet.");
+                                info!("Received an encrypted packet we have no key for.");
                                 return None;
                             }
                         }
@@ -330,35 +514,22 @@ pub async fn process_packet(
                         ni.clone().user.unwrap_or_else(User::default).id,
                         ni.num
                     );
-                    let mut cn = ComprehensiveNode::with_id(ni.num);
-                    cn.node_info = ni.clone();
-                    cn.last_seen = util::get_secs();
-                    cn.last_rssi = 0;
-                    cn.last_snr = ni.snr;
-
-                    return Some(PacketResponse::NodeUpdate(ni.num, Box::new(cn)));
+                    node_list.update(ni.num, |cn| {
+                        cn.heard_by.insert(radio);
+                        cn.last_snr = ni.snr;
+                        cn.node_info = ni;
+                        cn.last_seen = util::get_secs();
+                        cn.last_rssi = 0;
+                    });
+                    return None;
                 }
                 from_radio::PayloadVariant::Config(cfg) => {
                     info!("Receiving DeviceConfig from device.");
                     match cfg.payload_variant {
                         None => {}
                         Some(s) => {
-                            let mut f = DEVICE_CONFIG.write().await;
-                            if f.is_none() {
-                                *f = Some(DeviceConfiguration::default());
-                            }
-                            let mut devcfg = f.clone().unwrap();
-                            match s {
-                                PayloadVariant::Device(d) => devcfg.device = d,
-                                PayloadVariant::Position(p) => devcfg.position = p,
-                                PayloadVariant::Power(p) => devcfg.power = p,
-                                PayloadVariant::Network(n) => devcfg.network = n,
-                                PayloadVariant::Display(d) => devcfg.display = d,
-                                PayloadVariant::Lora(l) => devcfg.lora = l,
-                                PayloadVariant::Bluetooth(b) => devcfg.bluetooth = b,
-                            }
-                            devcfg.last_update = get_secs();
-                            *f = Some(devcfg);
+                            let mut f = DEVICE_CONFIGS.write().await;
+                            f.entry(radio).or_default().file_config(s);
                         }
                     }
                 }
@@ -392,35 +563,15 @@ pub async fn process_packet(
                 from_radio::PayloadVariant::Rebooted(v) => {
                     if v {
                         info!("Device has reported a reboot");
+                        return Some(PacketResponse::Rebooted);
                     }
                     return None;
                 }
                 from_radio::PayloadVariant::ModuleConfig(module_obj) => {
                     info!("Receiving ModulesConfig from device.");
                     if let Some(module) = module_obj.payload_variant {
-                        let mut f = DEVICE_CONFIG.write().await;
-                        if f.is_none() {
-                            *f = Some(DeviceConfiguration::default());
-                        }
-                        let mut devcfg = f.clone().unwrap();
-
-                        match module {
-                            mpv::Mqtt(o) => devcfg.mqtt = o,
-                            mpv::Serial(o) => devcfg.serial = o,
-                            mpv::ExternalNotification(o) => devcfg.external_notification = o,
-                            mpv::StoreForward(o) => devcfg.store_forward = o,
-                            mpv::RangeTest(o) => devcfg.range_test = o,
-                            mpv::Telemetry(o) => devcfg.telemetry = o,
-                            mpv::CannedMessage(o) => devcfg.canned_message = o,
-                            mpv::Audio(o) => devcfg.audio = o,
-                            mpv::RemoteHardware(o) => devcfg.remote_hardware = o,
-                            mpv::NeighborInfo(o) => devcfg.neighbor_info = o,
-                            mpv::AmbientLighting(o) => devcfg.ambient_lighting = o,
-                            mpv::DetectionSensor(o) => devcfg.detection_sensor = o,
-                            mpv::Paxcounter(o) => devcfg.paxcounter = o,
-                        }
-                        devcfg.last_update = get_secs();
-                        *f = Some(devcfg);
+                        let mut f = DEVICE_CONFIGS.write().await;
+                        f.entry(radio).or_default().file_module_config(module);
                     }
                 }
                 from_radio::PayloadVariant::ConfigCompleteId(u) => {
@@ -430,33 +581,15 @@ pub async fn process_packet(
                     );
                 }
                 from_radio::PayloadVariant::Channel(c) => {
-                    let mut channelpacket = c.clone();
-                    if let Some(mut channel) = channelpacket.settings.clone() {
-                        let mut f = DEVICE_CONFIG.write().await;
-                        if f.is_none() {
-                            *f = Some(DeviceConfiguration::default());
-                        }
-                        let mut devcfg = f.clone().unwrap();
-                        if c.index == 0 && channel.name.is_empty() && channel.psk == [1] {
-                            channel.name = "LongFast (Default)".to_string();
-                        };
-                        channelpacket.settings = Some(channel.clone());
-                        info!(
-                            "Storing channel config for {} (Ch: {})",
-                            channel.name, c.index
-                        );
-                        devcfg.channels.insert(c.index, channelpacket.clone());
-
-                        devcfg.last_update = get_secs();
-                        *f = Some(devcfg);
-                    }
+                    let mut f = DEVICE_CONFIGS.write().await;
+                    f.entry(radio).or_default().file_channel(c);
                 }
                 from_radio::PayloadVariant::QueueStatus(v) => {
                     debug!(
                         "QueueStatus: res {}/free {}/maxlen {}/mesh_packet_id {}",
                         v.res, v.free, v.maxlen, v.mesh_packet_id
                     );
-                    return None;
+                    return Some(PacketResponse::QueueStatus(v));
                 }
                 from_radio::PayloadVariant::XmodemPacket(v) => {
                     info!("{:#?}", v);
@@ -464,6 +597,10 @@ pub async fn process_packet(
                 }
                 from_radio::PayloadVariant::Metadata(v) => {
                     info!("Device firmware version: {}", v.firmware_version);
+                    let mut f = DEVICE_CONFIGS.write().await;
+                    let devcfg = f.entry(radio).or_default();
+                    devcfg.metadata = v;
+                    devcfg.last_update = get_secs();
                     return None;
                 }
                 from_radio::PayloadVariant::MqttClientProxyMessage(v) => {
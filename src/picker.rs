@@ -0,0 +1,247 @@
+//! The screen for choosing a radio, shown at startup when no connection was given and again
+//! whenever F10 is pressed to switch to a different one.
+//!
+//! It offers serial ports that look like Meshtastic hardware, the profiles from the config file,
+//! hosts we've connected to before, and a place to type an address.
+use crate::app::{centered_rect, Connection};
+use crate::config_file::{self, Profile};
+use crate::consts;
+use crate::mqtt::MqttSettings;
+use crate::theme::THEME;
+use crate::util;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{prelude::*, widgets::*};
+use serialport::SerialPortType;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+pub struct SerialDevice {
+    pub path: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+enum PickerEntry {
+    Serial(SerialDevice),
+    Profile(String, Connection),
+    Recent(String, u16),
+    TypeAddress,
+}
+
+impl PickerEntry {
+    fn label(&self) -> String {
+        match self {
+            PickerEntry::Serial(dev) => format!("serial   {}  {}", dev.path, dev.description),
+            PickerEntry::Profile(name, conn) => format!("profile  {name}  {}", describe(conn)),
+            PickerEntry::Recent(host, port) => format!("recent   {}", util::host_port(host, *port)),
+            PickerEntry::TypeAddress => "Type a TCP address or mqtt:// url...".to_string(),
+        }
+    }
+}
+
+pub enum PickerAction {
+    Connect(Connection),
+    Cancel,
+    Quit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectPicker {
+    entries: Vec<PickerEntry>,
+    list_state: ListState,
+    profiles: BTreeMap<String, Profile>,
+    /// Some while an address is being typed in.
+    input: Option<String>,
+    error: Option<String>,
+    /// Whether there's a connection to go back to if the user changes their mind.
+    can_cancel: bool,
+}
+
+fn describe(conn: &Connection) -> String {
+    match conn {
+        Connection::TCP(host, port) => util::host_port(host, *port),
+        Connection::Serial(device) => device.clone(),
+        Connection::Mqtt(settings) => settings.describe(),
+        _ => String::new(),
+    }
+}
+
+/// Serial ports whose USB ids belong to the boards and usb-serial chips Meshtastic radios use.
+pub fn detect_serial_ports() -> Vec<SerialDevice> {
+    let ports = match serialport::available_ports() {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Couldn't list serial ports: {e}");
+            return vec![];
+        }
+    };
+    ports
+        .into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb)
+                if consts::MESHTASTIC_USB_IDS.iter().any(|(vid, pid)| {
+                    *vid == usb.vid && (pid.is_none() || *pid == Some(usb.pid))
+                }) =>
+            {
+                Some(SerialDevice {
+                    path: port.port_name,
+                    description: format!(
+                        "{} ({:04x}:{:04x})",
+                        usb.product.unwrap_or_else(|| "USB serial".to_string()),
+                        usb.vid,
+                        usb.pid
+                    ),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+impl ConnectPicker {
+    pub fn new(profiles: BTreeMap<String, Profile>, can_cancel: bool) -> Self {
+        let mut picker = ConnectPicker {
+            profiles,
+            can_cancel,
+            ..Default::default()
+        };
+        picker.rescan();
+        picker
+    }
+
+    /// Rebuilds the list, picking up radios that were plugged in since we last looked.
+    pub fn rescan(&mut self) {
+        let mut entries: Vec<PickerEntry> = detect_serial_ports()
+            .into_iter()
+            .map(PickerEntry::Serial)
+            .collect();
+        for (name, profile) in self.profiles.iter() {
+            if let Ok(Some(conn)) = profile.connection() {
+                entries.push(PickerEntry::Profile(name.clone(), conn));
+            }
+        }
+        for (host, port) in config_file::recent_hosts() {
+            entries.push(PickerEntry::Recent(host, port));
+        }
+        entries.push(PickerEntry::TypeAddress);
+        self.entries = entries;
+        self.list_state.select(Some(0));
+    }
+
+    pub fn handle_key(&mut self, press: KeyEvent) -> Option<PickerAction> {
+        use KeyCode::*;
+        if let Some(input) = self.input.as_mut() {
+            match press.code {
+                Enter => {
+                    let parsed = match input.starts_with("mqtt://") {
+                        true => MqttSettings::parse(input).map(Connection::Mqtt),
+                        false => {
+                            util::parse_host(input).map(|(host, port)| Connection::TCP(host, port))
+                        }
+                    };
+                    match parsed {
+                        Ok(conn) => return Some(PickerAction::Connect(conn)),
+                        Err(e) => self.error = Some(e.to_string()),
+                    }
+                }
+                Char(c) => input.push(c),
+                Backspace => {
+                    input.pop();
+                }
+                Esc => {
+                    self.input = None;
+                    self.error = None;
+                }
+                _ => {}
+            }
+            return None;
+        }
+        match press.code {
+            Char('k') | Up => {
+                let i = self.list_state.selected().unwrap_or(0);
+                self.list_state.select(Some(i.saturating_sub(1)));
+            }
+            Char('j') | Down => {
+                let i = self.list_state.selected().unwrap_or(0);
+                self.list_state
+                    .select(Some(i.saturating_add(1).min(self.entries.len() - 1)));
+            }
+            Char('r') => self.rescan(),
+            Enter => {
+                let entry = self.entries.get(self.list_state.selected().unwrap_or(0))?;
+                let conn = match entry {
+                    PickerEntry::Serial(dev) => Connection::Serial(dev.path.clone()),
+                    PickerEntry::Profile(_, conn) => conn.clone(),
+                    PickerEntry::Recent(host, port) => Connection::TCP(host.clone(), *port),
+                    PickerEntry::TypeAddress => {
+                        self.input = Some(String::new());
+                        return None;
+                    }
+                };
+                return Some(PickerAction::Connect(conn));
+            }
+            Char('q') | Esc => match self.can_cancel {
+                true => return Some(PickerAction::Cancel),
+                false => return Some(PickerAction::Quit),
+            },
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Widget for &ConnectPicker {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::new()
+            .borders(Borders::ALL)
+            .title("Connect to a radio")
+            .title_alignment(Alignment::Center)
+            .border_set(symbols::border::DOUBLE)
+            .style(THEME.middle);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let [list_area, hint_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(2)]).areas(inner);
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .map(|e| ListItem::new(e.label()))
+            .collect();
+        let mut list_state = self.list_state.clone();
+        StatefulWidget::render(
+            List::new(items).highlight_style(THEME.message_selected),
+            list_area,
+            buf,
+            &mut list_state,
+        );
+
+        let back = match self.can_cancel {
+            true => "go back",
+            false => "quit",
+        };
+        let mut hints = vec![Line::from(format!(
+            "Enter: connect   r: look for serial ports again   Esc: {back}"
+        ))];
+        if let Some(e) = &self.error {
+            hints.push(Line::styled(e.clone(), THEME.warning_highlight));
+        }
+        Paragraph::new(hints).render(hint_area, buf);
+
+        if let Some(input) = &self.input {
+            let popup_area = centered_rect(area, 60, 25);
+            Clear.render(popup_area, buf);
+            Paragraph::new(input.clone())
+                .style(THEME.message_selected)
+                .block(
+                    Block::new()
+                        .borders(Borders::ALL)
+                        .title("host, host:port or mqtt://broker/msh/region")
+                        .title_alignment(Alignment::Center)
+                        .border_set(symbols::border::DOUBLE)
+                        .style(THEME.popup_window),
+                )
+                .render(popup_area, buf);
+        }
+    }
+}
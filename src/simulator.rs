@@ -0,0 +1,509 @@
+//! A pretend radio for demos and for working on the UI without any hardware attached.
+//!
+//! The simulator answers the config handshake the way a real device does (MyInfo, NodeInfo
+//! dump, channels, configs) and then keeps a small mesh busy with positions, telemetry,
+//! neighbor info and chatter.  Anything we send it is acked, and traceroutes get a reply.
+use crate::app::{ConnectionState, RadioId};
+use crate::ipc::IPCMessage;
+use crate::packet_handler::MessageEnvelope;
+use crate::util::{get_secs, set_connection_state};
+use anyhow::Result;
+use meshtastic::packet::PacketDestination;
+use meshtastic::protobufs::config::{device_config, lo_ra_config};
+use meshtastic::protobufs::*;
+use meshtastic::utils;
+use meshtastic::Message;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::{Duration, Instant};
+
+pub const SIM_NODE_NUM: u32 = 0x5eed_0001;
+const SIM_LAT: f64 = 40.7128_f64;
+const SIM_LON: f64 = -74.0060_f64;
+const EVENT_INTERVAL: Duration = Duration::from_secs(3);
+
+struct SimNode {
+    num: u32,
+    long_name: &'static str,
+    short_name: &'static str,
+    hops_away: u32,
+    battery_level: u32,
+    voltage: f32,
+    temperature: f32,
+    lat: f64,
+    lon: f64,
+}
+
+fn sim_mesh() -> Vec<SimNode> {
+    let names = [
+        ("Hilltop Repeater", "HILL", 0, 101, 5.0, 12.0),
+        ("Base Camp", "BASE", 1, 87, 4.1, 18.5),
+        ("Kayak 1", "KYK1", 1, 64, 3.9, 21.0),
+        ("Solar Relay North", "SOLN", 2, 93, 4.0, 15.2),
+        ("Rover", "ROVR", 2, 45, 3.7, 19.8),
+        ("Fire Tower", "FIRE", 3, 100, 4.2, 9.4),
+        ("Marina", "MRNA", 1, 78, 3.9, 22.3),
+    ];
+    names
+        .iter()
+        .enumerate()
+        .map(
+            |(i, (long_name, short_name, hops_away, battery_level, voltage, temperature))| {
+                SimNode {
+                    num: SIM_NODE_NUM + 0x100 + i as u32,
+                    long_name: *long_name,
+                    short_name: *short_name,
+                    hops_away: *hops_away,
+                    battery_level: *battery_level,
+                    voltage: *voltage,
+                    temperature: *temperature,
+                    lat: SIM_LAT + (i as f64 * 0.013) - 0.04,
+                    lon: SIM_LON + (i as f64 * 0.017) - 0.05,
+                }
+            },
+        )
+        .collect()
+}
+
+const CHATTER: [&str; 6] = [
+    "radio check, anyone copy?",
+    "copy you 5 by 5",
+    "heading back to base",
+    "battery swapped on the relay",
+    "weather is turning, stay safe out there",
+    "seq 42",
+];
+
+fn rand_below(max: u32) -> u32 {
+    utils::generate_rand_id::<u32>() % max.max(1)
+}
+
+pub(crate) fn radio_frame(payload_variant: from_radio::PayloadVariant) -> FromRadio {
+    FromRadio {
+        id: utils::generate_rand_id(),
+        payload_variant: Some(payload_variant),
+    }
+}
+
+pub(crate) fn sim_packet(from: u32, to: u32, hops_away: u32, data: Data) -> FromRadio {
+    let packet = MeshPacket {
+        from,
+        to,
+        channel: 0,
+        id: utils::generate_rand_id(),
+        rx_time: get_secs() as u32,
+        rx_snr: 10.0 - (rand_below(200) as f32 / 10.0),
+        rx_rssi: -40 - rand_below(80) as i32,
+        hop_limit: 3_u32.saturating_sub(hops_away),
+        hop_start: 3,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(data)),
+        ..Default::default()
+    };
+    radio_frame(from_radio::PayloadVariant::Packet(packet))
+}
+
+pub(crate) fn sim_data(portnum: PortNum, payload: Vec<u8>) -> Data {
+    Data {
+        portnum: portnum as i32,
+        payload,
+        ..Default::default()
+    }
+}
+
+fn user_for(num: u32, long_name: &str, short_name: &str) -> User {
+    User {
+        id: format!("!{:08x}", num),
+        long_name: long_name.to_string(),
+        short_name: short_name.to_string(),
+        hw_model: HardwareModel::Tbeam as i32,
+        ..Default::default()
+    }
+}
+
+fn position_for(node: &SimNode) -> Position {
+    // wander a little so the position updates actually look like something happened.
+    let jitter = (rand_below(200) as f64 - 100.0) * 0.00001;
+    Position {
+        latitude_i: ((node.lat + jitter) / 0.0000001) as i32,
+        longitude_i: ((node.lon + jitter) / 0.0000001) as i32,
+        altitude: 100 + rand_below(50) as i32,
+        time: get_secs() as u32,
+        ..Default::default()
+    }
+}
+
+fn device_metrics_for(node: &SimNode) -> DeviceMetrics {
+    DeviceMetrics {
+        battery_level: node.battery_level,
+        voltage: node.voltage - (rand_below(10) as f32 / 100.0),
+        channel_utilization: 5.0 + rand_below(150) as f32 / 10.0,
+        air_util_tx: 0.5 + rand_below(30) as f32 / 10.0,
+        ..Default::default()
+    }
+}
+
+/// Everything a device sends in response to `want_config_id`.
+fn config_dump(mesh: &[SimNode]) -> Vec<FromRadio> {
+    use from_radio::PayloadVariant as fpv;
+    let mut packets = vec![radio_frame(fpv::MyInfo(MyNodeInfo {
+        my_node_num: SIM_NODE_NUM,
+        ..Default::default()
+    }))];
+
+    packets.push(radio_frame(fpv::NodeInfo(NodeInfo {
+        num: SIM_NODE_NUM,
+        user: Some(user_for(SIM_NODE_NUM, "meshtui simulator", "SIM")),
+        position: Some(Position {
+            latitude_i: (SIM_LAT / 0.0000001) as i32,
+            longitude_i: (SIM_LON / 0.0000001) as i32,
+            altitude: 10,
+            ..Default::default()
+        }),
+        last_heard: get_secs() as u32,
+        ..Default::default()
+    })));
+    for node in mesh.iter() {
+        packets.push(radio_frame(fpv::NodeInfo(NodeInfo {
+            num: node.num,
+            user: Some(user_for(node.num, node.long_name, node.short_name)),
+            position: Some(position_for(node)),
+            snr: 6.25,
+            last_heard: get_secs().saturating_sub(rand_below(3600) as u64) as u32,
+            device_metrics: Some(device_metrics_for(node)),
+            hops_away: node.hops_away,
+            ..Default::default()
+        })));
+    }
+
+    packets.push(radio_frame(fpv::Metadata(DeviceMetadata {
+        firmware_version: "2.3.0.simulated".to_string(),
+        hw_model: HardwareModel::Tbeam as i32,
+        ..Default::default()
+    })));
+
+    packets.push(radio_frame(fpv::Channel(Channel {
+        index: 0,
+        settings: Some(ChannelSettings {
+            psk: vec![1],
+            ..Default::default()
+        }),
+        role: channel::Role::Primary as i32,
+    })));
+    packets.push(radio_frame(fpv::Channel(Channel {
+        index: 1,
+        settings: Some(ChannelSettings {
+            psk: vec![0x5e; 16],
+            name: "SimOps".to_string(),
+            ..Default::default()
+        }),
+        role: channel::Role::Secondary as i32,
+    })));
+
+    let configs = vec![
+        config::PayloadVariant::Device(config::DeviceConfig {
+            role: device_config::Role::Client as i32,
+            ..Default::default()
+        }),
+        config::PayloadVariant::Position(config::PositionConfig {
+            position_broadcast_secs: 900,
+            ..Default::default()
+        }),
+        config::PayloadVariant::Power(config::PowerConfig::default()),
+        config::PayloadVariant::Network(config::NetworkConfig::default()),
+        config::PayloadVariant::Display(config::DisplayConfig::default()),
+        config::PayloadVariant::Lora(config::LoRaConfig {
+            use_preset: true,
+            modem_preset: lo_ra_config::ModemPreset::LongFast as i32,
+            region: lo_ra_config::RegionCode::Us as i32,
+            hop_limit: 3,
+            tx_enabled: true,
+            ..Default::default()
+        }),
+        config::PayloadVariant::Bluetooth(config::BluetoothConfig::default()),
+    ];
+    for c in configs {
+        packets.push(radio_frame(fpv::Config(Config {
+            payload_variant: Some(c),
+        })));
+    }
+
+    let module_configs = vec![
+        module_config::PayloadVariant::Mqtt(module_config::MqttConfig::default()),
+        module_config::PayloadVariant::Telemetry(module_config::TelemetryConfig {
+            device_update_interval: 900,
+            ..Default::default()
+        }),
+        module_config::PayloadVariant::NeighborInfo(module_config::NeighborInfoConfig {
+            enabled: true,
+            update_interval: 900,
+            ..Default::default()
+        }),
+    ];
+    for m in module_configs {
+        packets.push(radio_frame(fpv::ModuleConfig(ModuleConfig {
+            payload_variant: Some(m),
+        })));
+    }
+    packets
+}
+
+/// Picks something for the mesh to do.  Roughly what a quiet rural mesh looks like, sped up.
+fn random_event(mesh: &[SimNode]) -> FromRadio {
+    let node = &mesh[rand_below(mesh.len() as u32) as usize];
+    match rand_below(6) {
+        0 => sim_packet(
+            node.num,
+            u32::MAX,
+            node.hops_away,
+            sim_data(PortNum::PositionApp, position_for(node).encode_to_vec()),
+        ),
+        1 => {
+            let telemetry = Telemetry {
+                time: get_secs() as u32,
+                variant: Some(telemetry::Variant::DeviceMetrics(device_metrics_for(node))),
+            };
+            sim_packet(
+                node.num,
+                u32::MAX,
+                node.hops_away,
+                sim_data(PortNum::TelemetryApp, telemetry.encode_to_vec()),
+            )
+        }
+        2 => {
+            let telemetry = Telemetry {
+                time: get_secs() as u32,
+                variant: Some(telemetry::Variant::EnvironmentMetrics(EnvironmentMetrics {
+                    temperature: node.temperature + (rand_below(20) as f32 / 10.0),
+                    relative_humidity: 40.0 + rand_below(300) as f32 / 10.0,
+                    barometric_pressure: 1005.0 + rand_below(200) as f32 / 10.0,
+                    ..Default::default()
+                })),
+            };
+            sim_packet(
+                node.num,
+                u32::MAX,
+                node.hops_away,
+                sim_data(PortNum::TelemetryApp, telemetry.encode_to_vec()),
+            )
+        }
+        3 => {
+            let neighbors = mesh
+                .iter()
+                .filter(|n| n.num != node.num && n.hops_away.abs_diff(node.hops_away) <= 1)
+                .map(|n| Neighbor {
+                    node_id: n.num,
+                    snr: 8.0 - (rand_below(160) as f32 / 10.0),
+                    last_rx_time: get_secs() as u32,
+                    ..Default::default()
+                })
+                .collect();
+            let info = NeighborInfo {
+                node_id: node.num,
+                last_sent_by_id: node.num,
+                node_broadcast_interval_secs: 900,
+                neighbors,
+            };
+            sim_packet(
+                node.num,
+                u32::MAX,
+                node.hops_away,
+                sim_data(PortNum::NeighborinfoApp, info.encode_to_vec()),
+            )
+        }
+        4 => {
+            let text = CHATTER[rand_below(CHATTER.len() as u32) as usize];
+            sim_packet(
+                node.num,
+                u32::MAX,
+                node.hops_away,
+                sim_data(PortNum::TextMessageApp, text.as_bytes().to_vec()),
+            )
+        }
+        _ => sim_packet(
+            node.num,
+            u32::MAX,
+            node.hops_away,
+            sim_data(
+                PortNum::NodeinfoApp,
+                user_for(node.num, node.long_name, node.short_name).encode_to_vec(),
+            ),
+        ),
+    }
+}
+
+fn routing_ack(request_id: u32) -> FromRadio {
+    let routing = Routing {
+        variant: Some(routing::Variant::ErrorReason(routing::Error::None as i32)),
+    };
+    let mut ack = sim_data(PortNum::RoutingApp, routing.encode_to_vec());
+    ack.request_id = request_id;
+    sim_packet(SIM_NODE_NUM, SIM_NODE_NUM, 0, ack)
+}
+
+fn queue_status(mesh_packet_id: u32) -> FromRadio {
+    radio_frame(from_radio::PayloadVariant::QueueStatus(QueueStatus {
+        res: 0,
+        free: 15,
+        maxlen: 16,
+        mesh_packet_id,
+    }))
+}
+
+/// The packet a radio would put on the air for a text message, id and all.
+fn text_packet(message: &MessageEnvelope) -> MeshPacket {
+    let to = match &message.destination {
+        PacketDestination::Local => SIM_NODE_NUM,
+        PacketDestination::Broadcast => u32::MAX,
+        PacketDestination::Node(id) => id.id(),
+    };
+    MeshPacket {
+        from: SIM_NODE_NUM,
+        to,
+        channel: message.channel.channel(),
+        id: utils::generate_rand_id(),
+        want_ack: true,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(sim_data(
+            PortNum::TextMessageApp,
+            message.message.clone().into_bytes(),
+        ))),
+        ..Default::default()
+    }
+}
+
+/// Queue status and ack for a packet we transmitted, plus a traceroute reply if it was one.
+fn respond_to_packet(mp: MeshPacket, mesh: &[SimNode]) -> Vec<FromRadio> {
+    let mut replies = vec![];
+    let id = if mp.id == 0 {
+        utils::generate_rand_id()
+    } else {
+        mp.id
+    };
+    replies.push(queue_status(id));
+    if mp.want_ack {
+        replies.push(routing_ack(id));
+    }
+    if let Some(mesh_packet::PayloadVariant::Decoded(de)) = mp.payload_variant {
+        if de.portnum() == PortNum::TracerouteApp {
+            if let Some(target) = mesh.iter().find(|n| n.num == mp.to) {
+                // route through whichever nodes sit between us and the target.
+                let route = mesh
+                    .iter()
+                    .filter(|n| n.hops_away < target.hops_away && n.hops_away > 0)
+                    .map(|n| n.num)
+                    .take(target.hops_away.saturating_sub(1) as usize)
+                    .collect();
+                let mut reply = sim_data(
+                    PortNum::TracerouteApp,
+                    RouteDiscovery { route }.encode_to_vec(),
+                );
+                reply.request_id = id;
+                replies.push(sim_packet(
+                    target.num,
+                    SIM_NODE_NUM,
+                    target.hops_away,
+                    reply,
+                ));
+            }
+        }
+    }
+    replies
+}
+
+/// Works out what the simulated mesh says back when we transmit something.
+fn respond_to(outbound: IPCMessage, mesh: &[SimNode]) -> Vec<FromRadio> {
+    let mut replies = vec![];
+    match outbound {
+        IPCMessage::SendMessage(message) => {
+            info!("Simulator transmitting text: {}", message.message);
+            replies.extend(respond_to_packet(text_packet(&message), mesh));
+            let node = &mesh[rand_below(mesh.len() as u32) as usize];
+            replies.push(sim_packet(
+                node.num,
+                u32::MAX,
+                node.hops_away,
+                sim_data(
+                    PortNum::TextMessageApp,
+                    format!("heard you: {}", message.message).into_bytes(),
+                ),
+            ));
+        }
+        IPCMessage::ToRadio(tr) => {
+            if let Some(to_radio::PayloadVariant::Packet(mp)) = tr.payload_variant {
+                replies.extend(respond_to_packet(mp, mesh));
+            }
+        }
+        _ => {
+            warn!("Unknown ipc message sent into simulator.");
+        }
+    }
+    replies
+}
+
+pub(crate) async fn simulated_loop(
+    radio: RadioId,
+    tx: Sender<IPCMessage>,
+    mut rx: Receiver<IPCMessage>,
+) -> Result<()> {
+    let mesh = sim_mesh();
+    let config_id: u32 = utils::generate_rand_id();
+    let mut outbox = config_dump(&mesh);
+    outbox.push(radio_frame(from_radio::PayloadVariant::ConfigCompleteId(
+        config_id,
+    )));
+    info!("Connected to simulated meshtastic node!");
+    set_connection_state(radio, ConnectionState::Connected).await;
+
+    let mut next_event = Instant::now() + EVENT_INTERVAL;
+    loop {
+        for fr in outbox.drain(..) {
+            if let Err(e) = tx.send(IPCMessage::FromRadio(fr)).await {
+                error!("Couldn't send FromRadio packet to mpsc: {e}");
+            }
+        }
+        tokio::select! {
+            Some(outbound) = rx.recv() => {
+                outbox.extend(respond_to(outbound, &mesh));
+            }
+            _ = tokio::time::sleep_until(next_event) => {
+                outbox.push(random_event(&mesh));
+                next_event = Instant::now() + EVENT_INTERVAL;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meshtastic::types::MeshChannel;
+
+    #[test]
+    fn a_sent_text_is_acked_under_the_id_it_was_queued_with() {
+        let message = MessageEnvelope {
+            timestamp: 0,
+            source: None,
+            destination: PacketDestination::Broadcast,
+            channel: MeshChannel::new(0).unwrap(),
+            message: "hello".to_string(),
+            rx_rssi: 0,
+            rx_snr: 0.0,
+        };
+        let replies = respond_to(IPCMessage::SendMessage(message), &sim_mesh());
+        let queued = replies.iter().find_map(|fr| match &fr.payload_variant {
+            Some(from_radio::PayloadVariant::QueueStatus(qs)) => Some(qs.mesh_packet_id),
+            _ => None,
+        });
+        let acked = replies.iter().find_map(|fr| match &fr.payload_variant {
+            Some(from_radio::PayloadVariant::Packet(mp)) => match &mp.payload_variant {
+                Some(mesh_packet::PayloadVariant::Decoded(de))
+                    if de.portnum() == PortNum::RoutingApp =>
+                {
+                    Some(de.request_id)
+                }
+                _ => None,
+            },
+            _ => None,
+        });
+        assert!(queued.is_some_and(|id| id != 0));
+        assert_eq!(queued, acked);
+    }
+}
@@ -0,0 +1,195 @@
+//! The client side of Store & Forward.
+//!
+//! A Store & Forward router is a node that keeps the text messages it hears and replays them to
+//! anyone who asks.  We keep track of the routers we hear from, and whenever our radio
+//! (re)connects we ask one of them for whatever was sent while we were away.  Replayed messages
+//! come back as `StoreForwardApp` packets and are turned into ordinary text messages by
+//! `process_packet`.
+use crate::ipc::IPCMessage;
+use crate::util::{self, get_secs};
+use crate::{config_file, consts};
+use anyhow::Result;
+use meshtastic::protobufs::store_and_forward::{self, RequestResponse};
+use meshtastic::protobufs::{
+    mesh_packet, to_radio, Data, MeshPacket, PortNum, StoreAndForward, ToRadio,
+};
+use meshtastic::Message;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct RouterInfo {
+    pub last_heard: u64,
+    /// Seconds between heartbeats, if it has sent one.
+    pub heartbeat_period: u32,
+    pub secondary: bool,
+    pub stats: Option<store_and_forward::Statistics>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StoreForwardClient {
+    pub routers: BTreeMap<u32, RouterInfo>,
+    /// When the active radio was last connected, carried over from earlier runs.
+    last_connected: u64,
+    last_saved: u64,
+    was_connected: bool,
+    /// Set when we (re)connect to when we were last connected, until a router has been asked
+    /// for what we missed since then.
+    history_owed: Option<u64>,
+}
+
+fn request(
+    router: u32,
+    rr: RequestResponse,
+    variant: Option<store_and_forward::Variant>,
+) -> ToRadio {
+    let sf = StoreAndForward {
+        rr: rr as i32,
+        variant,
+    };
+    let mesh_packet = MeshPacket {
+        to: router,
+        want_ack: true,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+            portnum: i32::from(PortNum::StoreForwardApp),
+            payload: sf.encode_to_vec(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    ToRadio {
+        payload_variant: Some(to_radio::PayloadVariant::Packet(mesh_packet)),
+    }
+}
+
+impl StoreForwardClient {
+    pub fn new() -> Self {
+        StoreForwardClient {
+            last_connected: config_file::last_connected(),
+            ..Default::default()
+        }
+    }
+
+    /// The router we'd ask, preferring primaries and then whichever we heard from last.
+    pub fn best_router(&self) -> Option<(u32, &RouterInfo)> {
+        self.routers
+            .iter()
+            .max_by_key(|(_, r)| (!r.secondary, r.last_heard))
+            .map(|(id, r)| (*id, r))
+    }
+
+    /// Called on every tick with whether the active radio is connected.
+    pub async fn tend(&mut self, connected: bool) {
+        if connected && !self.was_connected {
+            self.history_owed = Some(self.last_connected);
+            self.request_missed().await;
+        }
+        self.was_connected = connected;
+        if !connected {
+            return;
+        }
+        let now = get_secs();
+        self.last_connected = now;
+        if now.saturating_sub(self.last_saved) >= consts::LAST_CONNECTED_SAVE_SECS {
+            self.last_saved = now;
+            if let Err(e) = config_file::remember_last_connected(now) {
+                warn!("Couldn't save when we were last connected: {e}");
+            }
+        }
+    }
+
+    /// Notes what a router told us about itself.
+    pub async fn handle(&mut self, from: u32, sf: StoreAndForward) {
+        let rr = sf.rr();
+        let from_router = matches!(
+            rr,
+            RequestResponse::RouterHeartbeat
+                | RequestResponse::RouterPing
+                | RequestResponse::RouterPong
+                | RequestResponse::RouterStats
+                | RequestResponse::RouterHistory
+                | RequestResponse::RouterBusy
+                | RequestResponse::RouterError
+        );
+        if !from_router {
+            return;
+        }
+        let is_new = !self.routers.contains_key(&from);
+        let router = self.routers.entry(from).or_default();
+        router.last_heard = get_secs();
+        match sf.variant {
+            Some(store_and_forward::Variant::Heartbeat(hb)) => {
+                router.heartbeat_period = hb.period;
+                router.secondary = hb.secondary != 0;
+            }
+            Some(store_and_forward::Variant::Stats(stats)) => router.stats = Some(stats),
+            Some(store_and_forward::Variant::History(history)) => info!(
+                "Store & Forward router !{from:x} is sending {} messages from the last {} minutes",
+                history.history_messages, history.window
+            ),
+            _ => {}
+        }
+        match rr {
+            RequestResponse::RouterBusy => warn!("Store & Forward router !{from:x} is busy."),
+            RequestResponse::RouterError => {
+                warn!("Store & Forward router !{from:x} reported an error.")
+            }
+            _ => {}
+        }
+        if is_new {
+            info!("Found a Store & Forward router, !{from:x}");
+            self.request_missed().await;
+        }
+    }
+
+    /// Asks for what we missed, once per connection, as soon as we know a router to ask.
+    async fn request_missed(&mut self) {
+        let since = match self.history_owed {
+            Some(s) => s,
+            None => return,
+        };
+        if self.best_router().is_none() {
+            return;
+        }
+        // with no idea when we were last around, the router's own default window will do.
+        let minutes = match since {
+            0 => None,
+            s => Some(get_secs().saturating_sub(s).div_ceil(60) as u32),
+        };
+        if let Err(e) = self.request_history(minutes).await {
+            error!("Couldn't ask for missed messages: {e}");
+        }
+        self.history_owed = None;
+    }
+
+    /// Asks the best router for its history, going back `minutes` or its default window.
+    pub async fn request_history(&self, minutes: Option<u32>) -> Result<()> {
+        let router = match self.best_router() {
+            Some((id, _)) => id,
+            None => {
+                info!("There's no Store & Forward router on the mesh that we know of.");
+                return Ok(());
+            }
+        };
+        let variant = minutes.map(|window| {
+            store_and_forward::Variant::History(store_and_forward::History {
+                window,
+                ..Default::default()
+            })
+        });
+        info!("Asking Store & Forward router !{router:x} for its history");
+        let packet = request(router, RequestResponse::ClientHistory, variant);
+        util::send_to_radio(IPCMessage::ToRadio(packet)).await
+    }
+
+    pub async fn request_stats(&self) -> Result<()> {
+        let router = match self.best_router() {
+            Some((id, _)) => id,
+            None => {
+                info!("There's no Store & Forward router on the mesh that we know of.");
+                return Ok(());
+            }
+        };
+        let packet = request(router, RequestResponse::ClientStats, None);
+        util::send_to_radio(IPCMessage::ToRadio(packet)).await
+    }
+}
@@ -1,6 +1,7 @@
 use crate::app::Mode;
 use crate::theme::THEME;
-use crate::{DEVICE_CONFIG, PAGE_SIZE};
+use crate::{util, PAGE_SIZE};
+use meshtastic::protobufs::config::LoRaConfig;
 use meshtastic::protobufs::Channel;
 use ratatui::{prelude::*, widgets::*};
 use strum::Display;
@@ -16,19 +17,19 @@ pub struct ChannelsTab {
     row_index: usize,
     page_size: u16,
     table_contents: Vec<Channel>,
+    lora: LoRaConfig,
 }
 
 impl ChannelsTab {
     pub async fn run(&mut self) {
         self.page_size = *PAGE_SIZE.read().await;
 
-        // get channel list and release lock asap
-        {
-            let dc = DEVICE_CONFIG.read().await;
-            if let Some(config) = dc.clone() {
-                self.table_contents = config.channels.values().cloned().collect();
-            }
-        }
+        // whichever radio is active now, or the node we're administering; empty until it has
+        // sent its channels.
+        (self.table_contents, self.lora) = match util::shown_config().await {
+            Some(config) => (config.channels.values().cloned().collect(), config.lora),
+            None => (vec![], LoRaConfig::default()),
+        };
         self.table_contents.sort_by(|a, b| a.index.cmp(&b.index));
     }
     pub fn escape(&mut self) -> Mode {
@@ -62,9 +63,14 @@ impl Widget for ChannelsTab {
             .iter()
             .map(|c| {
                 let settings = c.clone().settings.unwrap();
+                let mut name = util::channel_name(&self.lora, &settings);
+                // the apps mark an unnamed primary channel on the default key this way.
+                if c.index == 0 && settings.name.is_empty() && settings.psk == [1] {
+                    name.push_str(" (Default)");
+                }
                 Row::new(vec![
                     format!("{:02}", c.index),
-                    format!("{}", settings.name),
+                    name,
                     format!("{}/{}", settings.uplink_enabled, settings.downlink_enabled),
                 ])
             })
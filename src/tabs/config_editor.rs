@@ -0,0 +1,236 @@
+//! Editing config sections one field at a time, shared by the device and module config tabs.
+//!
+//! The config messages all have different shapes, so rather than a form for each we go through
+//! their json form: every top-level field gets a row, and what's typed for it is read as json
+//! (or taken as-is for text fields) and put back into the message.  Enums are their numbers.
+//!
+//! Fields that `config_check` has a warning about are flagged in the list, the warnings for the
+//! section are listed under it, and the editor shows the ones what's being typed would cause.
+use crate::admin;
+use crate::app::{centered_rect, DeviceConfiguration};
+use crate::config_check::{self, Warning};
+use crate::theme::THEME;
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{prelude::*, widgets::*};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+pub(crate) type Fields = Vec<(String, Value)>;
+
+pub(crate) fn fields<T: Serialize>(section: &T) -> Fields {
+    match serde_json::to_value(section) {
+        Ok(Value::Object(map)) => map.into_iter().collect(),
+        _ => vec![],
+    }
+}
+
+/// `section` with `field` set to `input`.
+pub(crate) fn edited<T: Serialize + DeserializeOwned>(
+    section: &T,
+    field: &str,
+    input: &str,
+) -> Result<T> {
+    let mut value = serde_json::to_value(section)?;
+    let slot = match value.get_mut(field) {
+        Some(s) => s,
+        None => bail!("There's no {field} field."),
+    };
+    *slot = match slot {
+        Value::String(_) => Value::String(input.to_string()),
+        _ => match serde_json::from_str(input.trim()) {
+            Ok(v) => v,
+            Err(e) => bail!("{input} isn't a value: {e}"),
+        },
+    };
+    match serde_json::from_value(value) {
+        Ok(section) => Ok(section),
+        Err(e) => bail!("{field} can't be {input}: {e}"),
+    }
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Says whose config this is, when it isn't the active radio's.
+pub(crate) fn title(target: Option<u32>, config: &DeviceConfiguration) -> String {
+    let node = match target {
+        Some(n) => n,
+        None => return "Configuration".to_string(),
+    };
+    let mut title = format!("Configuration of !{node:08x}");
+    if !config.owner.long_name.is_empty() {
+        title.push_str(&format!(" ({})", config.owner.long_name));
+    }
+    if !config.metadata.firmware_version.is_empty() {
+        title.push_str(&format!(", firmware {}", config.metadata.firmware_version));
+    }
+    if config.last_update == 0 {
+        title.push_str(", waiting for it to answer");
+    } else if admin::passkey_expired(&config.session_passkey, config.passkey_received) {
+        // older firmware doesn't hand out keys at all, and takes edits on the admin channel.
+        title.push_str(", no current session key: 2.5+ firmware will refuse edits, F2 for one");
+    }
+    title
+}
+
+/// F2 asks the remote node for its config again, F3 goes back to showing the active radio.
+pub(crate) async fn function_key(target: Option<u32>, num: u8) {
+    match (num, target) {
+        (2, Some(node)) => {
+            if let Err(e) = admin::request_remote_config(node).await {
+                error!("Couldn't ask !{node:x} for its config: {e}");
+            }
+        }
+        (3, Some(_)) => {
+            crate::util::set_admin_target(None);
+            info!("Showing the active radio's config again.");
+        }
+        _ => {}
+    }
+}
+
+/// The warnings `field` would have if `candidate` were sent.
+pub(crate) fn edit_warnings(
+    section: &str,
+    field: &str,
+    candidate: &DeviceConfiguration,
+) -> Vec<String> {
+    config_check::check(candidate)
+        .into_iter()
+        .filter(|w| w.is_about(section, field))
+        .map(|w| w.message)
+        .collect()
+}
+
+pub(crate) enum EditorAction {
+    Save(String, String),
+    Cancel,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FieldEditor {
+    pub field: String,
+    pub input: String,
+    pub error: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+impl FieldEditor {
+    pub fn new(field: &str, value: &Value) -> Self {
+        FieldEditor {
+            field: field.to_string(),
+            input: show(value),
+            error: None,
+            warnings: vec![],
+        }
+    }
+
+    pub fn handle_key(&mut self, press: KeyEvent) -> Option<EditorAction> {
+        match press.code {
+            KeyCode::Esc => return Some(EditorAction::Cancel),
+            KeyCode::Enter => {
+                return Some(EditorAction::Save(self.field.clone(), self.input.clone()))
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, area: Rect, buf: &mut Buffer) {
+        let popup_area = centered_rect(area, 60, 30);
+        let mut lines = vec![
+            Line::styled(self.input.clone(), THEME.message_selected),
+            Line::from(""),
+            Line::from("Enter: send to the node   Esc: cancel"),
+        ];
+        if let Some(e) = &self.error {
+            lines.push(Line::styled(e.clone(), THEME.warning_highlight));
+        }
+        for warning in self.warnings.iter() {
+            lines.push(Line::styled(
+                format!("! {warning}"),
+                THEME.warning_highlight,
+            ));
+        }
+        Clear.render(popup_area, buf);
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .title(format!("Edit {}", self.field))
+                    .title_alignment(Alignment::Center)
+                    .border_set(symbols::border::DOUBLE)
+                    .style(THEME.popup_window),
+            )
+            .render(popup_area, buf);
+    }
+}
+
+/// The field list, with a box of the section's warnings under it when it has any.
+pub(crate) fn render_fields(
+    fields: &Fields,
+    selected: usize,
+    section: &str,
+    warnings: &[Warning],
+    block: Block,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let here: Vec<&Warning> = warnings.iter().filter(|w| w.in_section(section)).collect();
+    let elsewhere = warnings.len() - here.len();
+    let mut lines: Vec<Line> = here
+        .iter()
+        .map(|w| Line::styled(format!("! {}", w.message), THEME.warning_highlight))
+        .collect();
+    if elsewhere > 0 {
+        lines.push(Line::from(format!(
+            "{elsewhere} more warning(s) in other sections"
+        )));
+    }
+    let [list_area, warning_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),
+            match lines.is_empty() {
+                true => Constraint::Length(0),
+                false => Constraint::Length(lines.len() as u16 * 2 + 2),
+            },
+        ])
+        .areas(area);
+    let items: Vec<ListItem> = fields
+        .iter()
+        .map(|(name, value)| {
+            let text = format!("{name}: {}", show(value));
+            match here.iter().any(|w| w.is_about(section, name)) {
+                true => ListItem::new(format!("! {text}")).style(THEME.warning_highlight),
+                false => ListItem::new(text),
+            }
+        })
+        .collect();
+    let mut state = ListState::default().with_selected(Some(selected));
+    StatefulWidget::render(
+        List::new(items)
+            .block(block.clone())
+            .highlight_style(THEME.message_selected),
+        list_area,
+        buf,
+        &mut state,
+    );
+    if !lines.is_empty() {
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block.title("Warnings"))
+            .render(warning_area, buf);
+    }
+}
@@ -0,0 +1,143 @@
+//! Comparing a saved config file with the node the config tabs are showing, and applying it.
+//!
+//! Nothing is sent until the diff has been on screen and Enter is pressed on it.
+use crate::app::{centered_rect, DeviceConfiguration};
+use crate::config_backup;
+use crate::theme::THEME;
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent};
+use meshtastic::protobufs::{admin_message, Position, User};
+use ratatui::{prelude::*, widgets::*};
+
+/// What the file would change, and the messages that change it.
+#[derive(Debug, Clone, Default)]
+struct Preview {
+    lines: Vec<String>,
+    messages: Vec<admin_message::PayloadVariant>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ImportView {
+    node: u32,
+    devcfg: DeviceConfiguration,
+    owner: User,
+    position: Option<Position>,
+    path: String,
+    preview: Option<Preview>,
+    scroll: usize,
+    error: Option<String>,
+}
+
+pub(crate) enum ImportAction {
+    Apply(Vec<admin_message::PayloadVariant>),
+    Close,
+}
+
+impl ImportView {
+    pub fn new(
+        node: u32,
+        devcfg: DeviceConfiguration,
+        owner: User,
+        position: Option<Position>,
+    ) -> Self {
+        ImportView {
+            node,
+            devcfg,
+            owner,
+            position,
+            ..Default::default()
+        }
+    }
+
+    fn load(&self) -> Result<Preview> {
+        let contents = std::fs::read_to_string(self.path.trim())?;
+        let file = config_backup::parse(&contents)?;
+        let lines = config_backup::diff(&self.devcfg, &self.owner, self.position.as_ref(), &file);
+        let messages = config_backup::changes(&self.devcfg, &self.owner, &file)?;
+        Ok(Preview { lines, messages })
+    }
+
+    pub fn handle_key(&mut self, press: KeyEvent) -> Option<ImportAction> {
+        use KeyCode::*;
+        if let Some(preview) = &self.preview {
+            match press.code {
+                Esc => self.preview = None,
+                Up | Char('k') => self.scroll = self.scroll.saturating_sub(1),
+                Down | Char('j') => {
+                    self.scroll = (self.scroll + 1).min(preview.lines.len().saturating_sub(1))
+                }
+                // an empty diff still has the channel list to send, but nothing worth a reboot.
+                Enter if preview.lines.is_empty() => return Some(ImportAction::Close),
+                Enter => return Some(ImportAction::Apply(preview.messages.clone())),
+                _ => {}
+            }
+            return None;
+        }
+        match press.code {
+            Esc => return Some(ImportAction::Close),
+            Char(c) => self.path.push(c),
+            Backspace => {
+                self.path.pop();
+            }
+            Enter => match self.load() {
+                Ok(preview) => {
+                    self.error = None;
+                    self.scroll = 0;
+                    self.preview = Some(preview);
+                }
+                Err(e) => self.error = Some(e.to_string()),
+            },
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, area: Rect, buf: &mut Buffer) {
+        let popup_area = centered_rect(area, 70, 70);
+        let mut lines: Vec<Line> = match &self.preview {
+            None => vec![
+                Line::from("The YAML file to compare with this node:"),
+                Line::styled(self.path.clone(), THEME.message_selected),
+                Line::from(""),
+                Line::from("Enter: show what it would change   Esc: close"),
+            ],
+            Some(preview) if preview.lines.is_empty() => vec![
+                Line::from(format!("!{:08x} already matches {}.", self.node, self.path)),
+                Line::from(""),
+                Line::from("Enter or Esc: close"),
+            ],
+            Some(preview) => {
+                let mut lines: Vec<Line> = preview
+                    .lines
+                    .iter()
+                    .skip(self.scroll)
+                    .map(|l| Line::from(l.clone()))
+                    .collect();
+                lines.insert(
+                    0,
+                    Line::from(format!(
+                        "{} change(s).  Up/Down: scroll   Enter: apply them   Esc: back out",
+                        preview.lines.len()
+                    )),
+                );
+                lines.insert(1, Line::from(""));
+                lines
+            }
+        };
+        if let Some(e) = &self.error {
+            lines.push(Line::styled(e.clone(), THEME.warning_highlight));
+        }
+        Clear.render(popup_area, buf);
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .title(format!("Import a config into !{:08x}", self.node))
+                    .title_alignment(Alignment::Center)
+                    .border_set(symbols::border::DOUBLE)
+                    .style(THEME.popup_window),
+            )
+            .render(popup_area, buf);
+    }
+}
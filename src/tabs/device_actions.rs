@@ -0,0 +1,469 @@
+//! The device actions menu: setting the owner and position (typed in or picked on a map),
+//! setting the clock, and rebooting, shutting down and resetting whichever node the config tabs
+//! are showing.
+use crate::app::centered_rect;
+use crate::consts;
+use crate::theme::THEME;
+use crate::util;
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent};
+use meshtastic::protobufs::{admin_message, Position, User};
+use ratatui::widgets::canvas::{Canvas, Map, MapResolution};
+use ratatui::{prelude::*, widgets::*};
+use strum::{Display, EnumIter, FromRepr, IntoEnumIterator};
+
+#[derive(Debug, Clone, Copy, Default, Display, EnumIter, FromRepr, PartialEq, Eq)]
+pub enum DeviceAction {
+    #[default]
+    #[strum(to_string = "Set owner")]
+    SetOwner,
+    #[strum(to_string = "Set fixed position")]
+    SetFixedPosition,
+    #[strum(to_string = "Set fixed position on a map")]
+    PickFixedPosition,
+    #[strum(to_string = "Clear fixed position")]
+    RemoveFixedPosition,
+    #[strum(to_string = "Set the clock from this computer")]
+    SetClock,
+    Reboot,
+    #[strum(to_string = "Reboot into OTA update mode")]
+    RebootOta,
+    #[strum(to_string = "Shut down")]
+    Shutdown,
+    #[strum(to_string = "Reset the node database")]
+    NodedbReset,
+    #[strum(to_string = "Factory reset")]
+    FactoryReset,
+}
+
+impl DeviceAction {
+    /// These either lose something or leave the node needing a hand to get it going again,
+    /// which is a long way to go for a node on a hilltop.
+    fn needs_confirmation(self) -> bool {
+        matches!(
+            self,
+            DeviceAction::RebootOta
+                | DeviceAction::Shutdown
+                | DeviceAction::NodedbReset
+                | DeviceAction::FactoryReset
+        )
+    }
+
+    fn form_labels(self) -> &'static [&'static str] {
+        match self {
+            DeviceAction::SetOwner => &["Long name", "Short name", "Licensed (yes/no)"],
+            DeviceAction::SetFixedPosition => &["Latitude", "Longitude", "Altitude (m)"],
+            _ => &[],
+        }
+    }
+
+    /// The admin message for actions that don't need anything typed in.
+    fn admin_message(self) -> Option<admin_message::PayloadVariant> {
+        use admin_message::PayloadVariant::*;
+        Some(match self {
+            DeviceAction::RemoveFixedPosition => RemoveFixedPosition(true),
+            DeviceAction::Reboot => RebootSeconds(consts::DEVICE_ACTION_DELAY_SECS),
+            DeviceAction::RebootOta => RebootOtaSeconds(consts::DEVICE_ACTION_DELAY_SECS),
+            DeviceAction::Shutdown => ShutdownSeconds(consts::DEVICE_ACTION_DELAY_SECS),
+            DeviceAction::NodedbReset => NodedbReset(1),
+            DeviceAction::FactoryReset => FactoryReset(1),
+            _ => return None,
+        })
+    }
+}
+
+fn to_degrees(i: i32) -> f64 {
+    i as f64 * consts::GPS_PRECISION_FACTOR as f64
+}
+
+/// A world map with a crosshair, for pinning a node somewhere without looking up coordinates.
+#[derive(Debug, Clone)]
+struct MapPicker {
+    lat: f64,
+    lon: f64,
+    /// Degrees of longitude the map is wide; it's half that tall.
+    span: f64,
+}
+
+enum PickerAction {
+    Picked(f64, f64),
+    Close,
+}
+
+impl MapPicker {
+    /// Starts zoomed in on where the node is now, or on the whole world if we don't know.
+    fn new(position: Option<&Position>) -> Self {
+        match position.filter(|p| p.latitude_i != 0 || p.longitude_i != 0) {
+            Some(p) => MapPicker {
+                lat: to_degrees(p.latitude_i),
+                lon: to_degrees(p.longitude_i),
+                span: consts::MAP_PICKER_NEAR_SPAN,
+            },
+            None => MapPicker {
+                lat: 0.0,
+                lon: 0.0,
+                span: 360.0,
+            },
+        }
+    }
+
+    fn handle_key(&mut self, press: KeyEvent) -> Option<PickerAction> {
+        use KeyCode::*;
+        let step = self.span / consts::MAP_PICKER_STEPS;
+        match press.code {
+            Esc => return Some(PickerAction::Close),
+            Enter => return Some(PickerAction::Picked(self.lat, self.lon)),
+            Up | Char('k') => self.lat = (self.lat + step).min(90.0),
+            Down | Char('j') => self.lat = (self.lat - step).max(-90.0),
+            // longitude wraps around the back of the world.
+            Left | Char('h') => self.lon = (self.lon - step + 540.0).rem_euclid(360.0) - 180.0,
+            Right | Char('l') => self.lon = (self.lon + step + 540.0).rem_euclid(360.0) - 180.0,
+            Char('+') | Char('=') => self.span = (self.span / 2.0).max(consts::MAP_PICKER_MIN_SPAN),
+            Char('-') => self.span = (self.span * 2.0).min(360.0),
+            _ => {}
+        }
+        None
+    }
+
+    fn render(&self, area: Rect, buf: &mut Buffer, title: String) {
+        let color = THEME.popup_window.fg.unwrap_or(Color::Reset);
+        let (lat, lon) = (self.lat, self.lon);
+        Clear.render(area, buf);
+        Canvas::default()
+            .block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .title_bottom(format!(
+                        " {lat:.5}, {lon:.5}   arrows: move  +/-: zoom  Enter: use this spot  \
+                         Esc: back "
+                    ))
+                    .title_alignment(Alignment::Center)
+                    .border_set(symbols::border::DOUBLE)
+                    .style(THEME.popup_window),
+            )
+            .marker(symbols::Marker::Braille)
+            .x_bounds([lon - self.span / 2.0, lon + self.span / 2.0])
+            .y_bounds([lat - self.span / 4.0, lat + self.span / 4.0])
+            .paint(move |ctx| {
+                ctx.draw(&Map {
+                    resolution: MapResolution::High,
+                    color,
+                });
+                ctx.layer();
+                ctx.print(lon, lat, Line::styled("+", THEME.warning_highlight));
+            })
+            .render(area, buf);
+    }
+}
+
+/// The few fields the owner and fixed position actions ask for, kept as typed until Enter.
+#[derive(Debug, Clone, Default)]
+struct Form {
+    action: DeviceAction,
+    values: Vec<String>,
+    field: usize,
+}
+
+impl Form {
+    fn new(action: DeviceAction, owner: &User, position: Option<&Position>) -> Self {
+        let values = match action {
+            DeviceAction::SetOwner => vec![
+                owner.long_name.clone(),
+                owner.short_name.clone(),
+                match owner.is_licensed {
+                    true => "yes".to_string(),
+                    false => "no".to_string(),
+                },
+            ],
+            DeviceAction::SetFixedPosition => match position {
+                Some(p) => vec![
+                    format!("{:.7}", to_degrees(p.latitude_i)),
+                    format!("{:.7}", to_degrees(p.longitude_i)),
+                    p.altitude.to_string(),
+                ],
+                None => vec![String::new(), String::new(), "0".to_string()],
+            },
+            _ => vec![],
+        };
+        Form {
+            action,
+            values,
+            field: 0,
+        }
+    }
+
+    fn value(&self, field: usize) -> &str {
+        self.values[field].trim()
+    }
+
+    fn build(&self) -> Result<admin_message::PayloadVariant> {
+        use admin_message::PayloadVariant::*;
+        match self.action {
+            DeviceAction::SetOwner => {
+                let long_name = self.value(0);
+                if long_name.is_empty() || long_name.len() > consts::OWNER_LONG_NAME_MAX {
+                    bail!(
+                        "The long name should be 1 to {} bytes.",
+                        consts::OWNER_LONG_NAME_MAX
+                    );
+                }
+                // the firmware's limits are in bytes, so an emoji uses up the whole short name.
+                let short_name = self.value(1);
+                if short_name.is_empty() || short_name.len() > consts::OWNER_SHORT_NAME_MAX {
+                    bail!(
+                        "The short name should be 1 to {} bytes.",
+                        consts::OWNER_SHORT_NAME_MAX
+                    );
+                }
+                let is_licensed = match self.value(2).to_lowercase().as_str() {
+                    "yes" | "y" | "true" => true,
+                    "no" | "n" | "false" => false,
+                    _ => bail!("Licensed should be yes or no."),
+                };
+                Ok(SetOwner(User {
+                    long_name: long_name.to_string(),
+                    short_name: short_name.to_string(),
+                    is_licensed,
+                    ..Default::default()
+                }))
+            }
+            DeviceAction::SetFixedPosition => {
+                let latitude = match self.value(0).parse::<f64>() {
+                    Ok(l) if (-90.0..=90.0).contains(&l) => l,
+                    _ => bail!("Latitude should be a number of degrees between -90 and 90."),
+                };
+                let longitude = match self.value(1).parse::<f64>() {
+                    Ok(l) if (-180.0..=180.0).contains(&l) => l,
+                    _ => bail!("Longitude should be a number of degrees between -180 and 180."),
+                };
+                let altitude = match self.value(2).parse::<i32>() {
+                    Ok(a) => a,
+                    Err(_) => bail!("Altitude should be a whole number of meters."),
+                };
+                Ok(SetFixedPosition(Position {
+                    latitude_i: (latitude * 1e7).round() as i32,
+                    longitude_i: (longitude * 1e7).round() as i32,
+                    altitude,
+                    time: util::get_secs() as u32,
+                    ..Default::default()
+                }))
+            }
+            action => bail!("{action} doesn't take any settings."),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ActionsMenu {
+    /// The node the actions go to, whose id has to be typed to confirm one.
+    node: u32,
+    /// What the node has now, to start the forms off with.
+    owner: User,
+    position: Option<Position>,
+    selected: usize,
+    form: Option<Form>,
+    map: Option<MapPicker>,
+    confirming: Option<DeviceAction>,
+    input: String,
+    error: Option<String>,
+}
+
+pub(crate) enum MenuAction {
+    Send(DeviceAction, admin_message::PayloadVariant),
+    SetClock,
+    Close,
+}
+
+impl ActionsMenu {
+    pub fn new(node: u32, owner: User, position: Option<Position>) -> Self {
+        ActionsMenu {
+            node,
+            owner,
+            position,
+            ..Default::default()
+        }
+    }
+
+    fn selected_action(&self) -> DeviceAction {
+        DeviceAction::from_repr(self.selected).unwrap_or_default()
+    }
+
+    fn handle_form_key(&mut self, press: KeyEvent) -> Option<MenuAction> {
+        use KeyCode::*;
+        let form = self.form.as_mut()?;
+        let field_count = form.values.len();
+        match press.code {
+            Esc => self.form = None,
+            Up | BackTab => form.field = (form.field + field_count - 1) % field_count,
+            Down | Tab => form.field = (form.field + 1) % field_count,
+            Char(c) => form.values[form.field].push(c),
+            Backspace => {
+                form.values[form.field].pop();
+            }
+            Enter => match form.build() {
+                Ok(message) => return Some(MenuAction::Send(form.action, message)),
+                Err(e) => self.error = Some(e.to_string()),
+            },
+            _ => {}
+        }
+        None
+    }
+
+    fn handle_confirm_key(&mut self, action: DeviceAction, press: KeyEvent) -> Option<MenuAction> {
+        use KeyCode::*;
+        match press.code {
+            Esc => {
+                self.confirming = None;
+                self.input.clear();
+            }
+            Char(c) => self.input.push(c),
+            Backspace => {
+                self.input.pop();
+            }
+            Enter => match (util::parse_node_id(&self.input), action.admin_message()) {
+                (Ok(id), Some(message)) if id == self.node => {
+                    return Some(MenuAction::Send(action, message))
+                }
+                _ => self.error = Some(format!("That isn't !{:08x}.", self.node)),
+            },
+            _ => {}
+        }
+        None
+    }
+
+    pub fn handle_key(&mut self, press: KeyEvent) -> Option<MenuAction> {
+        use KeyCode::*;
+        if self.form.is_some() {
+            return self.handle_form_key(press);
+        }
+        if let Some(map) = self.map.as_mut() {
+            match map.handle_key(press) {
+                // the typed form takes it from there, so the altitude can be filled in.
+                Some(PickerAction::Picked(lat, lon)) => {
+                    let mut form = Form::new(
+                        DeviceAction::SetFixedPosition,
+                        &self.owner,
+                        self.position.as_ref(),
+                    );
+                    form.values[0] = format!("{lat:.7}");
+                    form.values[1] = format!("{lon:.7}");
+                    form.field = 2;
+                    self.form = Some(form);
+                    self.map = None;
+                }
+                Some(PickerAction::Close) => self.map = None,
+                None => {}
+            }
+            return None;
+        }
+        if let Some(action) = self.confirming {
+            return self.handle_confirm_key(action, press);
+        }
+        let action_count = DeviceAction::iter().count();
+        match press.code {
+            Esc | Char('q') => return Some(MenuAction::Close),
+            Up | Char('k') => self.selected = (self.selected + action_count - 1) % action_count,
+            Down | Char('j') => self.selected = (self.selected + 1) % action_count,
+            Enter => {
+                self.error = None;
+                let action = self.selected_action();
+                if action == DeviceAction::SetClock {
+                    return Some(MenuAction::SetClock);
+                }
+                if action == DeviceAction::PickFixedPosition {
+                    self.map = Some(MapPicker::new(self.position.as_ref()));
+                    return None;
+                }
+                if !action.form_labels().is_empty() {
+                    self.form = Some(Form::new(action, &self.owner, self.position.as_ref()));
+                } else if action.needs_confirmation() {
+                    self.confirming = Some(action);
+                } else {
+                    return action
+                        .admin_message()
+                        .map(|message| MenuAction::Send(action, message));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn form_lines(form: &Form) -> Vec<Line> {
+        let mut lines: Vec<Line> = form
+            .action
+            .form_labels()
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let text = format!("{label:>20}: {}", form.values[i]);
+                match i == form.field {
+                    true => Line::styled(text, THEME.message_selected),
+                    false => Line::from(text),
+                }
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from(
+            "Up/Down: pick a field   Enter: send to the node   Esc: back",
+        ));
+        lines
+    }
+
+    pub fn render(&self, area: Rect, buf: &mut Buffer) {
+        if let Some(map) = &self.map {
+            let title = format!("Set fixed position for !{:08x}", self.node);
+            map.render(centered_rect(area, 80, 80), buf, title);
+            return;
+        }
+        let popup_area = centered_rect(area, 50, 60);
+        let mut lines: Vec<Line> = match &self.form {
+            Some(form) => ActionsMenu::form_lines(form),
+            None => DeviceAction::iter()
+                .map(|action| {
+                    let text = format!(" {action} ");
+                    match action as usize == self.selected {
+                        true => Line::styled(text, THEME.message_selected),
+                        false => Line::from(text),
+                    }
+                })
+                .collect(),
+        };
+        if self.form.is_none() {
+            lines.push(Line::from(""));
+            match self.confirming {
+                Some(action) => {
+                    lines.push(Line::from(format!(
+                        "{action} can't be undone from here.  Type !{:08x} and hit Enter",
+                        self.node
+                    )));
+                    lines.push(Line::from("to go ahead, or Esc to back out."));
+                    lines.push(Line::styled(self.input.clone(), THEME.message_selected));
+                }
+                None => lines.push(Line::from(
+                    "Up/Down: pick an action   Enter: do it   Esc: close",
+                )),
+            }
+        }
+        if let Some(e) = &self.error {
+            lines.push(Line::styled(e.clone(), THEME.warning_highlight));
+        }
+        let title = match &self.form {
+            Some(form) => format!("{} for !{:08x}", form.action, self.node),
+            None => format!("Device actions for !{:08x}", self.node),
+        };
+        Clear.render(popup_area, buf);
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .title_alignment(Alignment::Center)
+                    .border_set(symbols::border::DOUBLE)
+                    .style(THEME.popup_window),
+            )
+            .render(popup_area, buf);
+    }
+}
@@ -1,6 +1,18 @@
+use crate::admin;
+use crate::airtime::Budget;
+use crate::config_backup;
+use crate::ipc::IPCMessage;
 use crate::app::{DeviceConfiguration, Mode};
+use crate::config_check::{self, Warning};
+use crate::tabs::config_editor::{self, EditorAction, FieldEditor, Fields};
+use crate::tabs::config_import::{ImportAction, ImportView};
+use crate::tabs::device_actions::{ActionsMenu, MenuAction};
 use crate::theme::THEME;
-use crate::DEVICE_CONFIG;
+use crate::util;
+use anyhow::Result;
+use crossterm::event::KeyEvent;
+use meshtastic::protobufs::config::PayloadVariant;
+use meshtastic::protobufs::{NodeInfo, Position, User};
 use ratatui::{prelude::*, widgets::*};
 use strum::{Display, EnumIter, FromRepr, IntoEnumIterator};
 
@@ -9,6 +21,16 @@ pub struct ConfigTab {
     row_index: usize,
     pub device_config: DeviceConfiguration,
     tab: InnerConfigTabs,
+    /// The remote node we're showing, if it isn't the active radio.
+    target: Option<u32>,
+    my_node_id: u32,
+    /// Our own node, from the node list.
+    me: NodeInfo,
+    fields: Fields,
+    warnings: Vec<Warning>,
+    editor: Option<FieldEditor>,
+    actions: Option<ActionsMenu>,
+    import: Option<ImportView>,
 }
 
 #[derive(Debug, Clone, Copy, Default, Display, EnumIter, FromRepr, PartialEq, Eq)]
@@ -38,20 +60,172 @@ impl InnerConfigTabs {
         let tab = self;
         format!(" {tab} ")
     }
+    /// The section's name in config files and warnings.
+    fn section(self) -> &'static str {
+        match self {
+            InnerConfigTabs::Device => "device",
+            InnerConfigTabs::Bluetooth => "bluetooth",
+            InnerConfigTabs::Display => "display",
+            InnerConfigTabs::LoRa => "lora",
+            InnerConfigTabs::Network => "network",
+            InnerConfigTabs::Position => "position",
+            InnerConfigTabs::Power => "power",
+        }
+    }
 }
 
 impl ConfigTab {
-    pub async fn run(&mut self) {
-        let dc = DEVICE_CONFIG.read().await;
-        if let Some(config) = dc.clone() {
-            self.device_config = config.clone();
+    pub async fn run(&mut self, my_node_id: u32, me: Option<NodeInfo>) {
+        self.my_node_id = my_node_id;
+        self.me = me.unwrap_or_default();
+        self.target = util::admin_target();
+        self.device_config = util::shown_config().await.unwrap_or_default();
+        self.warnings = config_check::check(&self.device_config);
+        self.fields = self.section_fields();
+        self.row_index = self.row_index.min(self.fields.len().saturating_sub(1));
+    }
+
+    fn section_fields(&self) -> Fields {
+        let c = &self.device_config;
+        match self.tab {
+            InnerConfigTabs::Device => config_editor::fields(&c.device),
+            InnerConfigTabs::Bluetooth => config_editor::fields(&c.bluetooth),
+            InnerConfigTabs::Display => config_editor::fields(&c.display),
+            InnerConfigTabs::LoRa => config_editor::fields(&c.lora),
+            InnerConfigTabs::Network => config_editor::fields(&c.network),
+            InnerConfigTabs::Position => config_editor::fields(&c.position),
+            InnerConfigTabs::Power => config_editor::fields(&c.power),
+        }
+    }
+
+    /// The current section with one field changed, ready to send.
+    fn edited_section(&self, field: &str, input: &str) -> Result<PayloadVariant> {
+        let c = &self.device_config;
+        Ok(match self.tab {
+            InnerConfigTabs::Device => {
+                PayloadVariant::Device(config_editor::edited(&c.device, field, input)?)
+            }
+            InnerConfigTabs::Bluetooth => {
+                PayloadVariant::Bluetooth(config_editor::edited(&c.bluetooth, field, input)?)
+            }
+            InnerConfigTabs::Display => {
+                PayloadVariant::Display(config_editor::edited(&c.display, field, input)?)
+            }
+            InnerConfigTabs::LoRa => {
+                PayloadVariant::Lora(config_editor::edited(&c.lora, field, input)?)
+            }
+            InnerConfigTabs::Network => {
+                PayloadVariant::Network(config_editor::edited(&c.network, field, input)?)
+            }
+            InnerConfigTabs::Position => {
+                PayloadVariant::Position(config_editor::edited(&c.position, field, input)?)
+            }
+            InnerConfigTabs::Power => {
+                PayloadVariant::Power(config_editor::edited(&c.power, field, input)?)
+            }
+        })
+    }
+
+    /// While the editor is up it gets every key, so typing a `q` doesn't quit.
+    pub fn is_editing(&self) -> bool {
+        self.editor.is_some() || self.actions.is_some() || self.import.is_some()
+    }
+
+    pub async fn handle_key(&mut self, press: KeyEvent) {
+        if let Some(view) = self.import.as_mut() {
+            match view.handle_key(press) {
+                Some(ImportAction::Apply(messages)) => {
+                    self.import = None;
+                    info!("Applying {} config change(s) to the node.", messages.len());
+                    admin::send_paced(self.target, self.my_node_id, messages);
+                }
+                Some(ImportAction::Close) => self.import = None,
+                None => {}
+            }
+            return;
+        }
+        if let Some(menu) = self.actions.as_mut() {
+            match menu.handle_key(press) {
+                Some(MenuAction::Send(action, message)) => {
+                    self.actions = None;
+                    match admin::send(self.target, self.my_node_id, message).await {
+                        Ok(()) => info!("Sent {action} to the node."),
+                        Err(e) => error!("Couldn't send {action} to the node: {e}"),
+                    }
+                }
+                Some(MenuAction::SetClock) => {
+                    self.actions = None;
+                    self.set_clock().await;
+                }
+                Some(MenuAction::Close) => self.actions = None,
+                None => {}
+            }
+            return;
+        }
+        let action = match self.editor.as_mut() {
+            Some(editor) => editor.handle_key(press),
+            None => return,
+        };
+        match action {
+            Some(EditorAction::Cancel) => self.editor = None,
+            Some(EditorAction::Save(field, input)) => {
+                let result = match self.edited_section(&field, &input) {
+                    Ok(section) => admin::set_config(self.target, self.my_node_id, section).await,
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(()) => {
+                        info!("Sent the new {field} to the node.");
+                        self.editor = None;
+                    }
+                    Err(e) => {
+                        if let Some(editor) = self.editor.as_mut() {
+                            editor.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+            None => self.preview(),
+        }
+    }
+
+    /// Shows the editor what the value typed so far would get warned about.
+    fn preview(&mut self) {
+        let editor = match self.editor.as_ref() {
+            Some(e) => e,
+            None => return,
+        };
+        let mut candidate = self.device_config.clone();
+        if let Ok(edited) = self.edited_section(&editor.field, &editor.input) {
+            candidate.file_config(edited);
+        }
+        let warnings = config_editor::edit_warnings(self.tab.section(), &editor.field, &candidate);
+        if let Some(editor) = self.editor.as_mut() {
+            editor.warnings = warnings;
+        }
+    }
+
+    async fn set_clock(&self) {
+        if self.target.is_some() {
+            warn!("Only the radio we're connected to can have its clock set.");
+            return;
+        }
+        let packet = util::time_packet(self.my_node_id, self.me.position.clone());
+        match util::send_to_radio(IPCMessage::ToRadio(packet)).await {
+            Ok(()) => info!("Set the radio's clock to ours."),
+            Err(e) => error!("Couldn't set the radio's clock: {e}"),
         }
     }
 
     pub fn escape(&mut self) -> Mode {
         Mode::Exiting
     }
-    pub fn enter_key(&mut self) {}
+    pub fn enter_key(&mut self) {
+        if let Some((field, value)) = self.fields.get(self.row_index) {
+            self.editor = Some(FieldEditor::new(field, value));
+        }
+        self.preview();
+    }
     pub fn prev_row(&mut self) {
         self.row_index = self.row_index.saturating_sub(1);
     }
@@ -59,8 +233,44 @@ impl ConfigTab {
     pub fn next_row(&mut self) {
         self.row_index = self.row_index.saturating_add(1);
     }
-    pub fn function_key(&mut self, _num: u8) {
-        {}
+    /// A remote node's owner came with its config; ours is in the node list.
+    fn owner_and_position(&self) -> (User, Option<Position>) {
+        match self.target {
+            Some(_) => (self.device_config.owner.clone(), None),
+            None => (
+                self.me.user.clone().unwrap_or_default(),
+                self.me.position.clone(),
+            ),
+        }
+    }
+
+    /// Saves the config on show to a file in the current directory, named for the node.
+    fn export(&self, node: u32) -> Result<String> {
+        let (owner, position) = self.owner_and_position();
+        let yaml = config_backup::export(&self.device_config, &owner, position.as_ref())?;
+        let path = format!("meshtui-config-{node:08x}-{}.yaml", util::get_secs());
+        std::fs::write(&path, yaml)?;
+        Ok(path)
+    }
+
+    pub async fn function_key(&mut self, num: u8) {
+        match (num, self.target.unwrap_or(self.my_node_id)) {
+            (4..=6, 0) => warn!("We don't know our own node number yet."),
+            (4, node) => {
+                let (owner, position) = self.owner_and_position();
+                self.actions = Some(ActionsMenu::new(node, owner, position));
+            }
+            (5, node) => match self.export(node) {
+                Ok(path) => info!("Saved the config of !{node:08x} to {path}"),
+                Err(e) => error!("Couldn't save the config of !{node:08x}: {e}"),
+            },
+            (6, node) => {
+                let (owner, position) = self.owner_and_position();
+                let devcfg = self.device_config.clone();
+                self.import = Some(ImportView::new(node, devcfg, owner, position));
+            }
+            _ => config_editor::function_key(self.target, num).await,
+        }
     }
     pub fn render_tabs(&self, area: Rect, buf: &mut Buffer) {
         let titles = InnerConfigTabs::iter().map(InnerConfigTabs::title);
@@ -74,9 +284,11 @@ impl ConfigTab {
     }
     pub fn left(&mut self) {
         self.tab = self.tab.prev();
+        self.row_index = 0;
     }
     pub fn right(&mut self) {
         self.tab = self.tab.next();
+        self.row_index = 0;
     }
 }
 
@@ -96,7 +308,13 @@ impl Widget for ConfigTab {
             .margin(1)
             .areas(area);
 
-        let device_block = default_inner_block.clone().title("Configuration");
+        let device_block = default_inner_block
+            .clone()
+            .title(config_editor::title(self.target, &self.device_config));
+        let [shown, editable] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(crate::FIFTY_FIFTY.iter())
+            .areas(field);
 
         self.render_tabs(bar, buf);
         let pg = match self.tab {
@@ -108,6 +326,50 @@ impl Widget for ConfigTab {
             InnerConfigTabs::Position => format!("{:#?}", self.device_config.position),
             InnerConfigTabs::Power => format!("{:#?}", self.device_config.power),
         };
-        Paragraph::new(pg).block(device_block).render(field, buf);
+        let shown = match self.tab {
+            InnerConfigTabs::LoRa => {
+                let summary = Budget::new(&self.device_config).summary();
+                let [dump, budget] = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Min(1),
+                        Constraint::Length(summary.len() as u16 + 2),
+                    ])
+                    .areas(shown);
+                let lines: Vec<Line> = summary
+                    .into_iter()
+                    .map(|(text, over)| match over {
+                        true => Line::styled(text, THEME.warning_highlight),
+                        false => Line::from(text),
+                    })
+                    .collect();
+                Paragraph::new(lines)
+                    .wrap(Wrap { trim: false })
+                    .block(default_inner_block.clone().title("Airtime"))
+                    .render(budget, buf);
+                dump
+            }
+            _ => shown,
+        };
+        Paragraph::new(pg).block(device_block).render(shown, buf);
+        let edit_block = default_inner_block.title("Enter to edit");
+        config_editor::render_fields(
+            &self.fields,
+            self.row_index,
+            self.tab.section(),
+            &self.warnings,
+            edit_block,
+            editable,
+            buf,
+        );
+        if let Some(editor) = &self.editor {
+            editor.render(area, buf);
+        }
+        if let Some(menu) = &self.actions {
+            menu.render(area, buf);
+        }
+        if let Some(view) = &self.import {
+            view.render(area, buf);
+        }
     }
 }
@@ -3,16 +3,20 @@ use crate::packet_handler::MessageEnvelope;
 use crate::theme::THEME;
 use crate::{consts, PAGE_SIZE};
 use meshtastic::protobufs::User;
-use circular_buffer::CircularBuffer;
 use itertools::Itertools;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
 
-use crate::util::get_channel_from_id;
+use crate::util::get_channel_name_from_id;
 use ratatui::{prelude::*, widgets::*};
 use time::OffsetDateTime;
 
+/// Shared with anything else that serves messages, so there's only ever one copy.
+pub type MessageLog = Arc<RwLock<VecDeque<MessageEnvelope>>>;
+
 #[derive(Debug, Clone, Default)]
 pub struct MessagesTab {
-    pub messages: CircularBuffer<{ consts::MAX_MSG_RETENTION }, MessageEnvelope>,
+    pub messages: MessageLog,
     table_state: TableState,
     editing: bool,
     pub page_size: u16,
@@ -33,7 +37,7 @@ impl MessagesTab {
         let i = match self.table_state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.messages.len().saturating_sub(1)
+                    self.messages.read().unwrap().len().saturating_sub(1)
                 } else {
                     i.saturating_sub(1)
                 }
@@ -59,7 +63,7 @@ impl MessagesTab {
     pub fn next_row(&mut self) {
         let i = match self.table_state.selected() {
             Some(i) => {
-                if i >= self.messages.len().saturating_sub(1) {
+                if i >= self.messages.read().unwrap().len().saturating_sub(1) {
                     0
                 } else {
                     i.saturating_add(1)
@@ -70,10 +74,11 @@ impl MessagesTab {
         self.table_state.select(Some(i));
     }
     pub fn next_page(&mut self) {
+        let message_count = self.messages.read().unwrap().len();
         let i = match self.table_state.selected() {
             Some(i) => {
-                if i >= self.messages.len().saturating_sub(self.page_size as usize) {
-                    self.messages.len().saturating_sub(1)
+                if i >= message_count.saturating_sub(self.page_size as usize) {
+                    message_count.saturating_sub(1)
                 } else {
                     i.saturating_add(self.page_size as usize)
                 }
@@ -111,7 +116,8 @@ impl Widget for MessagesTab {
             Constraint::Min(50),
         ];
 
-        let mut message_list = self.messages.to_vec();
+        let mut message_list: Vec<MessageEnvelope> =
+            self.messages.read().unwrap().iter().cloned().collect();
         message_list.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
         message_list.reverse();
         let rows = message_list
@@ -120,10 +126,8 @@ impl Widget for MessagesTab {
                 let dt =
                     OffsetDateTime::from_unix_timestamp(message.clone().timestamp as i64).unwrap();
 
-                let channel_name = match get_channel_from_id(message.channel.channel()) {
-                    Some(s) => s.settings.unwrap().name.clone(),
-                    None => "".to_string(),
-                };
+                let channel_name =
+                    get_channel_name_from_id(message.channel.channel()).unwrap_or_default();
 
                 let destination_str = format!("{} (Ch. {})", channel_name, &message.channel);
 
@@ -1,6 +1,12 @@
+use crate::admin;
 use crate::app::{DeviceConfiguration, Mode};
+use crate::config_check::{self, Warning};
+use crate::tabs::config_editor::{self, EditorAction, FieldEditor, Fields};
 use crate::theme::THEME;
-use crate::DEVICE_CONFIG;
+use crate::util;
+use anyhow::Result;
+use crossterm::event::KeyEvent;
+use meshtastic::protobufs::module_config::PayloadVariant as mpv;
 use ratatui::{prelude::*, widgets::*};
 use strum::{Display, EnumIter, FromRepr, IntoEnumIterator};
 
@@ -9,6 +15,12 @@ pub struct ModulesConfigTab {
     row_index: usize,
     tab: ModuleTabs,
     device_config: DeviceConfiguration,
+    /// The remote node we're showing, if it isn't the active radio.
+    target: Option<u32>,
+    my_node_id: u32,
+    fields: Fields,
+    warnings: Vec<Warning>,
+    editor: Option<FieldEditor>,
 }
 
 #[derive(Debug, Clone, Copy, Default, Display, EnumIter, FromRepr, PartialEq, Eq)]
@@ -44,20 +56,150 @@ impl ModuleTabs {
         let tab = self;
         format!(" {tab} ")
     }
+    /// The section's name in config files and warnings.
+    fn section(self) -> &'static str {
+        match self {
+            ModuleTabs::Mqtt => "mqtt",
+            ModuleTabs::Serial => "serial",
+            ModuleTabs::ExternalNotification => "external_notification",
+            ModuleTabs::StoreForward => "store_forward",
+            ModuleTabs::RangeTest => "range_test",
+            ModuleTabs::Telemetry => "telemetry",
+            ModuleTabs::CannedMessage => "canned_message",
+            ModuleTabs::Audio => "audio",
+            ModuleTabs::RemoteHardware => "remote_hardware",
+            ModuleTabs::NeighborInfo => "neighbor_info",
+            ModuleTabs::AmbientLighting => "ambient_lighting",
+            ModuleTabs::DetectionSensor => "detection_sensor",
+            ModuleTabs::Paxcounter => "paxcounter",
+        }
+    }
 }
 
 impl ModulesConfigTab {
-    pub async fn run(&mut self) {
-        let dc = DEVICE_CONFIG.read().await;
-        if let Some(config) = dc.clone() {
-            self.device_config = config.clone();
+    pub async fn run(&mut self, my_node_id: u32) {
+        self.my_node_id = my_node_id;
+        self.target = util::admin_target();
+        self.device_config = util::shown_config().await.unwrap_or_default();
+        self.warnings = config_check::check(&self.device_config);
+        self.fields = self.module_fields();
+        self.row_index = self.row_index.min(self.fields.len().saturating_sub(1));
+    }
+
+    fn module_fields(&self) -> Fields {
+        let c = &self.device_config;
+        match self.tab {
+            ModuleTabs::Mqtt => config_editor::fields(&c.mqtt),
+            ModuleTabs::Serial => config_editor::fields(&c.serial),
+            ModuleTabs::ExternalNotification => config_editor::fields(&c.external_notification),
+            ModuleTabs::StoreForward => config_editor::fields(&c.store_forward),
+            ModuleTabs::RangeTest => config_editor::fields(&c.range_test),
+            ModuleTabs::Telemetry => config_editor::fields(&c.telemetry),
+            ModuleTabs::CannedMessage => config_editor::fields(&c.canned_message),
+            ModuleTabs::Audio => config_editor::fields(&c.audio),
+            ModuleTabs::RemoteHardware => config_editor::fields(&c.remote_hardware),
+            ModuleTabs::NeighborInfo => config_editor::fields(&c.neighbor_info),
+            ModuleTabs::AmbientLighting => config_editor::fields(&c.ambient_lighting),
+            ModuleTabs::DetectionSensor => config_editor::fields(&c.detection_sensor),
+            ModuleTabs::Paxcounter => config_editor::fields(&c.paxcounter),
+        }
+    }
+
+    /// The current module's config with one field changed, ready to send.
+    fn edited_module(&self, field: &str, input: &str) -> Result<mpv> {
+        use config_editor::edited;
+        let c = &self.device_config;
+        Ok(match self.tab {
+            ModuleTabs::Mqtt => mpv::Mqtt(edited(&c.mqtt, field, input)?),
+            ModuleTabs::Serial => mpv::Serial(edited(&c.serial, field, input)?),
+            ModuleTabs::ExternalNotification => {
+                mpv::ExternalNotification(edited(&c.external_notification, field, input)?)
+            }
+            ModuleTabs::StoreForward => {
+                mpv::StoreForward(edited(&c.store_forward, field, input)?)
+            }
+            ModuleTabs::RangeTest => mpv::RangeTest(edited(&c.range_test, field, input)?),
+            ModuleTabs::Telemetry => mpv::Telemetry(edited(&c.telemetry, field, input)?),
+            ModuleTabs::CannedMessage => {
+                mpv::CannedMessage(edited(&c.canned_message, field, input)?)
+            }
+            ModuleTabs::Audio => mpv::Audio(edited(&c.audio, field, input)?),
+            ModuleTabs::RemoteHardware => {
+                mpv::RemoteHardware(edited(&c.remote_hardware, field, input)?)
+            }
+            ModuleTabs::NeighborInfo => {
+                mpv::NeighborInfo(edited(&c.neighbor_info, field, input)?)
+            }
+            ModuleTabs::AmbientLighting => {
+                mpv::AmbientLighting(edited(&c.ambient_lighting, field, input)?)
+            }
+            ModuleTabs::DetectionSensor => {
+                mpv::DetectionSensor(edited(&c.detection_sensor, field, input)?)
+            }
+            ModuleTabs::Paxcounter => mpv::Paxcounter(edited(&c.paxcounter, field, input)?),
+        })
+    }
+
+    /// While the editor is up it gets every key, so typing a `q` doesn't quit.
+    pub fn is_editing(&self) -> bool {
+        self.editor.is_some()
+    }
+
+    pub async fn handle_key(&mut self, press: KeyEvent) {
+        let action = match self.editor.as_mut() {
+            Some(editor) => editor.handle_key(press),
+            None => return,
+        };
+        match action {
+            Some(EditorAction::Cancel) => self.editor = None,
+            Some(EditorAction::Save(field, input)) => {
+                let result = match self.edited_module(&field, &input) {
+                    Ok(module) => {
+                        admin::set_module_config(self.target, self.my_node_id, module).await
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(()) => {
+                        info!("Sent the new {field} to the node.");
+                        self.editor = None;
+                    }
+                    Err(e) => {
+                        if let Some(editor) = self.editor.as_mut() {
+                            editor.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+            None => self.preview(),
+        }
+    }
+
+    /// Shows the editor what the value typed so far would get warned about.
+    fn preview(&mut self) {
+        let editor = match self.editor.as_ref() {
+            Some(e) => e,
+            None => return,
+        };
+        let mut candidate = self.device_config.clone();
+        if let Ok(edited) = self.edited_module(&editor.field, &editor.input) {
+            candidate.file_module_config(edited);
+        }
+        let warnings = config_editor::edit_warnings(self.tab.section(), &editor.field, &candidate);
+        if let Some(editor) = self.editor.as_mut() {
+            editor.warnings = warnings;
         }
     }
 
     pub fn escape(&mut self) -> Mode {
         Mode::Exiting
     }
-    pub fn enter_key(&mut self) {}
+    pub fn enter_key(&mut self) {
+        if let Some((field, value)) = self.fields.get(self.row_index) {
+            self.editor = Some(FieldEditor::new(field, value));
+        }
+        self.preview();
+    }
     pub fn prev_row(&mut self) {
         self.row_index = self.row_index.saturating_sub(1);
     }
@@ -65,8 +207,8 @@ impl ModulesConfigTab {
     pub fn next_row(&mut self) {
         self.row_index = self.row_index.saturating_add(1);
     }
-    pub fn function_key(&mut self, _num: u8) {
-        {}
+    pub async fn function_key(&mut self, num: u8) {
+        config_editor::function_key(self.target, num).await;
     }
     pub fn render_tabs(&self, area: Rect, buf: &mut Buffer) {
         let titles = ModuleTabs::iter().map(ModuleTabs::title);
@@ -80,9 +222,11 @@ impl ModulesConfigTab {
     }
     pub fn left(&mut self) {
         self.tab = self.tab.prev();
+        self.row_index = 0;
     }
     pub fn right(&mut self) {
         self.tab = self.tab.next();
+        self.row_index = 0;
     }
 }
 
@@ -102,7 +246,13 @@ impl Widget for ModulesConfigTab {
             .margin(1)
             .areas(area);
 
-        let device_block = default_inner_block.clone().title("Configuration");
+        let device_block = default_inner_block
+            .clone()
+            .title(config_editor::title(self.target, &self.device_config));
+        let [shown, editable] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(crate::FIFTY_FIFTY.iter())
+            .areas(field);
 
         self.render_tabs(bar, buf);
         let pg = match self.tab {
@@ -146,6 +296,19 @@ impl Widget for ModulesConfigTab {
                 format!("{:#?}", self.device_config.paxcounter)
             }
         };
-        Paragraph::new(pg).block(device_block).render(field, buf);
+        Paragraph::new(pg).block(device_block).render(shown, buf);
+        let edit_block = default_inner_block.title("Enter to edit");
+        config_editor::render_fields(
+            &self.fields,
+            self.row_index,
+            self.tab.section(),
+            &self.warnings,
+            edit_block,
+            editable,
+            buf,
+        );
+        if let Some(editor) = &self.editor {
+            editor.render(area, buf);
+        }
     }
 }
@@ -1,5 +1,6 @@
-use crate::app::{MenuTabs, Mode, Preferences};
+use crate::app::{MenuTabs, Mode, Preferences, RadioId};
 use crate::consts::GPS_PRECISION_FACTOR;
+use crate::node_store::NodeStore;
 use crate::theme::THEME;
 use crate::util::get_secs;
 use crate::PREFERENCES;
@@ -7,16 +8,11 @@ use crate::{consts, util, PAGE_SIZE};
 use geoutils::Location;
 use itertools::Itertools;
 
-use meshtastic::protobufs;
-use meshtastic::protobufs::to_radio::PayloadVariant::Packet;
-use meshtastic::protobufs::PortNum::TracerouteApp;
 use meshtastic::protobufs::*;
 use pretty_duration::pretty_duration;
 use ratatui::{prelude::*, widgets::*};
-use std::collections::HashMap;
-use std::ops::Div;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::time::Duration;
-use circular_buffer::CircularBuffer;
 use strum::Display;
 
 use crate::ipc::IPCMessage;
@@ -32,16 +28,19 @@ pub enum DisplayMode {
 #[derive(Debug, Clone, Default)]
 pub struct NodesTab {
     //row_index: usize,
-    pub node_list: HashMap<u32, ComprehensiveNode>,
+    pub node_list: NodeStore,
     table_state: TableState,
-    pub table_contents: Vec<ComprehensiveNode>,
+    pub table_contents: Vec<u32>,
+    table_version: Option<u64>,
     pub scrollbar_state: ScrollbarState,
     pub my_node_id: u32,
     prefs: Preferences,
     pub display_mode: DisplayMode,
     pub selected_node_id: u32,
     pub page_size: u16,
-    pub which_graph: DisplayedGraph
+    pub which_graph: DisplayedGraph,
+    /// How many radios we're connected to; the heard-by column only shows when it's more than one.
+    pub radio_count: usize,
 }
 #[derive(Default, Debug, Display, Clone)]
 pub enum DisplayedGraph {
@@ -56,12 +55,14 @@ pub enum DisplayedGraph {
     RelativeHumidity,
     BarometricPressure,
     GasResistance,
+    PaxWifi,
+    PaxBle,
 }
 impl DisplayedGraph {
     fn prev(&self) -> Self {
         use DisplayedGraph::*;
         match *self {
-            Battery => GasResistance,
+            Battery => PaxBle,
             Voltage => Battery,
             AirUtilization => Voltage,
             ChannelUtilization => AirUtilization,
@@ -70,7 +71,9 @@ impl DisplayedGraph {
             Temperature => SNR,
             RelativeHumidity => Temperature,
             BarometricPressure => RelativeHumidity,
-            GasResistance => BarometricPressure
+            GasResistance => BarometricPressure,
+            PaxWifi => GasResistance,
+            PaxBle => PaxWifi,
 
         }
     }
@@ -86,7 +89,9 @@ impl DisplayedGraph {
             Temperature => RelativeHumidity,
             RelativeHumidity => BarometricPressure,
             BarometricPressure => GasResistance,
-            GasResistance => Battery,
+            GasResistance => PaxWifi,
+            PaxWifi => PaxBle,
+            PaxBle => Battery,
         }
     }
 }
@@ -100,8 +105,18 @@ pub struct ComprehensiveNode {
     pub last_snr: f32,
     pub last_rssi: i32,
     pub route_list: HashMap<u32, Vec<u32>>,
-    pub timeseries: CircularBuffer<{ consts::MAX_MSG_RETENTION }, TimeSeriesData>,
+    pub timeseries: VecDeque<TimeSeriesData>,
     pub timeseries_start: u64,
+    /// Which of our radios have heard from (or know of) this node.
+    pub heard_by: BTreeSet<RadioId>,
+    /// Detection sensor triggers, oldest first.
+    pub detections: VecDeque<DetectionEvent>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DetectionEvent {
+    pub timestamp: u64,
+    pub text: String,
 }
 
 
@@ -112,16 +127,27 @@ pub struct TimeSeriesData {
     pub environment: EnvironmentMetrics,
     pub air_quality: AirQualityMetrics,
     pub power: PowerMetrics,
+    /// Only set on samples that came from the paxcounter.
+    pub pax: Option<Paxcount>,
     pub rssi: f64,
     pub snr: f64
 }
 
 impl ComprehensiveNode {
     pub fn with_id(id: u32) -> Self {
-        ComprehensiveNode {
+        let mut cn = ComprehensiveNode {
             id,
             ..Default::default()
-        }
+        };
+        cn.node_info.num = id;
+        cn
+    }
+    /// `heard_by` the way people count radios, from 1.
+    pub fn heard_by_label(&self) -> String {
+        self.heard_by.iter().map(|r| (r + 1).to_string()).join(",")
+    }
+    pub fn user_id(&self) -> Option<String> {
+        self.node_info.user.as_ref().map(|u| u.id.clone())
     }
 }
 
@@ -149,29 +175,32 @@ impl NodesTab {
         }
         self.page_size = *PAGE_SIZE.read().await;
 
-        // We sort by last heard, in reverse order, so that the most recent update is at the top.
-        self.table_contents = self.node_list.values().cloned().collect();
-
-        if !self.prefs.show_mqtt {
-            self.table_contents = self
-                .table_contents
-                .iter()
-                .filter_map(|cn| {
-                    if !cn.clone().node_info.via_mqtt {
-                        Some(cn.to_owned())
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+        // only re-sort when something actually changed since the last time we looked.
+        let version = self.node_list.version();
+        if self.table_version == Some(version) {
+            return;
         }
-        self.table_contents
-            .sort_by(|a, b| a.last_seen.cmp(&b.last_seen));
-        self.table_contents.reverse();
+        self.table_version = Some(version);
+
+        // We sort by last heard, in reverse order, so that the most recent update is at the top.
+        let mut sortable: Vec<(u64, u32)> = self
+            .node_list
+            .read()
+            .values()
+            .filter(|cn| self.prefs.show_mqtt || !cn.node_info.via_mqtt)
+            .map(|cn| (cn.last_seen, cn.id))
+            .collect();
+        sortable.sort();
+        sortable.reverse();
+        self.table_contents = sortable.into_iter().map(|(_, id)| id).collect();
     }
     pub(crate) fn get_details_for_node(&self, area: Rect, buf: &mut Buffer) {
-        let me = self.node_list.get(&self.my_node_id).unwrap();
-        let cn = self.node_list.get(&self.selected_node_id).cloned().unwrap();
+        // the node can be gone by now, if the radio was switched or forgot it.
+        let cn = match self.node_list.get(self.selected_node_id) {
+            Some(cn) => cn,
+            None => return,
+        };
+        let my_id = self.my_node_id;
 
         //region layout and block pre-game
         let left_side_constraints = vec![Constraint::Max(30), Constraint::Max(30)];
@@ -259,6 +288,12 @@ impl NodesTab {
             "Last RF SNR/RSSI".to_string(),
             format!("{:.2}dB/{:.2}db", cn.last_snr, cn.last_rssi),
         ]));
+        if self.radio_count > 1 {
+            rows.push(Row::new(vec![
+                "Heard by radio(s)".to_string(),
+                cn.heard_by_label(),
+            ]));
+        }
 
         //region DeviceMetrics-struct display fields
         if let Some(device_metrics) = cn.node_info.device_metrics {
@@ -320,7 +355,7 @@ impl NodesTab {
             if position.altitude > 0 {
                 rows.push(Row::new(vec![
                     "Altitude".to_string(),
-                    format!("{}m", position.altitude),
+                    self.prefs.units.altitude(position.altitude),
                 ]));
             }
         }
@@ -347,13 +382,9 @@ impl NodesTab {
             for item in cn.neighbors.iter() {
                 let id = self
                     .node_list
-                    .get(&item.node_id)
-                    .unwrap()
-                    .clone()
-                    .node_info
-                    .user
-                    .unwrap()
-                    .id;
+                    .with_node(item.node_id, |n| n.user_id())
+                    .flatten()
+                    .unwrap_or_else(|| format!("*{:x}", item.node_id));
                 let snr = format!("{:.2}dB", item.snr);
                 let mut last_seen: String = "Unknown".to_string();
                 if item.last_rx_time > 0 {
@@ -377,14 +408,45 @@ impl NodesTab {
         );
         //endregion
 
+        // detection sensor triggers share the bottom right with the traceroute, if there are any.
+        let (right_bottom_layout, detections_layout) = match cn.detections.is_empty() {
+            true => (right_bottom_layout, None),
+            false => {
+                let [route, detections] = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints(crate::FIFTY_FIFTY.iter())
+                    .areas(right_bottom_layout);
+                (route, Some(detections))
+            }
+        };
+        if let Some(detections_area) = detections_layout {
+            let rows: Vec<Row> = cn
+                .detections
+                .iter()
+                .rev()
+                .map(|event| {
+                    let when = time::OffsetDateTime::from_unix_timestamp(event.timestamp as i64)
+                        .map(|dt| dt.format(consts::DATE_FORMAT).unwrap())
+                        .unwrap_or_default();
+                    Row::new(vec![when, event.text.clone()])
+                })
+                .collect();
+            Widget::render(
+                Table::new(rows, vec![Constraint::Length(20), Constraint::Min(0)])
+                    .block(default_inner_block.clone().title("Detections")),
+                detections_area,
+                buf,
+            );
+        }
+
         //region traceroute display
         let mut right_bottom_rows: Vec<Row> = vec![];
-        if let Some(routes) = cn.route_list.get(&me.id) {
+        if let Some(routes) = cn.route_list.get(&my_id) {
             let whole_route: String = if routes.is_empty() {
-                format!("!{:x} -> !{:x} (Direct Hop)", me.id, cn.id)
+                format!("!{:x} -> !{:x} (Direct Hop)", my_id, cn.id)
             } else {
                 let rest_of_route = routes.iter().map(|s| format!("!{:x}", &s)).join(" -> ");
-                format!("!{:x} -> {} -> !{:x}", me.id, &rest_of_route, cn.id)
+                format!("!{:x} -> {} -> !{:x}", my_id, &rest_of_route, cn.id)
             };
             right_bottom_rows.push(Row::new(vec!["Latest Route:", ""]));
             right_bottom_rows.push(Row::new(vec!["".to_string(), whole_route]));
@@ -402,7 +464,10 @@ impl NodesTab {
     pub fn make_graph(&self, area: Rect, buf: &mut Buffer) {
         // chart time
         use DisplayedGraph::*;
-        let cn = self.node_list.get(&self.selected_node_id).cloned().unwrap();
+        let cn = match self.node_list.get(self.selected_node_id) {
+            Some(cn) => cn,
+            None => return,
+        };
         let mut data: Vec<(f64,f64)>;
         let graph_name: String;
         let y_axis_unit: String;
@@ -466,10 +531,23 @@ impl NodesTab {
                 y_axis_unit = "milliohms (mΩ)".to_string();
                 data = cn.timeseries.iter().map(|d| (d.timestamp as f64,d.environment.gas_resistance as f64)).collect()
             }
+            PaxWifi => {
+                graph_name = "Wifi devices".to_string();
+                y_axis_unit = "devices".to_string();
+                data = cn.timeseries.iter().filter_map(|d| d.pax.as_ref().map(|p| (d.timestamp as f64, p.wifi as f64))).collect()
+            }
+            PaxBle => {
+                graph_name = "Bluetooth devices".to_string();
+                y_axis_unit = "devices".to_string();
+                data = cn.timeseries.iter().filter_map(|d| d.pax.as_ref().map(|p| (d.timestamp as f64, p.ble as f64))).collect()
+            }
         };
         // if our dataset has exact 0.0 entries, the chances are astronomically high that the
-        // value was put there by Default::default() instead of an actual data read.
-        data.retain(|(_,  datum)| datum > &0.0);
+        // value was put there by Default::default() instead of an actual data read.  Pax counts
+        // only come from real reads, and an empty room is a perfectly good zero.
+        if !matches!(self.which_graph, PaxWifi | PaxBle) {
+            data.retain(|(_,  datum)| datum > &0.0);
+        }
 
         let dataset = Dataset::default()
             .marker(symbols::Marker::Braille)
@@ -517,40 +595,19 @@ impl NodesTab {
         );
     }
 
+    /// The node under the cursor, or the one whose details are up.
+    pub fn selected_node(&self) -> Option<u32> {
+        match self.display_mode {
+            DisplayMode::Detail => Some(self.selected_node_id),
+            _ => self.table_contents.get(self.table_state.selected()?).copied(),
+        }
+    }
     pub async fn send_traceroute(&mut self) {
         if let Some(index) = self.table_state.selected() {
-            self.selected_node_id = self.table_contents[index].clone().id;
-
-            #[allow(deprecated)]
-                let mesh_packet = MeshPacket {
-                from: 0,
-                to: self.selected_node_id,
-                channel: 0,
-                id: 0,
-                rx_time: 0,
-                rx_snr: 0.0,
-                hop_limit: 0,
-                want_ack: true,
-                priority: 0,
-                rx_rssi: 0,
-                delayed: 0,
-                via_mqtt: true,
-                hop_start: 0,
-                payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(Data {
-                    portnum: i32::from(TracerouteApp),
-                    payload: vec![],
-                    want_response: true,
-                    dest: 0,
-                    source: 0,
-                    request_id: 0,
-                    reply_id: 0,
-                    emoji: 0,
-                })),
-            };
-            let payload_variant = Some(Packet(mesh_packet));
-            if let Err(e) =
-                util::send_to_radio(IPCMessage::ToRadio(ToRadio { payload_variant })).await
-            {
+            self.selected_node_id = self.table_contents[index];
+
+            let traceroute = util::traceroute_request(self.selected_node_id);
+            if let Err(e) = util::send_to_radio(IPCMessage::ToRadio(traceroute)).await {
                 error!("Tried sending traceroute but failed: {e}");
             } else {
                 info!("Emitted Traceroute Request to !{:x}", self.selected_node_id);
@@ -574,7 +631,7 @@ impl NodesTab {
         match self.display_mode {
             DisplayMode::List => {
                 if let Some(index) = self.table_state.selected() {
-                    self.selected_node_id = self.table_contents[index].clone().id;
+                    self.selected_node_id = self.table_contents[index];
                     self.display_mode = DisplayMode::Detail
                 }
             }
@@ -616,7 +673,7 @@ impl NodesTab {
         }
     }
     pub fn next_page(&mut self) {
-        if self.display_mode == DisplayMode::List {
+        if self.display_mode == DisplayMode::List && !self.node_list.is_empty() {
             let i = match self.table_state.selected() {
                 Some(i) => {
                     if i >= self.node_list.len().saturating_sub(self.page_size as usize) {
@@ -702,7 +759,7 @@ impl Widget for NodesTab {
                 self.get_details_for_node(area, buf);
             }
             DisplayMode::List => {
-                let node_list_constraints = vec![
+                let mut node_list_constraints = vec![
                     Constraint::Max(10),    // ID
                     Constraint::Max(5),     // ShortName
                     Constraint::Max(25),    // LongName
@@ -718,24 +775,32 @@ impl Widget for NodesTab {
                     Constraint::Max(20),    // Last Heard
                     Constraint::Max(20),    // Last Updated
                 ];
+                let show_radios = self.radio_count > 1;
+                if show_radios {
+                    node_list_constraints.insert(5, Constraint::Max(6)); // Radios
+                }
 
                 let mut my_location: Option<Location> = None;
-                if let Some(my_node) = self.node_list.get(&self.my_node_id) {
-                    if let Some(pos) = my_node.clone().node_info.position {
-                        let lat = pos.latitude_i as f32 * consts::GPS_PRECISION_FACTOR;
-                        let lon = pos.longitude_i as f32 * consts::GPS_PRECISION_FACTOR;
-                        if lat.ne(&0.0) && lon.ne(&0.0) {
-                            my_location = Some(Location::new(lat, lon));
-                        }
+                if let Some(pos) = self
+                    .node_list
+                    .with_node(self.my_node_id, |n| n.node_info.position.clone())
+                    .flatten()
+                {
+                    let lat = pos.latitude_i as f32 * consts::GPS_PRECISION_FACTOR;
+                    let lon = pos.longitude_i as f32 * consts::GPS_PRECISION_FACTOR;
+                    if lat.ne(&0.0) && lon.ne(&0.0) {
+                        my_location = Some(Location::new(lat, lon));
                     }
                 }
+                let nodes = self.node_list.read();
                 let rows = self
                     .table_contents
                     .iter()
+                    .filter_map(|id| nodes.get(id))
                     .map(|cn| {
                         let _add_this_entry: bool = true;
                         let user_id_str;
-                        let user = cn.clone().node_info.user.unwrap_or_default();
+                        let user = cn.node_info.user.clone().unwrap_or_default();
                         if !user.id.is_empty() {
                             if cn.id == self.my_node_id {
                                 user_id_str = format!("^{:x}", cn.id);
@@ -743,10 +808,10 @@ impl Widget for NodesTab {
                                 user_id_str = user.id;
                             }
                         } else {
-                            user_id_str = format!("*{:x}", cn.clone().id);
+                            user_id_str = format!("*{:x}", cn.id);
                         }
-                        let device = cn.clone().node_info.device_metrics.unwrap_or_default();
-                        let position = cn.clone().node_info.position.unwrap_or_default();
+                        let device = cn.node_info.device_metrics.clone().unwrap_or_default();
+                        let position = cn.node_info.position.clone().unwrap_or_default();
 
                         let station_lat = position.latitude_i as f32 * consts::GPS_PRECISION_FACTOR;
                         let station_lon =
@@ -756,8 +821,7 @@ impl Widget for NodesTab {
                             let station_location = Location::new(station_lat, station_lon);
                             let distance = station_location.distance_to(&my_location.unwrap()).ok();
                             if distance.is_some() {
-                                distance_str =
-                                    format!("{:.3}km", distance.unwrap().meters().div(1000.0_f64));
+                                distance_str = self.prefs.units.distance(distance.unwrap().meters());
                             }
                         }
 
@@ -792,7 +856,7 @@ impl Widget for NodesTab {
 
                         let mut altitude_str = "".to_string();
                         if position.altitude.ne(&0) {
-                            altitude_str = format!("{}m", position.altitude);
+                            altitude_str = self.prefs.units.altitude(position.altitude);
                         };
 
                         let mut voltage_str = "".to_string();
@@ -828,7 +892,7 @@ impl Widget for NodesTab {
                         // I don't want to blocking read every loop iteration so we'll cheat and set
                         // self.prefs here, avoiding ::new(),::default() adjusting shenanigans.
 
-                        Row::new(vec![
+                        let mut cells = vec![
                             user_id_str,
                             user.short_name,
                             user.long_name,
@@ -843,11 +907,16 @@ impl Widget for NodesTab {
                             battery_str,
                             ni_lastheard_since_string,
                             lastupdate_since_string,
-                        ])
+                        ];
+                        if show_radios {
+                            cells.insert(5, cn.heard_by_label());
+                        }
+                        Row::new(cells)
                     })
                     .collect_vec();
+                drop(nodes);
 
-                let header = Row::new(vec![
+                let mut header_cells = vec![
                     "ID",
                     "Short",
                     "Long",
@@ -862,7 +931,11 @@ impl Widget for NodesTab {
                     "Battery",
                     "Last Heard NodeInfo",
                     "Last Update",
-                ])
+                ];
+                if show_radios {
+                    header_cells.insert(5, "Radios");
+                }
+                let header = Row::new(header_cells)
                     .style(THEME.message_header)
                     .bottom_margin(1);
 
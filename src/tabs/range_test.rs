@@ -0,0 +1,447 @@
+//! Range test sessions.  A node running the range test module sends `seq N` every so often;
+//! we log each one we hear along with its signal, where the sender was and how far away that
+//! is, and work out how many went missing from the gaps in the numbering.  The session can be
+//! saved as CSV for spreadsheets or GPX for mapping tools.
+use crate::app::{Mode, Preferences};
+use crate::consts;
+use crate::theme::THEME;
+use crate::PREFERENCES;
+use anyhow::Result;
+use geoutils::Location;
+use itertools::Itertools;
+use meshtastic::protobufs::Position;
+use ratatui::{prelude::*, widgets::*};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, RwLock};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, Default)]
+pub struct RangeTestSample {
+    pub timestamp: u64,
+    pub from: u32,
+    pub seq: u32,
+    pub snr: f32,
+    pub rssi: i32,
+    pub hops: u32,
+    /// Where the sender last said it was when this arrived.
+    pub sender_position: Option<Position>,
+    pub our_position: Option<Position>,
+    /// Meters between the two, if both are known.
+    pub distance: Option<f64>,
+}
+
+impl RangeTestSample {
+    /// Fills in the positions and the distance between them.
+    pub fn locate(&mut self, sender: Option<Position>, us: Option<Position>) {
+        let known = |p: Option<Position>| p.filter(|p| p.latitude_i != 0 || p.longitude_i != 0);
+        self.sender_position = known(sender);
+        self.our_position = known(us);
+        if let (Some(a), Some(b)) = (&self.sender_position, &self.our_position) {
+            self.distance = location(a)
+                .distance_to(&location(b))
+                .ok()
+                .map(|d| d.meters());
+        }
+    }
+}
+
+fn degrees(i: i32) -> f64 {
+    i as f64 * consts::GPS_PRECISION_FACTOR as f64
+}
+
+fn location(p: &Position) -> Location {
+    Location::new(degrees(p.latitude_i), degrees(p.longitude_i))
+}
+
+/// Every sample we've heard, per sender, in the order they arrived.
+pub type RangeTestLog = Arc<RwLock<BTreeMap<u32, Vec<RangeTestSample>>>>;
+
+pub fn record(log: &RangeTestLog, sample: RangeTestSample) {
+    let mut log = log.write().unwrap();
+    let samples = log.entry(sample.from).or_default();
+    // the same packet can reach us through more than one radio.
+    if samples
+        .iter()
+        .rev()
+        .take(consts::RANGE_TEST_DUPLICATE_WINDOW)
+        .any(|s| s.seq == sample.seq)
+    {
+        return;
+    }
+    samples.push(sample);
+}
+
+/// How many packets arrived and how many went missing.  A number that doesn't go up means the
+/// sender started over, which isn't counted as a loss.
+fn received_and_lost(samples: &[RangeTestSample]) -> (usize, u32) {
+    let lost = samples
+        .iter()
+        .tuple_windows()
+        .map(|(a, b)| b.seq.saturating_sub(a.seq).saturating_sub(1))
+        .sum::<u32>();
+    (samples.len(), lost)
+}
+
+fn loss_percent(received: usize, lost: u32) -> f64 {
+    match received as u32 + lost {
+        0 => 0.0,
+        total => lost as f64 * 100.0 / total as f64,
+    }
+}
+
+fn timestamp(secs: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(secs as i64)
+        .ok()
+        .and_then(|dt| dt.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+pub fn to_csv(log: &BTreeMap<u32, Vec<RangeTestSample>>) -> String {
+    let mut csv = String::from(
+        "time,from,seq,snr,rssi,hops,sender_lat,sender_lon,sender_alt,our_lat,our_lon,distance_m\n",
+    );
+    let coords = |p: &Option<Position>| match p {
+        Some(p) => (
+            format!("{:.7}", degrees(p.latitude_i)),
+            format!("{:.7}", degrees(p.longitude_i)),
+            p.altitude.to_string(),
+        ),
+        None => Default::default(),
+    };
+    for s in log.values().flatten().sorted_by_key(|s| s.timestamp) {
+        let (sender_lat, sender_lon, sender_alt) = coords(&s.sender_position);
+        let (our_lat, our_lon, _) = coords(&s.our_position);
+        let _ = writeln!(
+            csv,
+            "{},!{:08x},{},{},{},{},{sender_lat},{sender_lon},{sender_alt},{our_lat},{our_lon},{}",
+            timestamp(s.timestamp),
+            s.from,
+            s.seq,
+            s.snr,
+            s.rssi,
+            s.hops,
+            s.distance.map(|d| format!("{d:.1}")).unwrap_or_default()
+        );
+    }
+    csv
+}
+
+/// A waypoint for every sample whose sender position we know, named by sender and sequence.
+pub fn to_gpx(log: &BTreeMap<u32, Vec<RangeTestSample>>) -> String {
+    let mut gpx = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<gpx version=\"1.1\" creator=\"meshtui\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n"
+    ));
+    for s in log.values().flatten().sorted_by_key(|s| s.timestamp) {
+        let pos = match &s.sender_position {
+            Some(p) => p,
+            None => continue,
+        };
+        let distance = s
+            .distance
+            .map(|d| format!(", {d:.0}m away"))
+            .unwrap_or_default();
+        let _ = write!(
+            gpx,
+            concat!(
+                "  <wpt lat=\"{:.7}\" lon=\"{:.7}\">\n",
+                "    <ele>{}</ele>\n",
+                "    <time>{}</time>\n",
+                "    <name>!{:08x} seq {}</name>\n",
+                "    <desc>SNR {} dB, RSSI {} dBm{}</desc>\n",
+                "  </wpt>\n"
+            ),
+            degrees(pos.latitude_i),
+            degrees(pos.longitude_i),
+            pos.altitude,
+            timestamp(s.timestamp),
+            s.from,
+            s.seq,
+            s.snr,
+            s.rssi,
+            distance
+        );
+    }
+    gpx.push_str("</gpx>\n");
+    gpx
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RangeTestTab {
+    pub log: RangeTestLog,
+    table_state: TableState,
+    senders: Vec<u32>,
+    prefs: Preferences,
+}
+
+impl RangeTestTab {
+    pub async fn run(&mut self) {
+        if self.prefs.initialized.is_empty() {
+            self.prefs = PREFERENCES.read().await.clone();
+        }
+        self.senders = self.log.read().unwrap().keys().copied().collect();
+        if self.table_state.selected().is_none() && !self.senders.is_empty() {
+            self.table_state.select(Some(0));
+        }
+    }
+
+    pub fn escape(&mut self) -> Mode {
+        Mode::Exiting
+    }
+
+    pub fn prev_row(&mut self) {
+        let i = match self.table_state.selected() {
+            Some(0) | None => self.senders.len().saturating_sub(1),
+            Some(i) => i - 1,
+        };
+        self.table_state.select(Some(i));
+    }
+
+    pub fn next_row(&mut self) {
+        let i = match self.table_state.selected() {
+            Some(i) if i + 1 < self.senders.len() => i + 1,
+            _ => 0,
+        };
+        self.table_state.select(Some(i));
+    }
+
+    fn export(&self, extension: &str) -> Result<String> {
+        let log = self.log.read().unwrap();
+        let contents = match extension {
+            "gpx" => to_gpx(&log),
+            _ => to_csv(&log),
+        };
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let path = format!("meshtui-rangetest-{now}.{extension}");
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    pub fn function_key(&mut self, num: u8) {
+        let extension = match num {
+            2 => "csv",
+            3 => "gpx",
+            4 => {
+                self.log.write().unwrap().clear();
+                self.table_state.select(None);
+                info!("Started a new range test session.");
+                return;
+            }
+            _ => return,
+        };
+        match self.export(extension) {
+            Ok(path) => info!("Saved the range test session to {path}"),
+            Err(e) => error!("Couldn't save the range test session: {e}"),
+        }
+    }
+
+    fn render_samples(&self, samples: &[RangeTestSample], area: Rect, buf: &mut Buffer) {
+        let constraints = vec![
+            Constraint::Length(20), // Time
+            Constraint::Length(8),  // Seq
+            Constraint::Length(20), // SNR/RSSI
+            Constraint::Length(6),  // Hops
+            Constraint::Length(26), // Sender position
+            Constraint::Min(12),    // Distance
+        ];
+        let rows: Vec<Row> = samples
+            .iter()
+            .rev()
+            .map(|s| {
+                let time = OffsetDateTime::from_unix_timestamp(s.timestamp as i64)
+                    .map(|dt| dt.format(consts::DATE_FORMAT).unwrap())
+                    .unwrap_or_default();
+                let position = match &s.sender_position {
+                    Some(p) => format!(
+                        "{:.5}, {:.5}",
+                        degrees(p.latitude_i),
+                        degrees(p.longitude_i)
+                    ),
+                    None => "Unknown".to_string(),
+                };
+                Row::new(vec![
+                    time,
+                    s.seq.to_string(),
+                    format!("{:.2} dB / {} dBm", s.snr, s.rssi),
+                    s.hops.to_string(),
+                    position,
+                    s.distance
+                        .map(|d| self.prefs.units.distance(d))
+                        .unwrap_or_default(),
+                ])
+            })
+            .collect();
+        let header = Row::new(vec![
+            "Time",
+            "Seq",
+            "SNR/RSSI",
+            "Hops",
+            "Sender at",
+            "Distance",
+        ])
+        .style(THEME.message_header)
+        .bottom_margin(1);
+        Widget::render(
+            Table::new(rows, constraints).header(header).block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .title("Packets")
+                    .title_alignment(Alignment::Center)
+                    .border_set(symbols::border::DOUBLE)
+                    .style(THEME.middle),
+            ),
+            area,
+            buf,
+        );
+    }
+}
+
+impl Widget for RangeTestTab {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let log = self.log.read().unwrap().clone();
+        let [summary_area, samples_area] = Layout::vertical([
+            Constraint::Length((self.senders.len() as u16).saturating_add(4).min(12)),
+            Constraint::Min(0),
+        ])
+        .areas(area);
+
+        let constraints = vec![
+            Constraint::Length(12), // Sender
+            Constraint::Length(10), // Received
+            Constraint::Length(8),  // Lost
+            Constraint::Length(8),  // Loss
+            Constraint::Length(20), // Last SNR/RSSI
+            Constraint::Length(10), // Avg SNR
+            Constraint::Min(12),    // Max distance
+        ];
+        let rows: Vec<Row> = self
+            .senders
+            .iter()
+            .filter_map(|id| log.get(id).map(|samples| (id, samples)))
+            .map(|(id, samples)| {
+                let (received, lost) = received_and_lost(samples);
+                let last = samples.last().cloned().unwrap_or_default();
+                let avg_snr =
+                    samples.iter().map(|s| s.snr as f64).sum::<f64>() / samples.len().max(1) as f64;
+                let max_distance = samples
+                    .iter()
+                    .filter_map(|s| s.distance)
+                    .reduce(f64::max)
+                    .map(|d| self.prefs.units.distance(d))
+                    .unwrap_or_default();
+                Row::new(vec![
+                    format!("!{id:08x}"),
+                    received.to_string(),
+                    lost.to_string(),
+                    format!("{:.1}%", loss_percent(received, lost)),
+                    format!("{:.2} dB / {} dBm", last.snr, last.rssi),
+                    format!("{avg_snr:.2} dB"),
+                    max_distance,
+                ])
+            })
+            .collect();
+        let header = Row::new(vec![
+            "Sender",
+            "Received",
+            "Lost",
+            "Loss",
+            "Last SNR/RSSI",
+            "Avg SNR",
+            "Farthest",
+        ])
+        .style(THEME.message_header)
+        .bottom_margin(1);
+        StatefulWidget::render(
+            Table::new(rows, constraints)
+                .header(header)
+                .highlight_style(THEME.tabs_selected)
+                .block(
+                    Block::new()
+                        .borders(Borders::ALL)
+                        .title("Range test (F2: save CSV, F3: save GPX, F4: new session)")
+                        .title_alignment(Alignment::Center)
+                        .border_set(symbols::border::DOUBLE)
+                        .style(THEME.middle),
+                ),
+            summary_area,
+            buf,
+            &mut self.table_state,
+        );
+
+        let selected = self
+            .table_state
+            .selected()
+            .and_then(|i| self.senders.get(i))
+            .and_then(|id| log.get(id));
+        self.render_samples(
+            selected.map(|s| s.as_slice()).unwrap_or_default(),
+            samples_area,
+            buf,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(from: u32, seq: u32) -> RangeTestSample {
+        RangeTestSample {
+            from,
+            seq,
+            ..Default::default()
+        }
+    }
+
+    fn samples(seqs: &[u32]) -> Vec<RangeTestSample> {
+        seqs.iter().map(|&seq| sample(1, seq)).collect()
+    }
+
+    #[test]
+    fn gaps_count_as_lost() {
+        assert_eq!(received_and_lost(&[]), (0, 0));
+        assert_eq!(received_and_lost(&samples(&[1, 2, 3])), (3, 0));
+        assert_eq!(received_and_lost(&samples(&[1, 4, 5, 7])), (4, 3));
+        assert_eq!(loss_percent(4, 3), 300.0 / 7.0);
+        assert_eq!(loss_percent(0, 0), 0.0);
+    }
+
+    #[test]
+    fn starting_over_isnt_a_loss() {
+        assert_eq!(received_and_lost(&samples(&[8, 9, 1, 2])), (4, 0));
+    }
+
+    #[test]
+    fn duplicates_are_only_recorded_once() {
+        let log = RangeTestLog::default();
+        record(&log, sample(1, 1));
+        record(&log, sample(1, 1));
+        record(&log, sample(2, 1));
+        record(&log, sample(1, 2));
+        let log = log.read().unwrap();
+        assert_eq!(log[&1].len(), 2);
+        assert_eq!(log[&2].len(), 1);
+    }
+
+    #[test]
+    fn distance_needs_both_positions() {
+        let here = Position {
+            latitude_i: 515_000_000,
+            longitude_i: 0,
+            ..Default::default()
+        };
+        let north = Position {
+            latitude_i: 515_100_000,
+            ..here.clone()
+        };
+        let mut s = sample(1, 1);
+        s.locate(Some(north.clone()), None);
+        assert_eq!(s.distance, None);
+        s.locate(Some(north), Some(here));
+        // a hundredth of a degree of latitude is about 1.1km.
+        let d = s.distance.unwrap();
+        assert!((1100.0..1125.0).contains(&d), "{d}m");
+    }
+}
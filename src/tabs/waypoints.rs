@@ -0,0 +1,456 @@
+//! Waypoints are named spots on the map that nodes share with the rest of the mesh.  We keep
+//! every one we hear (and every one we send) until it expires or its owner takes it down, which
+//! the apps do by sending it again with an expiry in the past.
+use crate::app::{centered_rect, Mode, Preferences};
+use crate::consts;
+use crate::ipc::IPCMessage;
+use crate::theme::THEME;
+use crate::util::{self, get_secs};
+use crate::PREFERENCES;
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent};
+use geoutils::Location;
+use meshtastic::protobufs::{Position, Waypoint};
+use meshtastic::utils;
+use ratatui::{prelude::*, widgets::*};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use strum::{Display, EnumIter, FromRepr, IntoEnumIterator};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone)]
+pub struct KnownWaypoint {
+    pub waypoint: Waypoint,
+    /// The node that sent it.
+    pub from: u32,
+    /// The channel it came in on, which is where changes to it go back out.
+    pub channel: u32,
+    pub last_update: u64,
+}
+
+/// Shared like the message log, keyed by waypoint id.
+pub type WaypointStore = Arc<RwLock<BTreeMap<u32, KnownWaypoint>>>;
+
+fn is_expired(waypoint: &Waypoint) -> bool {
+    waypoint.expire != 0 && waypoint.expire as u64 <= get_secs()
+}
+
+/// Files a waypoint we heard or sent, or drops it if it's being taken down.
+pub fn file_waypoint(store: &WaypointStore, waypoint: Waypoint, from: u32, channel: u32) {
+    let mut waypoints = store.write().unwrap();
+    if is_expired(&waypoint) {
+        if waypoints.remove(&waypoint.id).is_some() {
+            info!("Waypoint {} was deleted by !{:x}", waypoint.name, from);
+        }
+        return;
+    }
+    waypoints.insert(
+        waypoint.id,
+        KnownWaypoint {
+            waypoint,
+            from,
+            channel,
+            last_update: get_secs(),
+        },
+    );
+}
+
+#[derive(Debug, Clone, Copy, Display, EnumIter, FromRepr, PartialEq, Eq)]
+enum Field {
+    Name,
+    Description,
+    Icon,
+    Latitude,
+    Longitude,
+    #[strum(to_string = "Expires in hours (0 = never)")]
+    ExpiresIn,
+    Channel,
+}
+
+/// The create/edit popup.  Every field is kept as typed and only checked on Enter.
+#[derive(Debug, Clone, Default)]
+struct WaypointEditor {
+    /// The waypoint being edited, or None for a new one.
+    original: Option<Waypoint>,
+    values: Vec<String>,
+    field: usize,
+    error: Option<String>,
+}
+
+impl WaypointEditor {
+    fn new(original: Option<&KnownWaypoint>, here: Option<&Position>) -> Self {
+        let mut values = vec![String::new(); Field::iter().count()];
+        match original {
+            Some(known) => {
+                let w = &known.waypoint;
+                values[Field::Name as usize] = w.name.clone();
+                values[Field::Description as usize] = w.description.clone();
+                values[Field::Icon as usize] = icon(w.icon);
+                values[Field::Latitude as usize] = format!("{:.7}", to_degrees(w.latitude_i));
+                values[Field::Longitude as usize] = format!("{:.7}", to_degrees(w.longitude_i));
+                values[Field::ExpiresIn as usize] = match w.expire {
+                    0 => "0".to_string(),
+                    at => format!(
+                        "{:.1}",
+                        at.saturating_sub(get_secs() as u32) as f64 / 3600.0
+                    ),
+                };
+                values[Field::Channel as usize] = known.channel.to_string();
+            }
+            None => {
+                // new waypoints start out wherever our own node is.
+                if let Some(pos) = here {
+                    values[Field::Latitude as usize] = format!("{:.7}", to_degrees(pos.latitude_i));
+                    values[Field::Longitude as usize] =
+                        format!("{:.7}", to_degrees(pos.longitude_i));
+                }
+                values[Field::ExpiresIn as usize] = consts::WAYPOINT_DEFAULT_HOURS.to_string();
+                values[Field::Channel as usize] = "0".to_string();
+            }
+        }
+        WaypointEditor {
+            original: original.map(|k| k.waypoint.clone()),
+            values,
+            ..Default::default()
+        }
+    }
+
+    fn value(&self, field: Field) -> &str {
+        self.values[field as usize].trim()
+    }
+
+    /// The waypoint as typed, and the channel to send it on.
+    fn build(&self) -> Result<(Waypoint, u32)> {
+        let name = self.value(Field::Name);
+        if name.is_empty() {
+            bail!("A waypoint needs a name.");
+        }
+        if name.len() > consts::WAYPOINT_NAME_MAX {
+            bail!("Names can be {} bytes at most.", consts::WAYPOINT_NAME_MAX);
+        }
+        let description = self.value(Field::Description);
+        if description.len() > consts::WAYPOINT_DESCRIPTION_MAX {
+            bail!(
+                "Descriptions can be {} bytes at most.",
+                consts::WAYPOINT_DESCRIPTION_MAX
+            );
+        }
+        let latitude = match self.value(Field::Latitude).parse::<f64>() {
+            Ok(l) if (-90.0..=90.0).contains(&l) => l,
+            _ => bail!("Latitude should be a number of degrees between -90 and 90."),
+        };
+        let longitude = match self.value(Field::Longitude).parse::<f64>() {
+            Ok(l) if (-180.0..=180.0).contains(&l) => l,
+            _ => bail!("Longitude should be a number of degrees between -180 and 180."),
+        };
+        let expire = match self.value(Field::ExpiresIn).parse::<f64>() {
+            Ok(h) if h == 0.0 => 0,
+            // a huge number of hours just lands on the last time a u32 can hold.
+            Ok(h) if h > 0.0 => (get_secs() as u32).saturating_add((h * 3600.0) as u32),
+            _ => bail!("Expiry should be a number of hours, or 0 to never expire."),
+        };
+        let channel = match self.value(Field::Channel).parse::<u32>() {
+            Ok(c) if c <= consts::MAX_CHANNEL_INDEX => c,
+            _ => bail!("Channel should be 0 to {}.", consts::MAX_CHANNEL_INDEX),
+        };
+        let original = self.original.clone().unwrap_or_default();
+        let waypoint = Waypoint {
+            id: match original.id {
+                0 => utils::generate_rand_id(),
+                id => id,
+            },
+            latitude_i: (latitude * 1e7).round() as i32,
+            longitude_i: (longitude * 1e7).round() as i32,
+            expire,
+            locked_to: original.locked_to,
+            name: name.to_string(),
+            description: description.to_string(),
+            icon: self
+                .value(Field::Icon)
+                .chars()
+                .next()
+                .map_or(0, |c| c as u32),
+        };
+        Ok((waypoint, channel))
+    }
+}
+
+fn to_degrees(i: i32) -> f64 {
+    i as f64 * consts::GPS_PRECISION_FACTOR as f64
+}
+
+fn icon(codepoint: u32) -> String {
+    char::from_u32(codepoint)
+        .map(String::from)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WaypointsTab {
+    pub waypoints: WaypointStore,
+    table_state: TableState,
+    table_contents: Vec<KnownWaypoint>,
+    prefs: Preferences,
+    my_node_id: u32,
+    my_position: Option<Position>,
+    editor: Option<WaypointEditor>,
+}
+
+impl WaypointsTab {
+    pub async fn run(&mut self, my_node_id: u32, my_position: Option<Position>) {
+        if self.prefs.initialized.is_empty() {
+            self.prefs = PREFERENCES.read().await.clone();
+        }
+        self.my_node_id = my_node_id;
+        self.my_position = my_position;
+        {
+            let mut waypoints = self.waypoints.write().unwrap();
+            waypoints.retain(|_, known| !is_expired(&known.waypoint));
+            self.table_contents = waypoints.values().cloned().collect();
+        }
+        self.table_contents
+            .sort_by(|a, b| b.last_update.cmp(&a.last_update));
+    }
+
+    /// While the editor is up it gets every key, so typing a `q` doesn't quit.
+    pub fn is_editing(&self) -> bool {
+        self.editor.is_some()
+    }
+
+    pub fn escape(&mut self) -> Mode {
+        Mode::Exiting
+    }
+
+    fn selected(&self) -> Option<&KnownWaypoint> {
+        self.table_contents.get(self.table_state.selected()?)
+    }
+
+    /// Whether we're allowed to change a waypoint; the owner can lock it to their node.
+    fn may_change(&self, known: &KnownWaypoint) -> bool {
+        let locked_to = known.waypoint.locked_to;
+        if locked_to != 0 && locked_to != self.my_node_id {
+            warn!(
+                "Waypoint {} is locked to !{:x}, so only they can change it.",
+                known.waypoint.name, locked_to
+            );
+            return false;
+        }
+        true
+    }
+
+    pub fn enter_key(&mut self) {
+        if let Some(known) = self.selected() {
+            if self.may_change(known) {
+                self.editor = Some(WaypointEditor::new(Some(known), None));
+            }
+        }
+    }
+
+    pub fn prev_row(&mut self) {
+        let i = match self.table_state.selected() {
+            Some(0) | None => self.table_contents.len().saturating_sub(1),
+            Some(i) => i - 1,
+        };
+        self.table_state.select(Some(i));
+    }
+
+    pub fn next_row(&mut self) {
+        let i = match self.table_state.selected() {
+            Some(i) if i + 1 < self.table_contents.len() => i + 1,
+            _ => 0,
+        };
+        self.table_state.select(Some(i));
+    }
+
+    pub async fn function_key(&mut self, num: u8) {
+        match num {
+            2 => self.editor = Some(WaypointEditor::new(None, self.my_position.as_ref())),
+            3 => self.delete_selected().await,
+            _ => {}
+        }
+    }
+
+    async fn send(&self, waypoint: Waypoint, channel: u32) -> Result<()> {
+        let packet = util::waypoint_packet(&waypoint, channel);
+        util::send_to_radio(IPCMessage::ToRadio(packet)).await?;
+        // the radio doesn't echo our own packets back, so file it ourselves.
+        file_waypoint(&self.waypoints, waypoint, self.my_node_id, channel);
+        Ok(())
+    }
+
+    async fn delete_selected(&mut self) {
+        let known = match self.selected() {
+            Some(k) if self.may_change(k) => k.clone(),
+            _ => return,
+        };
+        let mut waypoint = known.waypoint;
+        // an expiry in the past is how the apps say "delete this".
+        waypoint.expire = 1;
+        let name = waypoint.name.clone();
+        match self.send(waypoint, known.channel).await {
+            Ok(()) => info!("Deleted waypoint {name}"),
+            Err(e) => error!("Couldn't delete waypoint {name}: {e}"),
+        }
+    }
+
+    pub async fn handle_key(&mut self, press: KeyEvent) {
+        use KeyCode::*;
+        let editor = match self.editor.as_mut() {
+            Some(e) => e,
+            None => return,
+        };
+        let field_count = editor.values.len();
+        match press.code {
+            Esc => self.editor = None,
+            Up | BackTab => editor.field = (editor.field + field_count - 1) % field_count,
+            Down | Tab => editor.field = (editor.field + 1) % field_count,
+            Char(c) => editor.values[editor.field].push(c),
+            Backspace => {
+                editor.values[editor.field].pop();
+            }
+            Enter => match editor.build() {
+                Ok((waypoint, channel)) => {
+                    let name = waypoint.name.clone();
+                    match self.send(waypoint, channel).await {
+                        Ok(()) => {
+                            info!("Sent waypoint {name} to channel {channel}");
+                            self.editor = None;
+                        }
+                        Err(e) => {
+                            if let Some(editor) = self.editor.as_mut() {
+                                editor.error = Some(e.to_string());
+                            }
+                        }
+                    }
+                }
+                Err(e) => editor.error = Some(e.to_string()),
+            },
+            _ => {}
+        }
+    }
+
+    fn render_editor(&self, editor: &WaypointEditor, area: Rect, buf: &mut Buffer) {
+        let popup_area = centered_rect(area, 60, 60);
+        let title = match editor.original {
+            Some(_) => "Edit waypoint",
+            None => "New waypoint",
+        };
+        let mut lines: Vec<Line> = Field::iter()
+            .map(|field| {
+                let label = field.to_string();
+                let text = format!("{label:>30}: {}", editor.values[field as usize]);
+                match field as usize == editor.field {
+                    true => Line::styled(text, THEME.message_selected),
+                    false => Line::from(text),
+                }
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from(
+            "Up/Down: pick a field   Enter: send to the mesh   Esc: cancel",
+        ));
+        if let Some(e) = &editor.error {
+            lines.push(Line::styled(e.clone(), THEME.warning_highlight));
+        }
+        Clear.render(popup_area, buf);
+        Paragraph::new(lines)
+            .block(
+                Block::new()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .title_alignment(Alignment::Center)
+                    .border_set(symbols::border::DOUBLE)
+                    .style(THEME.popup_window),
+            )
+            .render(popup_area, buf);
+    }
+}
+
+impl Widget for WaypointsTab {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let my_location = self
+            .my_position
+            .as_ref()
+            .filter(|p| p.latitude_i != 0 && p.longitude_i != 0)
+            .map(|p| Location::new(to_degrees(p.latitude_i), to_degrees(p.longitude_i)));
+
+        let constraints = vec![
+            Constraint::Length(4), // Icon
+            Constraint::Max(30),   // Name
+            Constraint::Min(20),   // Description
+            Constraint::Max(24),   // Lat/Lon
+            Constraint::Max(12),   // Distance
+            Constraint::Max(20),   // Expires
+            Constraint::Max(10),   // From
+        ];
+        let rows: Vec<Row> = self
+            .table_contents
+            .iter()
+            .map(|known| {
+                let w = &known.waypoint;
+                let distance = match &my_location {
+                    Some(me) => Location::new(to_degrees(w.latitude_i), to_degrees(w.longitude_i))
+                        .distance_to(me)
+                        .map(|d| self.prefs.units.distance(d.meters()))
+                        .unwrap_or_default(),
+                    None => String::new(),
+                };
+                let expires = match w.expire {
+                    0 => "Never".to_string(),
+                    at => OffsetDateTime::from_unix_timestamp(at as i64)
+                        .map(|dt| dt.format(consts::DATE_FORMAT).unwrap())
+                        .unwrap_or_default(),
+                };
+                let mut name = w.name.clone();
+                if w.locked_to != 0 {
+                    name.push_str(" (locked)");
+                }
+                Row::new(vec![
+                    icon(w.icon),
+                    name,
+                    w.description.clone(),
+                    format!(
+                        "{:.5}, {:.5}",
+                        to_degrees(w.latitude_i),
+                        to_degrees(w.longitude_i)
+                    ),
+                    distance,
+                    expires,
+                    format!("!{:x}", known.from),
+                ])
+            })
+            .collect();
+
+        let block = Block::new()
+            .borders(Borders::ALL)
+            .title("Waypoints (F2: new, Enter: edit, F3: delete)")
+            .title_alignment(Alignment::Center)
+            .border_set(symbols::border::DOUBLE)
+            .style(THEME.middle);
+        let header = Row::new(vec![
+            "",
+            "Name",
+            "Description",
+            "Lat/Lon",
+            "Distance",
+            "Expires",
+            "From",
+        ])
+        .style(THEME.message_header)
+        .bottom_margin(1);
+
+        StatefulWidget::render(
+            Table::new(rows, constraints)
+                .block(block)
+                .header(header)
+                .highlight_style(THEME.tabs_selected),
+            area,
+            buf,
+            &mut self.table_state,
+        );
+
+        if let Some(editor) = &self.editor {
+            self.render_editor(editor, area, buf);
+        }
+    }
+}
@@ -1,5 +1,18 @@
+use crate::PREFERENCES;
+use lazy_static::lazy_static;
 use ratatui::prelude::*;
 
+pub const DEFAULT_THEME_NAME: &str = "default";
+pub const THEME_NAMES: [&str; 2] = [DEFAULT_THEME_NAME, "mono"];
+
+lazy_static! {
+    // picked the first time anything draws, which is after main has loaded the preferences.
+    pub static ref THEME: &'static Theme = match PREFERENCES.try_read() {
+        Ok(prefs) if prefs.theme == "mono" => &MONO_THEME,
+        _ => &DEFAULT_THEME,
+    };
+}
+
 pub struct Theme {
     pub root: Style,
     pub tabs: Style,
@@ -23,7 +36,7 @@ pub struct KeyBinding {
     pub key: Style,
     pub description: Style,
 }
-pub const THEME: Theme = Theme {
+pub const DEFAULT_THEME: Theme = Theme {
     root: Style::new().bg(MENU_COLOR_BACKGROUND),
     tabs: Style::new()
         .fg(MENU_COLOR_FOREGROUND)
@@ -57,6 +70,29 @@ pub const THEME: Theme = Theme {
     popup_window: Style::new().fg(TV_WHITE).bg(TV_GREY),
 };
 
+/// For terminals without color, or people who'd rather not.
+pub const MONO_THEME: Theme = Theme {
+    root: Style::new(),
+    tabs: Style::new(),
+    tabs_selected: Style::new().add_modifier(Modifier::REVERSED),
+    warning_highlight: Style::new().add_modifier(Modifier::BOLD),
+    footer: Style::new(),
+    borders: Style::new(),
+    middle: Style::new(),
+    nodes: NodesTheme {
+        list: Style::new(),
+        detail: Style::new().add_modifier(Modifier::REVERSED),
+    },
+    key_binding: KeyBinding {
+        key: Style::new().add_modifier(Modifier::BOLD),
+        description: Style::new(),
+    },
+    date_display: Style::new(),
+    message_header: Style::new().add_modifier(Modifier::BOLD),
+    message_selected: Style::new().add_modifier(Modifier::REVERSED),
+    popup_window: Style::new().add_modifier(Modifier::REVERSED),
+};
+
 //  https://docs.rs/ratatui/latest/ratatui/style/enum.Color.html#
 pub const MENU_COLOR_HIGHLIGHT: Color = TV_GREEN;
 pub const MENU_COLOR_FOREGROUND: Color = Color::Black;
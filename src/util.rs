@@ -1,7 +1,19 @@
+use crate::app::{ConnectionState, DeviceConfiguration, RadioId};
 use crate::ipc::IPCMessage;
-use crate::DEVICE_CONFIG;
+use crate::packet_handler::MessageEnvelope;
+use crate::{
+    consts, ACTIVE_RADIO, ADMIN_TARGET, CONNECTION_STATES, DEVICE_CONFIGS, REMOTE_CONFIGS,
+};
 use anyhow::{bail, Result};
-use meshtastic::protobufs::Channel;
+use meshtastic::packet::PacketDestination;
+use meshtastic::protobufs::config::lo_ra_config::ModemPreset;
+use meshtastic::protobufs::config::LoRaConfig;
+use meshtastic::protobufs::{
+    mesh_packet, to_radio, ChannelSettings, Data, MeshPacket, PortNum, Position, ToRadio, Waypoint,
+};
+use meshtastic::types::MeshChannel;
+use meshtastic::Message;
+use std::sync::atomic::Ordering;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_secs() -> u64 {
@@ -11,15 +23,13 @@ pub fn get_secs() -> u64 {
         .as_secs()
 }
 
-pub fn get_channel_from_id(id: u32) -> Option<Channel> {
-    match DEVICE_CONFIG.try_read() {
-        Ok(device_config) => {
-            let devcfg = device_config.clone();
-            if let Some(cfg) = devcfg {
-                let channel = cfg.channels.get(&(id as i32));
-                return channel.cloned();
-            };
-            None
+/// The name the active radio's channel `id` goes by, the way the firmware names unnamed ones.
+pub fn get_channel_name_from_id(id: u32) -> Option<String> {
+    match DEVICE_CONFIGS.try_read() {
+        Ok(configs) => {
+            let cfg = configs.get(&active_radio())?;
+            let settings = cfg.channels.get(&(id as i32))?.settings.as_ref()?;
+            Some(channel_name(&cfg.lora, settings))
         }
         Err(_e) => {
             warn!("Couldn't lock config for shared read, so, channel lookup failed.");
@@ -28,10 +38,283 @@ pub fn get_channel_from_id(id: u32) -> Option<Channel> {
     }
 }
 
+/// What the firmware calls the modem preset, or "Custom" when it isn't using one.
+pub fn preset_name(lora: &LoRaConfig) -> &'static str {
+    if !lora.use_preset {
+        return "Custom";
+    }
+    match lora.modem_preset() {
+        ModemPreset::LongFast => "LongFast",
+        ModemPreset::LongSlow => "LongSlow",
+        ModemPreset::VeryLongSlow => "VLongSlow",
+        ModemPreset::LongModerate => "LongMod",
+        ModemPreset::MediumSlow => "MediumSlow",
+        ModemPreset::MediumFast => "MediumFast",
+        ModemPreset::ShortSlow => "ShortSlow",
+        ModemPreset::ShortFast => "ShortFast",
+    }
+}
+
+/// A channel's name, or for an unnamed one the preset's.  This is the name the channel hash
+/// is worked out from, so it has to match the firmware exactly.
+pub fn channel_name(lora: &LoRaConfig, settings: &ChannelSettings) -> String {
+    match settings.name.is_empty() {
+        true => preset_name(lora).to_string(),
+        false => settings.name.clone(),
+    }
+}
+
+pub fn active_radio() -> RadioId {
+    ACTIVE_RADIO.load(Ordering::Relaxed)
+}
+
+pub fn set_active_radio(radio: RadioId) {
+    ACTIVE_RADIO.store(radio, Ordering::Relaxed);
+}
+
+/// The active radio's config, if it has sent us any yet.
+pub async fn device_config() -> Option<DeviceConfiguration> {
+    DEVICE_CONFIGS.read().await.get(&active_radio()).cloned()
+}
+
+/// Like `device_config`, for render code that can't wait on the lock.
+pub fn try_device_config() -> Option<DeviceConfiguration> {
+    DEVICE_CONFIGS
+        .try_read()
+        .ok()?
+        .get(&active_radio())
+        .cloned()
+}
+
+/// The remote node the config tabs are showing, if they aren't showing the active radio.
+pub fn admin_target() -> Option<u32> {
+    match ADMIN_TARGET.load(Ordering::Relaxed) {
+        0 => None,
+        node => Some(node),
+    }
+}
+
+pub fn set_admin_target(node: Option<u32>) {
+    ADMIN_TARGET.store(node.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// The config the config tabs should show: the admin target's if there is one, otherwise the
+/// active radio's.
+pub async fn shown_config() -> Option<DeviceConfiguration> {
+    match admin_target() {
+        Some(node) => REMOTE_CONFIGS.read().await.get(&node).cloned(),
+        None => device_config().await,
+    }
+}
+
+/// Sends through the active radio.
 pub async fn send_to_radio(ipc: IPCMessage) -> Result<()> {
-    let trm = crate::TO_RADIO_MPSC.write().await.clone().unwrap();
-    if let Err(e) = trm.clone().send(ipc).await {
+    send_to(active_radio(), ipc).await
+}
+
+pub async fn send_to(radio: RadioId, ipc: IPCMessage) -> Result<()> {
+    let trm = match crate::TO_RADIO_MPSC.read().await.get(&radio) {
+        Some(trm) => trm.clone(),
+        None => bail!("Radio {} isn't connected.", radio + 1),
+    };
+    if let Err(e) = trm.send(ipc).await {
         bail!(e);
     }
     Ok(())
 }
+
+pub async fn set_connection_state(radio: RadioId, state: ConnectionState) {
+    let mut cs = CONNECTION_STATES.write().await;
+    cs.insert(radio, state);
+}
+
+pub async fn get_connection_state(radio: RadioId) -> ConnectionState {
+    CONNECTION_STATES
+        .read()
+        .await
+        .get(&radio)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Accepts node ids the way people write them: `!a1b2c3d4`, `a1b2c3d4` or `0xa1b2c3d4`.
+pub fn parse_node_id(id: &str) -> Result<u32> {
+    let hex = id.trim().trim_start_matches('!').trim_start_matches("0x");
+    match u32::from_str_radix(hex, 16) {
+        Ok(num) => Ok(num),
+        Err(e) => bail!("{id} is not a node id: {e}"),
+    }
+}
+
+/// Splits `host`, `host:port`, `[ipv6]` or `[ipv6]:port` up. A bare ipv6 address has too many
+/// colons to carry a port, so it needs the brackets for one.
+pub fn split_host(addr: &str) -> Result<(String, Option<u16>)> {
+    let addr = addr.trim();
+    if addr.is_empty() {
+        bail!("Enter a host name or ip address.");
+    }
+    if let Some(rest) = addr.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, "")) => Ok((host.to_string(), None)),
+            Some((host, port)) => match port.strip_prefix(':') {
+                Some(port) => Ok((host.to_string(), Some(parse_port(port)?))),
+                None => bail!("{addr} should look like [address]:port."),
+            },
+            None => bail!("{addr} is missing its closing ]."),
+        };
+    }
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => {
+            Ok((host.to_string(), Some(parse_port(port)?)))
+        }
+        _ => Ok((addr.to_string(), None)),
+    }
+}
+
+fn parse_port(port: &str) -> Result<u16> {
+    match port.parse::<u16>() {
+        Ok(port) => Ok(port),
+        Err(_) => bail!("{port} isn't a port number."),
+    }
+}
+
+/// Like `split_host`, filling in the usual stream api port if there isn't one.
+pub fn parse_host(addr: &str) -> Result<(String, u16)> {
+    let (host, port) = split_host(addr)?;
+    Ok((host, port.unwrap_or(consts::DEFAULT_TCP_PORT)))
+}
+
+/// `host:port`, with the brackets an ipv6 address needs.
+pub fn host_port(host: &str, port: u16) -> String {
+    match host.contains(':') {
+        true => format!("[{host}]:{port}"),
+        false => format!("{host}:{port}"),
+    }
+}
+
+/// A text message ready to hand to `send_to_radio`, broadcast unless `dest` names a node.
+pub fn text_envelope(text: String, channel: u32, dest: Option<&str>) -> Result<MessageEnvelope> {
+    let destination = match dest {
+        Some(d) => PacketDestination::Node(parse_node_id(d)?.into()),
+        None => PacketDestination::Broadcast,
+    };
+    let channel = match MeshChannel::new(channel) {
+        Ok(c) => c,
+        Err(e) => bail!("Channel {channel} is no good: {e}"),
+    };
+    Ok(MessageEnvelope {
+        timestamp: 0,
+        source: None,
+        destination,
+        channel,
+        message: text,
+        rx_rssi: 0,
+        rx_snr: 0.0,
+    })
+}
+
+/// A waypoint broadcast to everyone on `channel`.
+pub fn waypoint_packet(waypoint: &Waypoint, channel: u32) -> ToRadio {
+    let mesh_packet = MeshPacket {
+        to: u32::MAX,
+        channel,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+            portnum: i32::from(PortNum::WaypointApp),
+            payload: waypoint.encode_to_vec(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    ToRadio {
+        payload_variant: Some(to_radio::PayloadVariant::Packet(mesh_packet)),
+    }
+}
+
+/// Sets our radio's clock to ours.  The 2.3 firmware has no admin message for that, but it takes
+/// the time from a position the phone sends it, the way the apps do it.  The position we know it
+/// to be at goes along too, so the time doesn't come with a trip to 0,0.
+pub fn time_packet(my_node_id: u32, position: Option<Position>) -> ToRadio {
+    let position = Position {
+        time: get_secs() as u32,
+        ..position.unwrap_or_default()
+    };
+    let mesh_packet = MeshPacket {
+        to: my_node_id,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+            portnum: i32::from(PortNum::PositionApp),
+            payload: position.encode_to_vec(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    ToRadio {
+        payload_variant: Some(to_radio::PayloadVariant::Packet(mesh_packet)),
+    }
+}
+
+pub fn traceroute_request(to: u32) -> ToRadio {
+    #[allow(deprecated)]
+    let mesh_packet = MeshPacket {
+        from: 0,
+        to,
+        channel: 0,
+        id: 0,
+        rx_time: 0,
+        rx_snr: 0.0,
+        hop_limit: 0,
+        want_ack: true,
+        priority: 0,
+        rx_rssi: 0,
+        delayed: 0,
+        via_mqtt: true,
+        hop_start: 0,
+        payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+            portnum: i32::from(PortNum::TracerouteApp),
+            payload: vec![],
+            want_response: true,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
+        })),
+    };
+    ToRadio {
+        payload_variant: Some(to_radio::PayloadVariant::Packet(mesh_packet)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_split_with_and_without_ports() {
+        let cases = [
+            ("10.0.0.5", "10.0.0.5", None),
+            ("radio.lan:4404", "radio.lan", Some(4404)),
+            ("fd00::5", "fd00::5", None),
+            ("[fd00::5]", "fd00::5", None),
+            ("[fd00::5]:4404", "fd00::5", Some(4404)),
+        ];
+        for (addr, host, port) in cases {
+            assert_eq!(split_host(addr).unwrap(), (host.to_string(), port));
+        }
+    }
+
+    #[test]
+    fn bad_hosts_are_refused() {
+        assert!(split_host("").is_err());
+        assert!(split_host("radio.lan:nope").is_err());
+        assert!(split_host("[fd00::5").is_err());
+        assert!(split_host("[fd00::5]4404").is_err());
+    }
+
+    #[test]
+    fn host_port_round_trips_through_parse_host() {
+        for (host, port) in [("10.0.0.5", 4403), ("fd00::5", 4404)] {
+            let joined = host_port(host, port);
+            assert_eq!(parse_host(&joined).unwrap(), (host.to_string(), port));
+        }
+    }
+}